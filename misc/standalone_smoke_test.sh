@@ -0,0 +1,18 @@
+#!/usr/bin/env bash
+set -euo pipefail
+
+# Checks that the standalone application starts: it runs with nih-plug's dummy audio backend
+# on a virtual display (xvfb-run) and has to still be running after RUN_SECONDS.
+RUN_SECONDS="${1:-10}"
+
+cargo build --release --features standalone --bin lesynth
+
+status=0
+xvfb-run --auto-servernum timeout "$RUN_SECONDS" target/release/lesynth --backend dummy || status=$?
+
+# timeout exits with 124 after stopping the application, so anything else means it quit on its own
+if [[ "$status" -ne 124 ]]; then
+  echo "lesynth exited with status ${status} within ${RUN_SECONDS} s"
+  exit 1
+fi
+echo "lesynth ran for ${RUN_SECONDS} s"
@@ -0,0 +1,30 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! LeSynth as a standalone application, the plugin's editor playing through JACK or ALSA.
+//!
+//! The audio and MIDI options are nih_plug's, see `lesynth --help`. `--backend dummy` runs
+//! without audio devices, which `misc/standalone_smoke_test.sh` uses to check that it starts.
+
+use std::process::ExitCode;
+use nih_plug::prelude::*;
+use lesynth_fourier::LeSynth;
+
+fn main() -> ExitCode {
+    if nih_export_standalone::<LeSynth>() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
@@ -0,0 +1,224 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Export a preset for use in other instruments, or make one from a recording.
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use lesynth_fourier::io::sfz::{self, SfzOptions};
+use lesynth_fourier::io::wav::SampleFormat;
+use lesynth_fourier::io::wavetable::{self, WavetableOptions};
+use lesynth_fourier::{
+    key_from_name, resynthesize_file, BucketMapping, Patch, ResynthesisOptions, SynthComputeEngine, SAMPLE_RATE,
+};
+
+const USAGE: &str = "Usage:
+    lesynth-export sfz <preset.json> <output-dir> [options]
+    lesynth-export wavetable <preset.json> <output.wav> [options]
+    lesynth-export resynth <input.wav> <output.json> [options]
+
+sfz: renders keys of the preset to looped WAV files and writes an SFZ instrument mapping them.
+
+Options:
+    --name <NAME>           Name of the .sfz file and sample folder (default: preset file name)
+    --keys <KEYS>           Comma-separated keys to sample, e.g. C2,F#3,C4
+    --step <SEMITONES>      Sample every Nth key from A0 instead (default 3)
+    --sample-rate <HZ>      Sample rate of the WAV files (default 44100)
+    --format <16|24|32f>    Sample format (default 24)
+
+wavetable: writes one single-cycle frame per bucket to a wavetable WAV with a clm chunk.
+
+Options:
+    --frame-size <SAMPLES>  Samples per frame (default 2048)
+    --frames <COUNT>        Resample the buckets to this many frames (default: one per bucket)
+    --format <16|24|32f>    Sample format (default 32f)
+
+resynth: analyses a recorded note and writes its harmonic curves to a preset.
+
+Options:
+    --preset <PRESET>       Preset to take the bucket count and render settings from
+    --start <SECONDS>       Start of the analysed part (default 0)
+    --end <SECONDS>         End of the analysed part (default: end of the recording)
+    --mapping <MAPPING>     stretch: buckets spread over the part (default)
+                            per-cycle: one cycle of the fundamental per bucket
+    --fundamental <HZ>      Use this fundamental instead of detecting it
+    --no-normalize          Keep the recorded level instead of scaling the loudest bucket to 1";
+
+struct SfzArgs {
+    preset: PathBuf,
+    output: PathBuf,
+    name: Option<String>,
+    keys: Vec<usize>,
+    sample_rate: u32,
+    format: SampleFormat,
+}
+
+struct WavetableArgs {
+    preset: PathBuf,
+    output: PathBuf,
+    options: WavetableOptions,
+}
+
+struct ResynthArgs {
+    input: PathBuf,
+    output: PathBuf,
+    preset: Option<PathBuf>,
+    options: ResynthesisOptions,
+}
+
+fn parse_value<T: std::str::FromStr>(option: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} needs a value", option))?;
+    value.parse().map_err(|_| format!("Invalid value for {}: {}", option, value))
+}
+
+fn parse_keys(list: &str) -> Result<Vec<usize>, String> {
+    list.split(',')
+        .map(|name| key_from_name(name.trim()).ok_or_else(|| format!("Unknown key: {}", name)))
+        .collect()
+}
+
+fn parse_sfz_args(mut args: impl Iterator<Item = String>) -> Result<SfzArgs, String> {
+    let mut positional = Vec::new();
+    let mut name = None;
+    let mut keys = sfz::every_nth_key(3);
+    let mut sample_rate = SAMPLE_RATE as u32;
+    let mut format = SampleFormat::Int24;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--name" => name = Some(parse_value(&arg, args.next())?),
+            "--keys" => keys = parse_keys(&parse_value::<String>(&arg, args.next())?)?,
+            "--step" => keys = sfz::every_nth_key(parse_value(&arg, args.next())?),
+            "--sample-rate" => sample_rate = parse_value(&arg, args.next())?,
+            "--format" => {
+                let value: String = parse_value(&arg, args.next())?;
+                format = SampleFormat::parse(&value).ok_or_else(|| format!("Unknown format: {}", value))?;
+            }
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option: {}", arg)),
+            _ => positional.push(PathBuf::from(arg)),
+        }
+    }
+    let [preset, output]: [PathBuf; 2] = positional.try_into().map_err(|_| USAGE.to_string())?;
+    if sample_rate == 0 {
+        return Err("Sample rate must be positive".to_string());
+    }
+    Ok(SfzArgs { preset, output, name, keys, sample_rate, format })
+}
+
+fn parse_wavetable_args(mut args: impl Iterator<Item = String>) -> Result<WavetableArgs, String> {
+    let mut positional = Vec::new();
+    let mut options = WavetableOptions::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frame-size" => options.frame_size = parse_value(&arg, args.next())?,
+            "--frames" => options.frames = Some(parse_value(&arg, args.next())?),
+            "--format" => {
+                let value: String = parse_value(&arg, args.next())?;
+                options.format = SampleFormat::parse(&value).ok_or_else(|| format!("Unknown format: {}", value))?;
+            }
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option: {}", arg)),
+            _ => positional.push(PathBuf::from(arg)),
+        }
+    }
+    let [preset, output]: [PathBuf; 2] = positional.try_into().map_err(|_| USAGE.to_string())?;
+    Ok(WavetableArgs { preset, output, options })
+}
+
+fn parse_resynth_args(mut args: impl Iterator<Item = String>) -> Result<ResynthArgs, String> {
+    let mut positional = Vec::new();
+    let mut preset = None;
+    let mut options = ResynthesisOptions::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--preset" => preset = Some(parse_value(&arg, args.next())?),
+            "--start" => options.start = parse_value(&arg, args.next())?,
+            "--end" => options.end = Some(parse_value(&arg, args.next())?),
+            "--mapping" => {
+                options.mapping = match parse_value::<String>(&arg, args.next())?.as_str() {
+                    "stretch" => BucketMapping::Stretch,
+                    "per-cycle" => BucketMapping::PerCycle,
+                    other => return Err(format!("Unknown mapping: {}", other)),
+                }
+            }
+            "--fundamental" => options.fundamental = Some(parse_value(&arg, args.next())?),
+            "--no-normalize" => options.normalize = false,
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option: {}", arg)),
+            _ => positional.push(PathBuf::from(arg)),
+        }
+    }
+    let [input, output]: [PathBuf; 2] = positional.try_into().map_err(|_| USAGE.to_string())?;
+    Ok(ResynthArgs { input, output, preset, options })
+}
+
+fn load_engine(preset: &Path, sample_rate: u32) -> Result<SynthComputeEngine, String> {
+    let patch = Patch::load(preset).map_err(|err| format!("Can't load {}: {}", preset.display(), err))?;
+    let engine = SynthComputeEngine::new(patch.render.clone());
+    engine.set_sample_rate(sample_rate as f32);
+    engine.load_patch(&patch).map_err(|err| format!("Invalid preset: {}", err))?;
+    Ok(engine)
+}
+
+fn export_wavetable(args: &WavetableArgs) -> Result<(), String> {
+    let engine = load_engine(&args.preset, SAMPLE_RATE as u32)?;
+    let frames = wavetable::export_wavetable(&engine, &args.output, &args.options).map_err(|err| err.to_string())?;
+    println!("Exported {} frames of {} samples to {}", frames, args.options.frame_size, args.output.display());
+    Ok(())
+}
+
+fn export_sfz(args: &SfzArgs) -> Result<(), String> {
+    let name = match &args.name {
+        Some(name) => name.clone(),
+        None => args.preset.file_stem().map_or("lesynth".into(), |stem| stem.to_string_lossy().into_owned()),
+    };
+
+    let engine = load_engine(&args.preset, args.sample_rate)?;
+    let options = SfzOptions { name, keys: args.keys.clone(), format: args.format };
+    let path = sfz::export_sfz(&engine, &args.output, &options).map_err(|err| err.to_string())?;
+    println!("Exported {} keys to {}", args.keys.len(), path.display());
+    Ok(())
+}
+
+fn resynthesize(args: &ResynthArgs) -> Result<(), String> {
+    let mut patch = match &args.preset {
+        Some(preset) => Patch::load(preset).map_err(|err| format!("Can't load {}: {}", preset.display(), err))?,
+        None => Patch::default(),
+    };
+    let resynthesis = resynthesize_file(&args.input, patch.num_buckets(), &args.options)
+        .map_err(|err| format!("Can't analyse {}: {}", args.input.display(), err))?;
+    patch.amplitudes = resynthesis.curves.amplitudes;
+    patch.phases = resynthesis.curves.phases;
+    patch.save(&args.output).map_err(|err| err.to_string())?;
+    println!("Fundamental {:.2} Hz, wrote {}", resynthesis.fundamental, args.output.display());
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let mut args = env::args().skip(1);
+    let result = match args.next().as_deref() {
+        Some("sfz") => parse_sfz_args(args).and_then(|args| export_sfz(&args)),
+        Some("wavetable") => parse_wavetable_args(args).and_then(|args| export_wavetable(&args)),
+        Some("resynth") => parse_resynth_args(args).and_then(|args| resynthesize(&args)),
+        _ => Err(USAGE.to_string()),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
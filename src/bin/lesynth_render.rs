@@ -0,0 +1,126 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Render a MIDI file with a preset to a WAV file.
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+use std::{env, fs};
+use lesynth_fourier::io::{midi, wav};
+use lesynth_fourier::{render_notes, OfflineOptions, Patch, SynthComputeEngine, SAMPLE_RATE};
+
+const USAGE: &str = "Usage: lesynth-render <preset.json> <input.mid> <output.wav> [options]
+
+Options:
+    --sample-rate <HZ>      Output sample rate (default 44100)
+    --block-size <SAMPLES>  Processing block size, notes start at block boundaries (default 512)
+    --format <16|24|32f>    Sample format (default 24)
+    --tail <SECONDS>        Audio rendered after the last event (default 1)
+    --timeout <SECONDS>     Time allowed for rendering the keys (default 120)";
+
+struct Args {
+    preset: PathBuf,
+    midi: PathBuf,
+    output: PathBuf,
+    sample_rate: u32,
+    block_size: usize,
+    format: wav::SampleFormat,
+    tail: f64,
+    timeout: f64,
+}
+
+fn parse_value<T: std::str::FromStr>(option: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} needs a value", option))?;
+    value.parse().map_err(|_| format!("Invalid value for {}: {}", option, value))
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut positional = Vec::new();
+    let mut parsed = Args {
+        preset: PathBuf::new(),
+        midi: PathBuf::new(),
+        output: PathBuf::new(),
+        sample_rate: SAMPLE_RATE as u32,
+        block_size: OfflineOptions::default().block_size,
+        format: wav::SampleFormat::Int24,
+        tail: 1.0,
+        timeout: OfflineOptions::default().timeout.as_secs_f64(),
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--sample-rate" => parsed.sample_rate = parse_value(&arg, args.next())?,
+            "--block-size" => parsed.block_size = parse_value(&arg, args.next())?,
+            "--tail" => parsed.tail = parse_value(&arg, args.next())?,
+            "--timeout" => parsed.timeout = parse_value(&arg, args.next())?,
+            "--format" => {
+                let name: String = parse_value(&arg, args.next())?;
+                parsed.format = wav::SampleFormat::parse(&name).ok_or_else(|| format!("Unknown format: {}", name))?;
+            }
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option: {}", arg)),
+            _ => positional.push(PathBuf::from(arg)),
+        }
+    }
+    let [preset, midi, output]: [PathBuf; 3] = positional.try_into().map_err(|_| USAGE.to_string())?;
+    if parsed.sample_rate == 0 || parsed.block_size == 0 {
+        return Err("Sample rate and block size must be positive".to_string());
+    }
+    if !parsed.tail.is_finite() || parsed.tail < 0.0 || !parsed.timeout.is_finite() || parsed.timeout <= 0.0 {
+        return Err("Tail can't be negative and timeout must be positive".to_string());
+    }
+    Ok(Args { preset, midi, output, ..parsed })
+}
+
+fn run(args: &Args) -> Result<(), String> {
+    let patch = Patch::load(&args.preset).map_err(|err| format!("Can't load {}: {}", args.preset.display(), err))?;
+    let bytes = fs::read(&args.midi).map_err(|err| format!("Can't read {}: {}", args.midi.display(), err))?;
+    let events = midi::read_notes(&bytes, args.sample_rate as f64)
+        .map_err(|err| format!("Can't parse {}: {}", args.midi.display(), err))?;
+
+    let engine = SynthComputeEngine::new(patch.render.clone());
+    engine.set_sample_rate(args.sample_rate as f32);
+    engine.load_patch(&patch).map_err(|err| format!("Invalid preset: {}", err))?;
+    let mut voice_bank = engine.take_voice_bank().ok_or("Voice bank is not available")?;
+
+    let options = OfflineOptions {
+        block_size: args.block_size,
+        tail: (args.tail * args.sample_rate as f64).round() as usize,
+        timeout: Duration::from_secs_f64(args.timeout),
+    };
+    let mono = render_notes(&engine, &mut voice_bank, &events, &options).map_err(|err| err.to_string())?;
+
+    // Both channels carry the same signal, like in the plugin
+    let stereo: Vec<f32> = mono.iter().flat_map(|&sample| [sample, sample]).collect();
+    let spec = wav::WavSpec { channels: 2, sample_rate: args.sample_rate, format: args.format };
+    wav::write(&args.output, &spec, &stereo).map_err(|err| format!("Can't write {}: {}", args.output.display(), err))?;
+    println!(
+        "Rendered {} notes, {:.2} s to {}",
+        events.iter().filter(|event| event.on).count(),
+        mono.len() as f64 / args.sample_rate as f64,
+        args.output.display()
+    );
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let result = parse_args(env::args().skip(1)).and_then(|args| run(&args));
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
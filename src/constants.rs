@@ -18,28 +18,65 @@ use std::f32::consts::PI;
 pub const NUM_HARMONICS: usize = 64;
 pub const NUM_KEYS: usize = 88;
 
+// Key rendered in the assembled chart until the user picks another one
+pub const DEFAULT_PREVIEW_KEY: usize = 24;
+
 // Parameter Defaults and Ranges
 pub static NUM_OF_BUCKETS_DEFAULT: usize = 70;
 pub static NUM_OF_BUCKETS_MIN: i32 = 30;
 pub static NUM_OF_BUCKETS_MAX: i32 = 2000;
 
+// Background Rendering
+pub static RENDER_THREADS_DEFAULT: i32 = 2;
+#[cfg(feature = "plugin")]
+pub static RENDER_THREADS_MIN: i32 = 1;
+#[cfg(feature = "plugin")]
+pub static RENDER_THREADS_MAX: i32 = 16;
+
+// Memory for rendered key buffers in megabytes, beyond which they are streamed in chunks
+pub static MEMORY_BUDGET_MB_DEFAULT: i32 = 256;
+#[cfg(feature = "plugin")]
+pub static MEMORY_BUDGET_MB_MIN: i32 = 8;
+#[cfg(feature = "plugin")]
+pub static MEMORY_BUDGET_MB_MAX: i32 = 4096;
+
+// Voice Parameter Ranges
+#[cfg(feature = "plugin")]
+pub const VOICE_GAIN_MIN_DB: f32 = -36.0;
+#[cfg(feature = "plugin")]
+pub const VOICE_GAIN_MAX_DB: f32 = 6.0;
+// Semitones either way
+#[cfg(feature = "plugin")]
+pub const VOICE_TUNING_RANGE: f32 = 12.0;
+
 // Amplitude Parameter Ranges
+#[cfg(feature = "plugin")]
 pub static MIN_OFFSET_AMP: f64 = 0.0;
+#[cfg(feature = "plugin")]
 pub static MAX_OFFSET_AMP: f64 = 1.0;
+#[cfg(feature = "plugin")]
 pub static MIN_AMP_SINE_AMP: f64 = 0.0;
+#[cfg(feature = "plugin")]
 pub static MAX_AMP_SINE_AMP: f64 = 1.0;
 
 // Phase Parameter Ranges
+#[cfg(feature = "plugin")]
 pub static MIN_OFFSET_PHASE: f64 = 0.0;
+#[cfg(feature = "plugin")]
 pub static MAX_OFFSET_PHASE: f64 = 6.28;
+#[cfg(feature = "plugin")]
 pub static MIN_PHASE_SINE_AMP: f64 = 0.0;
+#[cfg(feature = "plugin")]
 pub static MAX_PHASE_SINE_AMP: f64 = 6.28;
 
 // Sine Curve Frequency Ranges
+#[cfg(feature = "plugin")]
 pub static MIN_SINE_FREQ: f64 = 0.0;
+#[cfg(feature = "plugin")]
 pub static MAX_SINE_FREQ: f64 = 0.35;
 
 // GUI Constants
+#[cfg(feature = "plugin")]
 pub static LABEL_FONT_SIZE: f32 = 12.0;
 
 // Audio Processing Constants
@@ -65,6 +102,24 @@ pub fn max_harmonic_for_key(key: usize) -> usize {
     max_harmonic.min(NUM_HARMONICS)
 }
 
+/// Note name of a piano key, e.g. key 0 is "A0" and key 39 is "C4"
+pub fn key_name(key: usize) -> String {
+    const NOTE_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+    // Key 0 is A0, which is 9 semitones above C0
+    let semitones_from_c0 = key + 9;
+    format!("{}{}", NOTE_NAMES[semitones_from_c0 % 12], semitones_from_c0 / 12)
+}
+
+/// Piano key of a note name like "C4" or "F#2", the inverse of `key_name`
+pub fn key_from_name(name: &str) -> Option<usize> {
+    const NOTE_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+    let split = name.find(|c: char| c.is_ascii_digit())?;
+    let (note, octave) = name.split_at(split);
+    let note = NOTE_NAMES.iter().position(|&n| n.eq_ignore_ascii_case(note))?;
+    let semitones_from_c0 = octave.parse::<usize>().ok()? * 12 + note;
+    semitones_from_c0.checked_sub(9).filter(|&key| key < NUM_KEYS)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -85,6 +140,16 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "plugin")]
+    fn test_render_parameter_ranges() {
+        assert!(RENDER_THREADS_MIN <= RENDER_THREADS_DEFAULT);
+        assert!(RENDER_THREADS_DEFAULT <= RENDER_THREADS_MAX);
+        assert!(MEMORY_BUDGET_MB_MIN <= MEMORY_BUDGET_MB_DEFAULT);
+        assert!(MEMORY_BUDGET_MB_DEFAULT <= MEMORY_BUDGET_MB_MAX);
+    }
+
+    #[test]
+    #[cfg(feature = "plugin")]
     fn test_amplitude_ranges() {
         assert_eq!(MIN_OFFSET_AMP, 0.0);
         assert_eq!(MAX_OFFSET_AMP, 1.0);
@@ -96,6 +161,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "plugin")]
     fn test_phase_ranges() {
         assert_eq!(MIN_OFFSET_PHASE, 0.0);
         assert_eq!(MAX_OFFSET_PHASE, 6.28);
@@ -111,6 +177,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "plugin")]
     fn test_sine_frequency_ranges() {
         assert_eq!(MIN_SINE_FREQ, 0.0);
         assert_eq!(MAX_SINE_FREQ, 0.35);
@@ -124,6 +191,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "plugin")]
     fn test_gui_constants() {
         assert_eq!(LABEL_FONT_SIZE, 12.0);
         assert!(LABEL_FONT_SIZE > 0.0);
@@ -148,6 +216,28 @@ mod tests {
         assert_eq!(max_harmonic_for_key(NUM_KEYS), 0, "Invalid key should return 0");
     }
 
+    #[test]
+    fn test_key_name() {
+        assert_eq!(key_name(0), "A0");
+        assert_eq!(key_name(2), "B0");
+        assert_eq!(key_name(3), "C1");
+        assert_eq!(key_name(39), "C4");
+        assert_eq!(key_name(48), "A4");
+        assert_eq!(key_name(87), "C8");
+    }
+
+    #[test]
+    fn test_key_from_name() {
+        for key in 0..NUM_KEYS {
+            assert_eq!(key_from_name(&key_name(key)), Some(key));
+        }
+        assert_eq!(key_from_name("f#2"), Some(21));
+        assert_eq!(key_from_name("G#0"), None);
+        assert_eq!(key_from_name("C#8"), None);
+        assert_eq!(key_from_name("H4"), None);
+        assert_eq!(key_from_name("C"), None);
+    }
+
     #[test]
     fn test_sample_rate_constants() {
         assert_eq!(SAMPLE_RATE, 44100.0);
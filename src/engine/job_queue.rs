@@ -0,0 +1,607 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+use crate::constants::NUM_KEYS;
+use super::recovery::LockRecover;
+use super::render_snapshot::RenderSnapshot;
+use super::shared_params::{BufferState, PreviewRequest};
+
+/// Work item for the background computation threads
+#[derive(Debug, Clone, PartialEq)]
+pub enum Job {
+    Preview(PreviewRequest),
+    // Render a key buffer; obsolete once the key's generation moves past `generation`
+    Key { key: usize, generation: u64 },
+    // Render a bucket range of a key buffer that was split across workers, all parts
+    // from the same snapshot
+    Part { key: usize, generation: u64, buckets: Range<usize>, snapshot: Arc<RenderSnapshot> },
+    // Render a chunk of a streamed key buffer started at `generation`
+    Chunk { key: usize, generation: u64, chunk: usize },
+}
+
+impl Job {
+    /// Key and generation the job renders, `None` for previews
+    pub fn key(&self) -> Option<(usize, u64)> {
+        match *self {
+            Job::Preview(_) => None,
+            Job::Key { key, generation } | Job::Part { key, generation, .. } | Job::Chunk { key, generation, .. } => {
+                Some((key, generation))
+            }
+        }
+    }
+}
+
+/// Keys to render before the rest, most urgent first within each group
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JobPriority {
+    // Missing chunks of streamed keys ahead of their playheads as (key, generation, chunk)
+    pub chunks: Vec<(usize, u64, usize)>,
+    // Keys currently held down
+    pub held: Vec<usize>,
+    // Keys shown in the assembled chart
+    pub visible: Vec<usize>,
+    // Keys played recently, newest first
+    pub recent: Vec<usize>,
+}
+
+// Key buffer being put together from parts rendered by several workers
+struct Assembly {
+    generation: u64,
+    samples: Vec<f32>,
+    remaining: usize,
+}
+
+struct QueueState {
+    buffer_states: Vec<BufferState>,
+    preview: Option<PreviewRequest>,
+    parts: VecDeque<Job>,
+    assemblies: Vec<Option<Assembly>>,
+    // Chunks taken by a worker, as (key, chunk)
+    chunks_in_flight: Vec<(usize, usize)>,
+}
+
+impl QueueState {
+    // Forget split work of a key that became outdated
+    fn discard_parts(&mut self, key: usize) {
+        if self.assemblies[key].take().is_some() {
+            self.parts.retain(|job| !matches!(job, Job::Part { key: k, .. } if *k == key));
+        }
+    }
+}
+
+/// Pending renders with condition-variable wakeups.
+///
+/// Every key has a generation counter that is bumped when the key is marked dirty, so a
+/// render in progress can tell whether its result is still wanted without a global flag.
+pub struct JobQueue {
+    state: Mutex<QueueState>,
+    wakeup: Condvar,
+    key_generations: Box<[AtomicU64]>,
+}
+
+impl JobQueue {
+    /// New queue with every key buffer waiting to be rendered
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(QueueState {
+                buffer_states: vec![BufferState::Dirty; NUM_KEYS],
+                preview: None,
+                parts: VecDeque::new(),
+                assemblies: (0..NUM_KEYS).map(|_| None).collect(),
+                chunks_in_flight: Vec::new(),
+            }),
+            wakeup: Condvar::new(),
+            key_generations: (0..NUM_KEYS).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    pub fn buffer_states(&self) -> Vec<BufferState> {
+        self.state.lock_recover().buffer_states.clone()
+    }
+
+    pub fn buffer_state(&self, key: usize) -> BufferState {
+        self.state.lock_recover().buffer_states[key]
+    }
+
+    pub fn key_generation(&self, key: usize) -> u64 {
+        self.key_generations[key].load(Ordering::Acquire)
+    }
+
+    /// Whether a render of `key` started at `generation` is still wanted
+    pub fn is_current(&self, key: usize, generation: u64) -> bool {
+        self.key_generation(key) == generation
+    }
+
+    /// Mark every key buffer as outdated, cancelling renders in progress
+    pub fn mark_all_dirty(&self) {
+        let mut state = self.state.lock_recover();
+        for key in 0..NUM_KEYS {
+            self.key_generations[key].fetch_add(1, Ordering::AcqRel);
+            state.buffer_states[key] = BufferState::Dirty;
+            state.discard_parts(key);
+        }
+        self.wakeup.notify_all();
+    }
+
+    /// Mark a single key buffer as outdated
+    pub fn mark_dirty(&self, key: usize) {
+        if key >= NUM_KEYS {
+            return;
+        }
+        let mut state = self.state.lock_recover();
+        self.key_generations[key].fetch_add(1, Ordering::AcqRel);
+        state.buffer_states[key] = BufferState::Dirty;
+        state.discard_parts(key);
+        self.wakeup.notify_all();
+    }
+
+    /// Queue a preview render, replacing any pending one
+    pub fn submit_preview(&self, request: PreviewRequest) {
+        self.state.lock_recover().preview = Some(request);
+        self.wakeup.notify_all();
+    }
+
+    /// Put an interrupted preview back unless a newer one has been submitted meanwhile
+    pub fn requeue_preview(&self, request: PreviewRequest) {
+        self.state.lock_recover().preview.get_or_insert(request);
+        self.wakeup.notify_all();
+    }
+
+    pub fn pending_preview(&self) -> Option<PreviewRequest> {
+        self.state.lock_recover().preview
+    }
+
+    /// Record a finished render. Returns `false` if the result is outdated or the buffer was
+    /// already completed by another render.
+    pub fn finish_key(&self, key: usize, generation: u64) -> bool {
+        let mut state = self.state.lock_recover();
+        if !self.is_current(key, generation) || state.buffer_states[key] == BufferState::Clean {
+            return false;
+        }
+        state.buffer_states[key] = BufferState::Clean;
+        true
+    }
+
+    /// Split a key job into bucket ranges that any worker can pick up; `len` is the length
+    /// of the whole key buffer
+    pub fn split_key(
+        &self,
+        key: usize,
+        generation: u64,
+        len: usize,
+        parts: Vec<Range<usize>>,
+        snapshot: Arc<RenderSnapshot>,
+    ) {
+        let mut state = self.state.lock_recover();
+        if !self.is_current(key, generation) {
+            return;
+        }
+        state.assemblies[key] = Some(Assembly {
+            generation,
+            samples: vec![0.0; len],
+            remaining: parts.len(),
+        });
+        state
+            .parts
+            .extend(parts.into_iter().map(|buckets| Job::Part { key, generation, buckets, snapshot: snapshot.clone() }));
+        self.wakeup.notify_all();
+    }
+
+    /// Copy a rendered part into its key buffer, starting at sample `offset`.
+    /// Returns the whole buffer once the last part is in.
+    pub fn complete_part(&self, key: usize, generation: u64, offset: usize, samples: &[f32]) -> Option<Vec<f32>> {
+        let mut state = self.state.lock_recover();
+        let assembly = state.assemblies[key].as_mut().filter(|a| a.generation == generation)?;
+        assembly.samples[offset..offset + samples.len()].copy_from_slice(samples);
+        assembly.remaining -= 1;
+        if assembly.remaining > 0 {
+            return None;
+        }
+        state.assemblies[key].take().map(|a| a.samples)
+    }
+
+    /// Put back a part that was interrupted while still current, e.g. by a shutdown
+    pub fn requeue_part(&self, part: Job) {
+        let Job::Part { key, generation, .. } = part else {
+            return;
+        };
+        let mut state = self.state.lock_recover();
+        if self.is_current(key, generation) && state.assemblies[key].is_some() {
+            state.parts.push_front(part);
+            self.wakeup.notify_all();
+        }
+    }
+
+    /// Give back a render that was interrupted while still current, e.g. by a shutdown
+    pub fn abandon_key(&self, key: usize, generation: u64) {
+        let mut state = self.state.lock_recover();
+        if self.is_current(key, generation) && state.buffer_states[key] == BufferState::Computing {
+            state.buffer_states[key] = BufferState::Dirty;
+            self.wakeup.notify_all();
+        }
+    }
+
+    /// Give up on a render that panicked. The key is not rendered again until its data changes,
+    /// so a render that always fails can't keep a worker busy.
+    pub fn fail_key(&self, key: usize, generation: u64) {
+        if key >= NUM_KEYS {
+            return;
+        }
+        let mut state = self.state.lock_recover();
+        state.chunks_in_flight.retain(|&(taken, _)| taken != key);
+        if self.is_current(key, generation) {
+            state.buffer_states[key] = BufferState::Failed;
+            state.discard_parts(key);
+        }
+        self.wakeup.notify_all();
+    }
+
+    /// Queue keys whose render panicked to be rendered again
+    pub fn retry_failed_keys(&self) {
+        let mut state = self.state.lock_recover();
+        for buffer_state in state.buffer_states.iter_mut().filter(|s| **s == BufferState::Failed) {
+            *buffer_state = BufferState::Dirty;
+        }
+        self.wakeup.notify_all();
+    }
+
+    /// Release a chunk taken by `next_job`, rendered or not
+    pub fn finish_chunk(&self, key: usize, chunk: usize) {
+        let mut state = self.state.lock_recover();
+        state.chunks_in_flight.retain(|&taken| taken != (key, chunk));
+        self.wakeup.notify_all();
+    }
+
+    /// Drop a rendered key buffer to free memory, cancelling renders of its chunks. The key is
+    /// rendered again once it is held or shown. Returns `false` unless the buffer was clean.
+    pub fn evict_key(&self, key: usize) -> bool {
+        let mut state = self.state.lock_recover();
+        if key >= NUM_KEYS || state.buffer_states[key] != BufferState::Clean {
+            return false;
+        }
+        self.key_generations[key].fetch_add(1, Ordering::AcqRel);
+        state.buffer_states[key] = BufferState::Evicted;
+        true
+    }
+
+    /// Wake all waiting workers, e.g. after setting their stop flag
+    pub fn wake_all(&self) {
+        let _state = self.state.lock_recover();
+        self.wakeup.notify_all();
+    }
+
+    /// Wait for the most urgent job. Returns `None` when `stop` is set or after `timeout`
+    /// without work, so the caller can do periodic housekeeping.
+    ///
+    /// Parts of split keys come first so started buffers complete quickly, then chunks that
+    /// streamed keys are about to play, then held keys, then the preview, then visible and
+    /// recently played keys, then the remaining keys from the lowest (slowest to render) up.
+    /// Evicted keys are only rendered again when held or visible. `priority` is evaluated
+    /// while the queue is locked, so it must not touch the queue itself.
+    pub fn next_job(
+        &self,
+        stop: &AtomicBool,
+        timeout: Duration,
+        priority: impl Fn() -> JobPriority,
+    ) -> Option<Job> {
+        let mut state = self.state.lock_recover();
+        let mut waited = false;
+        loop {
+            if stop.load(Ordering::Acquire) {
+                return None;
+            }
+            if let Some(job) = self.take_job(&mut state, &priority()) {
+                return Some(job);
+            }
+            if waited {
+                return None;
+            }
+            let (guard, result) = self.wakeup.wait_timeout(state, timeout).unwrap_or_else(PoisonError::into_inner);
+            state = guard;
+            waited = result.timed_out();
+        }
+    }
+
+    fn take_job(&self, state: &mut QueueState, priority: &JobPriority) -> Option<Job> {
+        if let Some(job) = state.parts.pop_front() {
+            return Some(job);
+        }
+        if let Some(&(key, generation, chunk)) = priority
+            .chunks
+            .iter()
+            .find(|&&(key, _, chunk)| {
+                state.buffer_states[key] != BufferState::Failed && !state.chunks_in_flight.contains(&(key, chunk))
+            })
+        {
+            state.chunks_in_flight.push((key, chunk));
+            return Some(Job::Chunk { key, generation, chunk });
+        }
+        if let Some(job) = priority.held.iter().find_map(|&key| self.take_key(state, key, true)) {
+            return Some(job);
+        }
+        if let Some(request) = state.preview.take() {
+            return Some(Job::Preview(request));
+        }
+        if let Some(job) = priority.visible.iter().find_map(|&key| self.take_key(state, key, true)) {
+            return Some(job);
+        }
+        priority
+            .recent
+            .iter()
+            .copied()
+            .chain(0..NUM_KEYS)
+            .find_map(|key| self.take_key(state, key, false))
+    }
+
+    fn take_key(&self, state: &mut QueueState, key: usize, revive_evicted: bool) -> Option<Job> {
+        let wanted = match state.buffer_states.get(key) {
+            Some(BufferState::Dirty) => true,
+            Some(BufferState::Evicted) => revive_evicted,
+            _ => false,
+        };
+        if !wanted {
+            return None;
+        }
+        state.buffer_states[key] = BufferState::Computing;
+        Some(Job::Key { key, generation: self.key_generation(key) })
+    }
+}
+
+impl Default for JobQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NO_WAIT: Duration = Duration::from_millis(1);
+
+    fn clean_queue() -> JobQueue {
+        let queue = JobQueue::new();
+        let stop = AtomicBool::new(false);
+        while let Some(Job::Key { key, generation }) = queue.next_job(&stop, NO_WAIT, JobPriority::default) {
+            assert!(queue.finish_key(key, generation));
+        }
+        queue
+    }
+
+    fn next_job(queue: &JobQueue) -> Option<Job> {
+        let stop = AtomicBool::new(false);
+        queue.next_job(&stop, NO_WAIT, JobPriority::default)
+    }
+
+    fn next_key(queue: &JobQueue, priority: &JobPriority) -> Option<usize> {
+        let stop = AtomicBool::new(false);
+        match queue.next_job(&stop, NO_WAIT, || priority.clone()) {
+            Some(Job::Key { key, .. }) => Some(key),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_all_keys_start_dirty_lowest_first() {
+        let queue = JobQueue::new();
+        assert!(queue.buffer_states().iter().all(|&s| s == BufferState::Dirty));
+        assert_eq!(next_key(&queue, &JobPriority::default()), Some(0));
+        assert_eq!(queue.buffer_state(0), BufferState::Computing);
+        assert_eq!(next_key(&queue, &JobPriority::default()), Some(1));
+    }
+
+    #[test]
+    fn test_priority_order() {
+        let queue = clean_queue();
+        for key in [5, 20, 30, 40, 50] {
+            queue.mark_dirty(key);
+        }
+        queue.submit_preview(PreviewRequest { key: 30, compare_key: None, generation: 1 });
+        let priority = JobPriority { held: vec![50], visible: vec![30], recent: vec![40, 20], ..Default::default() };
+        let stop = AtomicBool::new(false);
+
+        assert_eq!(next_key(&queue, &priority), Some(50));
+        assert!(matches!(
+            queue.next_job(&stop, NO_WAIT, || priority.clone()),
+            Some(Job::Preview(PreviewRequest { key: 30, .. }))
+        ));
+        assert_eq!(next_key(&queue, &priority), Some(30));
+        assert_eq!(next_key(&queue, &priority), Some(40));
+        assert_eq!(next_key(&queue, &priority), Some(20));
+        assert_eq!(next_key(&queue, &priority), Some(5));
+        assert_eq!(next_key(&queue, &priority), None);
+    }
+
+    #[test]
+    fn test_chunks_are_taken_once_before_held_keys() {
+        let queue = clean_queue();
+        queue.mark_dirty(50);
+        let priority = JobPriority { chunks: vec![(7, 3, 1), (7, 3, 2)], held: vec![50], ..Default::default() };
+        let stop = AtomicBool::new(false);
+        let next = || queue.next_job(&stop, NO_WAIT, || priority.clone());
+
+        assert_eq!(next(), Some(Job::Chunk { key: 7, generation: 3, chunk: 1 }));
+        // A chunk in flight is not handed out twice until it is released
+        assert_eq!(next(), Some(Job::Chunk { key: 7, generation: 3, chunk: 2 }));
+        assert!(matches!(next(), Some(Job::Key { key: 50, .. })));
+        queue.finish_chunk(7, 1);
+        assert_eq!(next(), Some(Job::Chunk { key: 7, generation: 3, chunk: 1 }));
+    }
+
+    #[test]
+    fn test_evicted_keys_wait_until_needed() {
+        let queue = clean_queue();
+        let generation = queue.key_generation(12);
+        assert!(queue.evict_key(12));
+        assert!(!queue.evict_key(12));
+        assert!(!queue.is_current(12, generation));
+        assert_eq!(queue.buffer_state(12), BufferState::Evicted);
+
+        // Playing it recently is not enough, holding it is
+        let recent = JobPriority { recent: vec![12], ..Default::default() };
+        assert_eq!(next_key(&queue, &recent), None);
+        let held = JobPriority { held: vec![12], ..Default::default() };
+        assert_eq!(next_key(&queue, &held), Some(12));
+    }
+
+    #[test]
+    fn test_generations_cancel_outdated_renders() {
+        let queue = clean_queue();
+        queue.mark_dirty(10);
+        let stop = AtomicBool::new(false);
+        let Some(Job::Key { key, generation }) = queue.next_job(&stop, NO_WAIT, JobPriority::default) else {
+            panic!("Expected a key job");
+        };
+        assert_eq!(key, 10);
+
+        // An edit during the render makes the result obsolete and queues the key again
+        queue.mark_dirty(10);
+        assert!(!queue.is_current(10, generation));
+        assert!(!queue.finish_key(10, generation));
+        assert_eq!(queue.buffer_state(10), BufferState::Dirty);
+
+        // Abandoning an obsolete render leaves the newer state alone
+        queue.abandon_key(10, generation);
+        assert_eq!(queue.buffer_state(10), BufferState::Dirty);
+    }
+
+    #[test]
+    fn test_abandoned_render_is_retried() {
+        let queue = clean_queue();
+        queue.mark_dirty(3);
+        let stop = AtomicBool::new(false);
+        let Some(Job::Key { generation, .. }) = queue.next_job(&stop, NO_WAIT, JobPriority::default) else {
+            panic!("Expected a key job");
+        };
+        queue.abandon_key(3, generation);
+        assert_eq!(queue.buffer_state(3), BufferState::Dirty);
+        assert_eq!(next_key(&queue, &JobPriority::default()), Some(3));
+    }
+
+    #[test]
+    fn test_failed_key_waits_for_edit() {
+        let queue = clean_queue();
+        queue.mark_dirty(3);
+        let stop = AtomicBool::new(false);
+        let Some(Job::Key { generation, .. }) = queue.next_job(&stop, NO_WAIT, JobPriority::default) else {
+            panic!("Expected a key job");
+        };
+        queue.fail_key(3, generation);
+        assert_eq!(queue.buffer_state(3), BufferState::Failed);
+        // Neither holding it nor its pending chunks bring it back
+        let priority = JobPriority { chunks: vec![(3, generation, 0)], held: vec![3], ..Default::default() };
+        assert_eq!(queue.next_job(&stop, NO_WAIT, || priority.clone()), None);
+
+        queue.retry_failed_keys();
+        assert_eq!(next_key(&queue, &JobPriority::default()), Some(3));
+        queue.fail_key(3, generation);
+        // An edit renders it again too
+        queue.mark_dirty(3);
+        assert_eq!(next_key(&queue, &JobPriority::default()), Some(3));
+    }
+
+    #[test]
+    fn test_split_key_assembles_parts() {
+        let queue = clean_queue();
+        queue.mark_dirty(0);
+        let Some(Job::Key { key, generation }) = next_job(&queue) else {
+            panic!("Expected a key job");
+        };
+        // Two buckets of three samples each, split into one part per bucket
+        queue.split_key(key, generation, 6, vec![0..1, 1..2], Arc::default());
+
+        let Some(Job::Part { buckets: first, .. }) = next_job(&queue) else {
+            panic!("Expected a part job");
+        };
+        let Some(second_part) = next_job(&queue) else {
+            panic!("Expected a part job");
+        };
+        assert!(matches!(&second_part, Job::Part { buckets, .. } if *buckets == (1..2)));
+        assert_eq!(first, 0..1);
+
+        // Parts may complete in any order; an interrupted part is handed out again
+        queue.requeue_part(second_part);
+        let Some(Job::Part { buckets: second, .. }) = next_job(&queue) else {
+            panic!("Expected the requeued part");
+        };
+        assert_eq!(queue.complete_part(key, generation, second.start * 3, &[4.0, 5.0, 6.0]), None);
+        let buffer = queue.complete_part(key, generation, 0, &[1.0, 2.0, 3.0]).unwrap();
+        assert_eq!(buffer, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        assert!(queue.finish_key(key, generation));
+    }
+
+    #[test]
+    fn test_edit_discards_split_parts() {
+        let queue = clean_queue();
+        queue.mark_dirty(1);
+        let Some(Job::Key { key, generation }) = next_job(&queue) else {
+            panic!("Expected a key job");
+        };
+        queue.split_key(key, generation, 4, vec![0..1, 1..2], Arc::default());
+        queue.mark_dirty(1);
+
+        // The outdated parts are gone and the key is rendered again from scratch
+        assert_eq!(queue.complete_part(key, generation, 0, &[1.0, 1.0]), None);
+        assert!(matches!(next_job(&queue), Some(Job::Key { key: 1, .. })));
+    }
+
+    #[test]
+    fn test_preview_requeue_keeps_newer_request() {
+        let queue = JobQueue::new();
+        let old = PreviewRequest { key: 1, compare_key: None, generation: 1 };
+        let new = PreviewRequest { key: 2, compare_key: None, generation: 2 };
+        queue.submit_preview(new);
+        queue.requeue_preview(old);
+        assert_eq!(queue.pending_preview(), Some(new));
+    }
+
+    #[test]
+    fn test_submit_wakes_waiting_worker() {
+        let queue = Arc::new(clean_queue());
+        let stop = Arc::new(AtomicBool::new(false));
+        let worker = {
+            let queue = queue.clone();
+            let stop = stop.clone();
+            std::thread::spawn(move || queue.next_job(&stop, Duration::from_secs(30), JobPriority::default))
+        };
+
+        std::thread::sleep(Duration::from_millis(20));
+        let start = std::time::Instant::now();
+        queue.mark_dirty(7);
+        let job = worker.join().unwrap();
+        assert!(matches!(job, Some(Job::Key { key: 7, .. })));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_stop_wakes_waiting_worker() {
+        let queue = Arc::new(clean_queue());
+        let stop = Arc::new(AtomicBool::new(false));
+        let worker = {
+            let queue = queue.clone();
+            let stop = stop.clone();
+            std::thread::spawn(move || queue.next_job(&stop, Duration::from_secs(30), JobPriority::default))
+        };
+
+        std::thread::sleep(Duration::from_millis(20));
+        stop.store(true, Ordering::Release);
+        queue.wake_all();
+        assert_eq!(worker.join().unwrap(), None);
+    }
+}
@@ -15,7 +15,23 @@
 pub mod shared_params;
 pub mod synth_compute_engine;
 pub mod chart_type;
+pub mod output_tap;
+#[cfg(feature = "plugin")]
+pub mod spectrum;
+pub mod job_queue;
+pub mod render_kernel;
+pub mod render_snapshot;
+pub mod wavetable;
+pub mod streaming;
+pub mod render_cache;
+pub mod recovery;
 
 pub use shared_params::SharedParams;
 pub use synth_compute_engine::SynthComputeEngine;
-pub use chart_type::ChartType;
\ No newline at end of file
+pub use chart_type::ChartType;
+pub use output_tap::OutputTap;
+#[cfg(feature = "plugin")]
+pub use spectrum::SpectrumAnalyzer;
+pub use recovery::{FailureLog, LockRecover};
+#[cfg(feature = "plugin")]
+pub use recovery::RwLockRecover;
@@ -0,0 +1,144 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use crate::constants::SAMPLE_RATE;
+
+// Must be a power of two so the write position can be wrapped with a mask
+pub const OUTPUT_TAP_CAPACITY: usize = 16384;
+
+/// Lock-free ring buffer holding the most recent output samples.
+///
+/// The audio thread is the only writer and never blocks; the editor takes snapshots
+/// of the tail for the oscilloscope and spectrum analyzer. A snapshot may race with the
+/// writer at its oldest end, which is harmless for visualization purposes.
+pub struct OutputTap {
+    samples: Box<[AtomicU32]>,
+    write_pos: AtomicUsize,
+    sample_rate: AtomicU32,
+}
+
+impl OutputTap {
+    pub fn new() -> Self {
+        Self {
+            samples: (0..OUTPUT_TAP_CAPACITY).map(|_| AtomicU32::new(0)).collect(),
+            write_pos: AtomicUsize::new(0),
+            sample_rate: AtomicU32::new((SAMPLE_RATE as f32).to_bits()),
+        }
+    }
+
+    /// Append one output sample, called from the audio thread
+    pub fn push(&self, sample: f32) {
+        let pos = self.write_pos.load(Ordering::Relaxed);
+        self.samples[pos & (OUTPUT_TAP_CAPACITY - 1)].store(sample.to_bits(), Ordering::Relaxed);
+        self.write_pos.store(pos.wrapping_add(1), Ordering::Release);
+    }
+
+    pub fn set_sample_rate(&self, sample_rate: f32) {
+        self.sample_rate.store(sample_rate.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        f32::from_bits(self.sample_rate.load(Ordering::Relaxed))
+    }
+
+    /// Copy the latest `len` samples, oldest first. Missing history is zero-filled.
+    pub fn snapshot(&self, len: usize) -> Vec<f32> {
+        let len = len.min(OUTPUT_TAP_CAPACITY);
+        let end = self.write_pos.load(Ordering::Acquire);
+        let available = end.min(len);
+
+        let mut out = vec![0.0; len - available];
+        out.extend((end - available..end).map(|pos| {
+            f32::from_bits(self.samples[pos & (OUTPUT_TAP_CAPACITY - 1)].load(Ordering::Relaxed))
+        }));
+        out
+    }
+}
+
+impl Default for OutputTap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Find the first rising zero crossing within `search_len` samples so the oscilloscope
+/// shows a stable picture for periodic signals. Falls back to the start of the window.
+#[cfg(feature = "plugin")]
+pub fn find_trigger(samples: &[f32], search_len: usize) -> usize {
+    let search_len = search_len.min(samples.len());
+    (1..search_len)
+        .find(|&i| samples[i - 1] < 0.0 && samples[i] >= 0.0)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_before_any_output() {
+        let tap = OutputTap::new();
+        let snapshot = tap.snapshot(8);
+        assert_eq!(snapshot, vec![0.0; 8]);
+    }
+
+    #[test]
+    fn test_snapshot_returns_latest_samples_in_order() {
+        let tap = OutputTap::new();
+        for i in 0..10 {
+            tap.push(i as f32);
+        }
+        assert_eq!(tap.snapshot(4), vec![6.0, 7.0, 8.0, 9.0]);
+        // Short history is padded at the front
+        assert_eq!(tap.snapshot(12)[..2], [0.0, 0.0]);
+        assert_eq!(tap.snapshot(12)[2], 0.0);
+        assert_eq!(tap.snapshot(12)[11], 9.0);
+    }
+
+    #[test]
+    fn test_snapshot_wraps_around_capacity() {
+        let tap = OutputTap::new();
+        for i in 0..(OUTPUT_TAP_CAPACITY + 5) {
+            tap.push(i as f32);
+        }
+        let snapshot = tap.snapshot(3);
+        assert_eq!(
+            snapshot,
+            vec![
+                (OUTPUT_TAP_CAPACITY + 2) as f32,
+                (OUTPUT_TAP_CAPACITY + 3) as f32,
+                (OUTPUT_TAP_CAPACITY + 4) as f32
+            ]
+        );
+        assert_eq!(tap.snapshot(OUTPUT_TAP_CAPACITY * 2).len(), OUTPUT_TAP_CAPACITY);
+    }
+
+    #[test]
+    fn test_sample_rate() {
+        let tap = OutputTap::new();
+        assert_eq!(tap.sample_rate(), SAMPLE_RATE as f32);
+        tap.set_sample_rate(48000.0);
+        assert_eq!(tap.sample_rate(), 48000.0);
+    }
+
+    #[test]
+    #[cfg(feature = "plugin")]
+    fn test_find_trigger() {
+        let samples = [0.5, 0.2, -0.3, -0.1, 0.4, 0.6];
+        assert_eq!(find_trigger(&samples, samples.len()), 4);
+        // No crossing within the search range
+        assert_eq!(find_trigger(&samples, 3), 0);
+    }
+}
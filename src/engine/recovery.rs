@@ -0,0 +1,164 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Keeping the plugin alive after a panic in a render thread or the editor.
+//!
+//! A panic while a lock is held poisons it, and unwrapping the lock result would spread the
+//! panic to every later user, up to the host. Shared state is only ever replaced whole, so it
+//! stays usable and the poison is cleared instead. Panics themselves are recorded for the
+//! editor to show.
+
+use std::any::Any;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+#[cfg(feature = "plugin")]
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Locking that recovers from poisoning instead of panicking
+pub trait LockRecover<T: ?Sized> {
+    fn lock_recover(&self) -> MutexGuard<'_, T>;
+}
+
+/// Read and write locking that recovers from poisoning instead of panicking
+#[cfg(feature = "plugin")]
+pub trait RwLockRecover<T: ?Sized> {
+    fn read_recover(&self) -> RwLockReadGuard<'_, T>;
+    fn write_recover(&self) -> RwLockWriteGuard<'_, T>;
+}
+
+impl<T: ?Sized> LockRecover<T> for Mutex<T> {
+    fn lock_recover(&self) -> MutexGuard<'_, T> {
+        self.lock().unwrap_or_else(|poisoned| {
+            log::error!("Recovering a lock poisoned by a panic");
+            self.clear_poison();
+            poisoned.into_inner()
+        })
+    }
+}
+
+#[cfg(feature = "plugin")]
+impl<T: ?Sized> RwLockRecover<T> for RwLock<T> {
+    fn read_recover(&self) -> RwLockReadGuard<'_, T> {
+        self.read().unwrap_or_else(|poisoned| {
+            log::error!("Recovering a lock poisoned by a panic");
+            self.clear_poison();
+            poisoned.into_inner()
+        })
+    }
+
+    fn write_recover(&self) -> RwLockWriteGuard<'_, T> {
+        self.write().unwrap_or_else(|poisoned| {
+            log::error!("Recovering a lock poisoned by a panic");
+            self.clear_poison();
+            poisoned.into_inner()
+        })
+    }
+}
+
+/// Panics caught in the render threads, for the editor
+#[derive(Debug, Default)]
+pub struct FailureLog {
+    count: AtomicUsize,
+    last: Mutex<Option<String>>,
+}
+
+impl FailureLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a caught panic with a description of what was being done
+    pub fn record(&self, context: &str, payload: &(dyn Any + Send)) {
+        let message = format!("{}: {}", context, panic_message(payload));
+        log::error!("{}", message);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        *self.last.lock_recover() = Some(message);
+    }
+
+    /// Number of panics recorded since the last `clear`
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn last(&self) -> Option<String> {
+        self.last.lock_recover().clone()
+    }
+
+    pub fn clear(&self) {
+        self.count.store(0, Ordering::Relaxed);
+        *self.last.lock_recover() = None;
+    }
+}
+
+/// Text of a panic payload, which is a `&str` or a `String` for `panic!` with a message
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_poisoned_mutex_is_recovered() {
+        let data = Arc::new(Mutex::new(vec![1, 2]));
+        let poisoner = data.clone();
+        let result = std::thread::spawn(move || {
+            let _guard = poisoner.lock().unwrap();
+            panic!("render failed");
+        })
+        .join();
+        assert!(result.is_err());
+        assert!(data.is_poisoned());
+
+        assert_eq!(*data.lock_recover(), vec![1, 2]);
+        // The poison is cleared, so plain locking works again too
+        assert!(!data.is_poisoned());
+        data.lock().unwrap().push(3);
+    }
+
+    #[test]
+    #[cfg(feature = "plugin")]
+    fn test_poisoned_rwlock_is_recovered() {
+        let data = Arc::new(RwLock::new(Some("cache".to_string())));
+        let poisoner = data.clone();
+        let _ = std::thread::spawn(move || {
+            let _guard = poisoner.write().unwrap();
+            panic!("editor failed");
+        })
+        .join();
+        assert_eq!(data.read_recover().as_deref(), Some("cache"));
+        *data.write_recover() = None;
+        assert!(data.read().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_failure_log() {
+        let log = FailureLog::new();
+        assert_eq!(log.count(), 0);
+        let payload = std::panic::catch_unwind(|| panic!("bucket {} out of range", 7)).unwrap_err();
+        log.record("Rendering key 3", payload.as_ref());
+        assert_eq!(log.count(), 1);
+        assert_eq!(log.last().as_deref(), Some("Rendering key 3: bucket 7 out of range"));
+        log.clear();
+        assert_eq!((log.count(), log.last()), (0, None));
+    }
+}
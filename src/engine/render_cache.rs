@@ -0,0 +1,218 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! On-disk cache of rendered keys, so reopening a project or switching back to a preset
+//! loads key buffers instead of rendering them again.
+//!
+//! Each file holds the unnormalized sums of one key and is named after a hash of everything
+//! the render depends on. Files are written under a temporary name and renamed, so a crash
+//! never leaves a truncated entry behind.
+
+use std::fs;
+use std::hash::Hasher;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::constants::max_harmonic_for_key;
+use super::render_snapshot::RenderSnapshot;
+
+// Bumped whenever rendering or the file layout changes, which invalidates older entries
+const CACHE_VERSION: u32 = 1;
+const MAGIC: &[u8; 4] = b"LSKR";
+const EXTENSION: &str = "lskr";
+// Magic, version and sample count
+const HEADER_LEN: usize = 16;
+
+// Distinguishes temporary files of concurrent writes of the same entry
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// 64-bit FNV-1a, stable across platforms and Rust versions unlike `DefaultHasher`
+pub struct Fnv1a(u64);
+
+impl Fnv1a {
+    pub fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+}
+
+/// Hash of everything the sums of `key` depend on
+pub fn cache_key(snapshot: &RenderSnapshot, key: usize, period: usize, sample_rate: f32) -> u64 {
+    let mut hasher = Fnv1a::new();
+    hasher.write(&CACHE_VERSION.to_le_bytes());
+    hasher.write(&(key as u64).to_le_bytes());
+    hasher.write(&(period as u64).to_le_bytes());
+    hasher.write(&(max_harmonic_for_key(key) as u64).to_le_bytes());
+    hasher.write(&sample_rate.to_bits().to_le_bytes());
+    snapshot.fingerprint(&mut hasher);
+    hasher.finish()
+}
+
+/// Directory of cached key sums
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderCache {
+    dir: PathBuf,
+}
+
+impl RenderCache {
+    /// Use `dir` for the cache, creating it if needed
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, hash: u64) -> PathBuf {
+        self.dir.join(format!("{:016x}.{}", hash, EXTENSION))
+    }
+
+    /// Cached sums for `hash`, `None` if missing or unreadable
+    pub fn load(&self, hash: u64) -> Option<Vec<f32>> {
+        decode(&fs::read(self.path(hash)).ok()?)
+    }
+
+    pub fn store(&self, hash: u64, sums: &[f32]) -> io::Result<()> {
+        let path = self.path(hash);
+        let temp = self.dir.join(format!(
+            "{:016x}.{}-{}.tmp",
+            hash,
+            std::process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&temp, encode(sums))?;
+        fs::rename(&temp, &path).inspect_err(|_| {
+            let _ = fs::remove_file(&temp);
+        })
+    }
+
+    // Cache entries in the directory
+    fn entries(&self) -> io::Result<impl Iterator<Item = fs::DirEntry>> {
+        Ok(fs::read_dir(&self.dir)?
+            .flatten()
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == EXTENSION)))
+    }
+
+    /// Number of cached keys and their total size in bytes
+    pub fn usage(&self) -> io::Result<(usize, u64)> {
+        Ok(self.entries()?.fold((0, 0), |(count, bytes), entry| {
+            (count + 1, bytes + entry.metadata().map_or(0, |m| m.len()))
+        }))
+    }
+
+    /// Delete all cached keys, leaving other files in the directory alone
+    pub fn clear(&self) -> io::Result<()> {
+        for entry in self.entries()? {
+            fs::remove_file(entry.path())?;
+        }
+        Ok(())
+    }
+}
+
+fn encode(sums: &[f32]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_LEN + std::mem::size_of_val(sums));
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&CACHE_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(sums.len() as u64).to_le_bytes());
+    for sum in sums {
+        bytes.extend_from_slice(&sum.to_le_bytes());
+    }
+    bytes
+}
+
+fn decode(bytes: &[u8]) -> Option<Vec<f32>> {
+    let (header, data) = bytes.split_at_checked(HEADER_LEN)?;
+    let version = u32::from_le_bytes(header[4..8].try_into().ok()?);
+    let len = u64::from_le_bytes(header[8..16].try_into().ok()?) as usize;
+    if &header[..4] != MAGIC || version != CACHE_VERSION || data.len() != len * 4 {
+        return None;
+    }
+    Some(data.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::RenderMethod;
+
+    fn temp_cache(name: &str) -> RenderCache {
+        let dir = std::env::temp_dir().join(format!("lesynth-cache-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        RenderCache::open(dir).unwrap()
+    }
+
+    fn snapshot(amp: f32, method: RenderMethod) -> RenderSnapshot {
+        let amplitudes = vec![vec![amp, 0.5]; 3];
+        let phases = vec![vec![0.0; 2]; 3];
+        RenderSnapshot::new(&amplitudes, &phases, &[true; 3], &[true; 3], method)
+    }
+
+    #[test]
+    fn test_fnv1a_reference_values() {
+        assert_eq!(Fnv1a::new().finish(), 0xcbf2_9ce4_8422_2325);
+        let mut hasher = Fnv1a::new();
+        hasher.write(b"a");
+        assert_eq!(hasher.finish(), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn test_cache_key_covers_render_inputs() {
+        let base = cache_key(&snapshot(0.1, RenderMethod::DirectSum), 10, 800, 44100.0);
+        assert_eq!(base, cache_key(&snapshot(0.1, RenderMethod::DirectSum), 10, 800, 44100.0));
+        assert_ne!(base, cache_key(&snapshot(0.2, RenderMethod::DirectSum), 10, 800, 44100.0));
+        assert_ne!(base, cache_key(&snapshot(0.1, RenderMethod::InverseFft), 10, 800, 44100.0));
+        assert_ne!(base, cache_key(&snapshot(0.1, RenderMethod::DirectSum), 11, 800, 44100.0));
+        assert_ne!(base, cache_key(&snapshot(0.1, RenderMethod::DirectSum), 10, 800, 48000.0));
+    }
+
+    #[test]
+    fn test_store_and_load() {
+        let cache = temp_cache("roundtrip");
+        assert_eq!(cache.load(7), None);
+        cache.store(7, &[0.5, -1.25, 3.0]).unwrap();
+        assert_eq!(cache.load(7), Some(vec![0.5, -1.25, 3.0]));
+        assert_eq!(cache.usage().unwrap(), (1, (HEADER_LEN + 12) as u64));
+
+        // Truncated or foreign files are ignored
+        fs::write(cache.path(8), &encode(&[1.0, 2.0])[..HEADER_LEN + 4]).unwrap();
+        assert_eq!(cache.load(8), None);
+
+        fs::write(cache.dir().join("notes.txt"), "keep").unwrap();
+        cache.clear().unwrap();
+        assert_eq!(cache.usage().unwrap(), (0, 0));
+        assert!(cache.dir().join("notes.txt").exists());
+        fs::remove_dir_all(cache.dir()).unwrap();
+    }
+}
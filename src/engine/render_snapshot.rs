@@ -0,0 +1,316 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::hash::Hasher;
+use std::ops::Range;
+use std::sync::Arc;
+use crate::params::RenderMethod;
+use super::render_kernel::{add_partial, CycleSynth};
+
+/// Harmonic data a key buffer is rendered from, with the enable switches already applied.
+///
+/// Key buffers keep the snapshot they were rendered from, so a later edit only needs to
+/// render the cells that differ between two snapshots.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RenderSnapshot {
+    // [harmonic][bucket], zero for disabled harmonics
+    amplitudes: Vec<Vec<f32>>,
+    // [harmonic][bucket], zero where the phase is disabled
+    phases: Vec<Vec<f32>>,
+    num_buckets: usize,
+    // Divisor of the whole buffer, the sum of every harmonic's loudest bucket if that is above 1
+    scale: f32,
+    method: RenderMethod,
+}
+
+/// Unnormalized sum of a rendered key buffer together with the snapshot it was rendered from
+#[derive(Debug, Clone)]
+pub struct KeyRender {
+    pub snapshot: Arc<RenderSnapshot>,
+    pub sums: Vec<f32>,
+    // Deltas applied since the last full render; rounding errors add up with each one
+    pub deltas: u32,
+}
+
+impl RenderSnapshot {
+    pub fn new(
+        amplitudes: &[Vec<f32>],
+        phases: &[Vec<f32>],
+        ampl_enabled: &[bool],
+        phase_enabled: &[bool],
+        method: RenderMethod,
+    ) -> Self {
+        let num_buckets = amplitudes.first().map_or(0, Vec::len);
+        // One scale for the whole timeline so envelopes keep their shape, taken over all
+        // harmonics whether enabled or not
+        let sum: f32 = amplitudes.iter().map(|row| row.iter().copied().fold(0.0, f32::max)).sum();
+        let scale = if sum > 1.0 { sum } else { 1.0 };
+
+        Self {
+            amplitudes: amplitudes
+                .iter()
+                .zip(ampl_enabled)
+                .map(|(row, &enabled)| if enabled { row.clone() } else { vec![0.0; row.len()] })
+                .collect(),
+            phases: phases
+                .iter()
+                .zip(phase_enabled)
+                .map(|(row, &enabled)| if enabled { row.clone() } else { vec![0.0; row.len()] })
+                .collect(),
+            num_buckets,
+            scale,
+            method,
+        }
+    }
+
+    pub fn num_buckets(&self) -> usize {
+        self.num_buckets
+    }
+
+    /// Feed everything a render depends on to `state`, in a platform-independent byte order
+    pub fn fingerprint(&self, state: &mut impl Hasher) {
+        state.write(&(self.amplitudes.len() as u64).to_le_bytes());
+        state.write(&(self.num_buckets() as u64).to_le_bytes());
+        state.write(&[self.method as u8]);
+        state.write(&self.scale.to_bits().to_le_bytes());
+        for value in self.amplitudes.iter().chain(&self.phases).flatten() {
+            state.write(&value.to_bits().to_le_bytes());
+        }
+    }
+
+    /// Whether a render of this snapshot can be patched into one of `other`
+    pub fn same_shape(&self, other: &RenderSnapshot) -> bool {
+        self.amplitudes.len() == other.amplitudes.len()
+            && self.num_buckets() == other.num_buckets()
+            && self.method == other.method
+    }
+
+    // Add the contribution of one cell to the samples of its bucket, before normalization
+    fn add_cell(&self, samples: &mut [f32], n: usize, bucket: usize, period: usize, sign: f32) {
+        let amp = self.amplitudes[n][bucket];
+        add_partial(samples, n + 1, period, sign * amp, self.phases[n][bucket]);
+    }
+
+    /// Sum the harmonics below `max_harmonic` over a bucket range, `period` samples per bucket.
+    /// Returns `None` if `is_cancelled` reports that the result is no longer wanted.
+    pub fn render(
+        &self,
+        period: usize,
+        max_harmonic: usize,
+        buckets: Range<usize>,
+        is_cancelled: &dyn Fn() -> bool,
+    ) -> Option<Vec<f32>> {
+        let harmonics = self.amplitudes.len().min(max_harmonic);
+        let buckets = buckets.start..buckets.end.min(self.num_buckets());
+        let mut cycle_synth = (self.method == RenderMethod::InverseFft).then(CycleSynth::new);
+        let mut sums = vec![0.0; buckets.len() * period];
+        for (bucket, samples) in buckets.zip(sums.chunks_mut(period.max(1))) {
+            if is_cancelled() {
+                return None;
+            }
+            match cycle_synth.as_mut() {
+                Some(synth) => {
+                    let partials = (0..harmonics)
+                        .filter(|&n| self.amplitudes[n][bucket] != 0.0)
+                        .map(|n| (n + 1, self.amplitudes[n][bucket], self.phases[n][bucket]));
+                    synth.add_cycle(samples, period, partials);
+                }
+                None => {
+                    for n in 0..harmonics {
+                        self.add_cell(samples, n, bucket, period, 1.0);
+                    }
+                }
+            }
+        }
+        Some(sums)
+    }
+
+    /// Cells as (harmonic, bucket) below `max_harmonic` that differ from `old`
+    pub fn changed_cells(&self, old: &RenderSnapshot, max_harmonic: usize) -> Vec<(usize, usize)> {
+        let harmonics = self.amplitudes.len().min(max_harmonic);
+        let mut cells = Vec::new();
+        for n in 0..harmonics {
+            for bucket in 0..self.num_buckets() {
+                let amp_changed = self.amplitudes[n][bucket] != old.amplitudes[n][bucket];
+                let phase_changed = self.phases[n][bucket] != old.phases[n][bucket]
+                    && (self.amplitudes[n][bucket] != 0.0 || old.amplitudes[n][bucket] != 0.0);
+                if amp_changed || phase_changed {
+                    cells.push((n, bucket));
+                }
+            }
+        }
+        cells
+    }
+
+    /// Number of cells below `max_harmonic` that contribute to a full render
+    pub fn active_cells(&self, max_harmonic: usize) -> usize {
+        self.amplitudes
+            .iter()
+            .take(max_harmonic)
+            .map(|row| row.iter().filter(|&&amp| amp != 0.0).count())
+            .sum()
+    }
+
+    /// Turn `sums` rendered from `old` into sums of this snapshot by adding the difference of
+    /// the changed `cells`. Returns `false` if cancelled, leaving `sums` partially updated.
+    pub fn apply_delta(
+        &self,
+        old: &RenderSnapshot,
+        cells: &[(usize, usize)],
+        period: usize,
+        sums: &mut [f32],
+        is_cancelled: &dyn Fn() -> bool,
+    ) -> bool {
+        for &(n, bucket) in cells {
+            if is_cancelled() {
+                return false;
+            }
+            let samples = &mut sums[bucket * period..(bucket + 1) * period];
+            self.add_cell(samples, n, bucket, period, 1.0);
+            old.add_cell(samples, n, bucket, period, -1.0);
+        }
+        true
+    }
+
+    /// Normalized and clamped key buffer from sums rendered from this snapshot
+    pub fn finish(&self, period: usize, sums: &[f32]) -> Vec<f32> {
+        self.finish_from(period, 0, sums)
+    }
+
+    /// Like `finish` for sums of the buckets starting at `first_bucket`
+    pub fn finish_from(&self, period: usize, first_bucket: usize, sums: &[f32]) -> Vec<f32> {
+        let len = self.num_buckets.saturating_sub(first_bucket) * period;
+        sums.iter().take(len).map(|&s| (s / self.scale).clamp(-1.0, 1.0)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(amplitudes: Vec<Vec<f32>>, phases: Vec<Vec<f32>>) -> RenderSnapshot {
+        let enabled = vec![true; amplitudes.len()];
+        RenderSnapshot::new(&amplitudes, &phases, &enabled, &enabled, RenderMethod::DirectSum)
+    }
+
+    #[test]
+    fn test_render_sums_harmonics() {
+        let snap = snapshot(vec![vec![0.5, 0.0], vec![0.25, 0.0]], vec![vec![0.0; 2]; 2]);
+        let sums = snap.render(4, 64, 0..2, &|| false).unwrap();
+        assert_eq!(sums.len(), 8);
+        // cos(0) for both harmonics at t = 0, cos(pi/2) and cos(pi) at t = 1
+        assert!((sums[0] - 0.75).abs() < 1e-6);
+        assert!((sums[1] + 0.25).abs() < 1e-6);
+        assert!(sums[4..].iter().all(|&s| s == 0.0));
+
+        // Harmonics at or above the limit are left out
+        let limited = snap.render(4, 1, 0..2, &|| false).unwrap();
+        assert!((limited[0] - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_render_cancelled() {
+        let snap = snapshot(vec![vec![0.5; 3]], vec![vec![0.0; 3]]);
+        assert_eq!(snap.render(10, 64, 0..3, &|| true), None);
+    }
+
+    #[test]
+    fn test_disabled_harmonics_are_folded_in() {
+        let amplitudes = vec![vec![0.5], vec![0.25]];
+        let phases = vec![vec![1.0], vec![1.0]];
+        let snap = RenderSnapshot::new(&amplitudes, &phases, &[true, false], &[false, true], RenderMethod::DirectSum);
+        assert_eq!(snap.amplitudes, vec![vec![0.5], vec![0.0]]);
+        assert_eq!(snap.phases, vec![vec![0.0], vec![1.0]]);
+    }
+
+    #[test]
+    fn test_changed_cells() {
+        let old = snapshot(vec![vec![0.5, 0.5], vec![0.0, 0.0], vec![0.1, 0.1]], vec![vec![0.0; 2]; 3]);
+        let mut amplitudes = old.amplitudes.clone();
+        let mut phases = old.phases.clone();
+        amplitudes[0][1] = 0.25;
+        amplitudes[2][0] = 0.2;
+        // Phase of a silent harmonic does not matter
+        phases[1][0] = 1.0;
+        let new = snapshot(amplitudes, phases);
+
+        assert_eq!(new.changed_cells(&old, 64), vec![(0, 1), (2, 0)]);
+        assert_eq!(new.changed_cells(&old, 2), vec![(0, 1)]);
+        assert_eq!(new.active_cells(64), 4);
+        assert_eq!(new.active_cells(1), 2);
+    }
+
+    #[test]
+    fn test_delta_matches_full_render() {
+        let period = 50;
+        let old = snapshot(
+            vec![vec![0.3, 0.2, 0.1], vec![0.1, 0.4, 0.0], vec![0.2, 0.0, 0.3]],
+            vec![vec![0.0, 0.5, 1.0], vec![0.2, 0.0, 0.0], vec![0.0, 0.0, 2.0]],
+        );
+        let mut amplitudes = old.amplitudes.clone();
+        let mut phases = old.phases.clone();
+        amplitudes[1] = vec![0.0, 0.1, 0.6];
+        phases[1] = vec![1.0, 1.5, 2.0];
+        let new = snapshot(amplitudes, phases);
+
+        let mut sums = old.render(period, 64, 0..3, &|| false).unwrap();
+        let cells = new.changed_cells(&old, 64);
+        assert_eq!(cells.len(), 3);
+        assert!(new.apply_delta(&old, &cells, period, &mut sums, &|| false));
+
+        let expected = new.render(period, 64, 0..3, &|| false).unwrap();
+        for (delta, full) in sums.iter().zip(&expected) {
+            assert!((delta - full).abs() < 1e-5, "{} != {}", delta, full);
+        }
+    }
+
+    #[test]
+    fn test_inverse_fft_matches_direct_sum() {
+        let period = 441;
+        let amplitudes: Vec<Vec<f32>> = (0..32).map(|n| vec![0.3 / (n + 1) as f32, 0.0, 0.02]).collect();
+        let phases: Vec<Vec<f32>> = (0..32).map(|n| vec![0.1 * n as f32; 3]).collect();
+        let direct = snapshot(amplitudes.clone(), phases.clone());
+        let enabled = vec![true; 32];
+        let ifft = RenderSnapshot::new(&amplitudes, &phases, &enabled, &enabled, RenderMethod::InverseFft);
+        assert!(!ifft.same_shape(&direct));
+
+        let expected = direct.render(period, 20, 0..3, &|| false).unwrap();
+        let rendered = ifft.render(period, 20, 0..3, &|| false).unwrap();
+        assert_eq!(rendered.len(), expected.len());
+        for (a, b) in rendered.iter().zip(&expected) {
+            assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_finish_normalizes_loud_patches() {
+        let snap = snapshot(vec![vec![1.0, 0.5], vec![0.5, 0.25]], vec![vec![0.0; 2]; 2]);
+        let sums = snap.render(2, 64, 0..2, &|| false).unwrap();
+        let buffer = snap.finish(2, &sums);
+        // The loudest buckets sum to 1.5, which scales the whole buffer so the decay is kept
+        assert!((buffer[0] - 1.0).abs() < 1e-6);
+        assert!((buffer[2] - 0.5).abs() < 1e-6);
+        assert!(buffer.iter().all(|s| (-1.0..=1.0).contains(s)));
+
+        // Quiet patches are left alone
+        let quiet = snapshot(vec![vec![0.25, 0.5], vec![0.25, 0.0]], vec![vec![0.0; 2]; 2]);
+        let quiet_buffer = quiet.finish(2, &quiet.render(2, 64, 0..2, &|| false).unwrap());
+        assert!((quiet_buffer[0] - 0.5).abs() < 1e-6);
+        assert!((quiet_buffer[2] - 0.5).abs() < 1e-6);
+
+        // A later range is scaled the same way
+        let tail = snap.render(2, 64, 1..2, &|| false).unwrap();
+        assert_eq!(snap.finish_from(2, 1, &tail), buffer[2..]);
+    }
+}
@@ -13,67 +13,282 @@
 // limitations under the License.
 
 use std::sync::{Arc, Mutex};
-use std::sync::atomic::{AtomicBool, Ordering};
-use crate::constants::NUM_KEYS;
-use crate::voice::Voice;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use rtrb::{Consumer, Producer, RingBuffer};
+use crate::constants::{DEFAULT_PREVIEW_KEY, MEMORY_BUDGET_MB_DEFAULT, NUM_KEYS, SAMPLE_RATE};
+use crate::params::{PlaybackMode, RenderMethod};
+use crate::voice::{AudioCommand, KeySound, VoiceActivity, VoiceBank, AUDIO_QUEUE_CAPACITY};
+use super::job_queue::{JobPriority, JobQueue};
+use super::recovery::{FailureLog, LockRecover};
+use super::render_cache::RenderCache;
+use super::render_snapshot::{KeyRender, RenderSnapshot};
+use super::streaming::{eager_memory_bytes, missing_chunks_ahead, KeyStream, MemoryUsage, BYTES_PER_MB, STREAM_LOOKAHEAD};
+use super::wavetable::{mip_levels, MipTable};
+use super::OutputTap;
+
+// Recently played keys rendered ahead of the remaining ones
+const RECENT_KEYS_PRIORITIZED: usize = 8;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum BufferState {
     Clean,     // Buffer is ready to use
     Dirty,     // Buffer needs recomputation
     Computing, // Buffer is currently being computed
+    Evicted,   // Buffer was freed to stay within the memory budget
+    Failed,    // Rendering panicked; retried after the next edit
+}
+
+/// Rendered waveform of a single key shown in the assembled chart
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PreviewTrace {
+    pub key: usize,
+    pub samples: Vec<f32>,
+}
+
+/// Pending render of the assembled chart; only the request with the latest generation is kept
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PreviewRequest {
+    pub key: usize,
+    pub compare_key: Option<usize>,
+    pub generation: u64,
 }
 
 #[derive(Clone)]
 pub struct SharedParams {
     pub amplitude_data: Arc<Mutex<Vec<Vec<f32>>>>,
-    pub amplitude_data_normalized: Arc<Mutex<Vec<Vec<f32>>>>,
     pub phase_data: Arc<Mutex<Vec<Vec<f32>>>>,
-    pub voices: Arc<Mutex<Vec<Option<Voice>>>>,
-    pub assembled_sound_plotted: Arc<Mutex<Vec<f32>>>,
+    pub assembled_sound_plotted: Arc<Mutex<Option<PreviewTrace>>>,
+    pub assembled_sound_compare: Arc<Mutex<Option<PreviewTrace>>>,
     pub piano_periods: Arc<Mutex<Vec<u32>>>,
-    pub normalization_needed: Arc<Mutex<bool>>,
     pub harmonic_ampl_enabled: Arc<Mutex<Vec<bool>>>,
     pub harmonic_phase_enabled: Arc<Mutex<Vec<bool>>>,
     pub fade_duration: usize,
     
     // Async buffer computation
-    pub key_buffers: Arc<Mutex<Vec<Option<Vec<f32>>>>>,
-    pub buffer_states: Arc<Mutex<Vec<BufferState>>>,
-    pub computation_cancel: Arc<AtomicBool>,
+    pub key_sounds: Arc<Mutex<Vec<Option<KeySound>>>>,
+    // Unnormalized sums behind the key buffers, patched in place when only a few cells change
+    pub key_renders: Arc<Mutex<Vec<Option<KeyRender>>>>,
+    // Streamed key buffers still being rendered. Never lock anything else while holding it,
+    // it is read while the job queue is locked.
+    pub key_streams: Arc<Mutex<Vec<Option<KeyStream>>>>,
+    // Bytes rendered sounds may take before key buffers are streamed and evicted
+    pub memory_budget: Arc<AtomicUsize>,
+    // Rendered keys on disk, `None` when no cache directory is set
+    pub render_cache: Arc<Mutex<Option<RenderCache>>>,
+    // Most recent snapshot, shared by all key renders taken from the same data
+    pub render_snapshot: Arc<Mutex<Arc<RenderSnapshot>>>,
+    pub render_method: Arc<Mutex<RenderMethod>>,
+    pub playback_mode: Arc<Mutex<PlaybackMode>>,
+    // Rendered wavetable per mip level; a level is locked while it is rendered
+    pub mip_tables: Arc<Vec<Mutex<Option<MipTable>>>>,
+    pub job_queue: Arc<JobQueue>,
+    // Panics caught in the computation threads
+    pub render_failures: Arc<FailureLog>,
+    // Rendered sounds not yet handed to the audio thread because its queue was full
+    pub unpublished_sounds: Arc<Mutex<Vec<bool>>>,
+
+    // Lock-free handoff to the audio thread. The mutexes are only ever locked by non-audio
+    // threads; the audio side lives in the voice bank, which the plugin takes once.
+    pub audio_commands: Arc<Mutex<Producer<AudioCommand>>>,
+    pub audio_garbage: Arc<Mutex<Consumer<KeySound>>>,
+    pub voice_bank: Arc<Mutex<Option<VoiceBank>>>,
+    pub voice_activity: Arc<VoiceActivity>,
     
     // Chart view control
     pub should_reset_chart_view: Arc<AtomicBool>,
+    // Selected preview key, `None` follows the last played key
+    pub preview_key: Arc<Mutex<Option<usize>>>,
+    pub compare_key: Arc<Mutex<Option<usize>>>,
+    pub last_played_key: Arc<AtomicUsize>,
+    // Latest preview job handed to the job queue
+    pub last_preview_request: Arc<Mutex<Option<PreviewRequest>>>,
+    pub preview_generation: Arc<AtomicU64>,
+    // Generation of the request whose traces are currently plotted
+    pub assembled_sound_generation: Arc<AtomicU64>,
+
+    // Most recent output samples published by the audio thread for the analyzer
+    pub output_tap: Arc<OutputTap>,
 }
 
 impl SharedParams {
     pub fn new(num_harmonics: usize, buckets: usize) -> Self {
+        let fade_duration = 128;
+        let (command_producer, command_consumer) = RingBuffer::new(AUDIO_QUEUE_CAPACITY);
+        let (garbage_producer, garbage_consumer) = RingBuffer::new(AUDIO_QUEUE_CAPACITY);
+        let voice_activity = Arc::new(VoiceActivity::new());
+        let voice_bank = VoiceBank::new(command_consumer, garbage_producer, voice_activity.clone(), fade_duration);
+
         Self {
             // 2D arrays for amplitude and phase data:
             // dimensions: [points_per_period/2] x [num_buckets]
             amplitude_data: Arc::new(Mutex::new(vec![vec![0.0; buckets]; num_harmonics])),
-            amplitude_data_normalized: Arc::new(Mutex::new(vec![vec![0.0; buckets]; num_harmonics])),
             phase_data: Arc::new(Mutex::new(vec![vec![0.0; buckets]; num_harmonics])),
-            voices: Arc::new(Mutex::new(vec![None; NUM_KEYS])),
-            assembled_sound_plotted: Arc::new(Mutex::new(Vec::new())),
-            piano_periods: Arc::new(Mutex::new(Self::populate_piano_periods())),
-            normalization_needed: Arc::new(Mutex::new(false)),
+            assembled_sound_plotted: Arc::new(Mutex::new(None)),
+            assembled_sound_compare: Arc::new(Mutex::new(None)),
+            piano_periods: Arc::new(Mutex::new(Self::populate_piano_periods(SAMPLE_RATE))),
             harmonic_ampl_enabled: Arc::new(Mutex::new(vec![true; num_harmonics])),
             harmonic_phase_enabled: Arc::new(Mutex::new(vec![true; num_harmonics])),
-            fade_duration: 128,
+            fade_duration,
             
             // Async buffer computation - initialize all buffers as dirty
-            key_buffers: Arc::new(Mutex::new(vec![None; NUM_KEYS])),
-            buffer_states: Arc::new(Mutex::new(vec![BufferState::Dirty; NUM_KEYS])),
-            computation_cancel: Arc::new(AtomicBool::new(false)),
+            key_sounds: Arc::new(Mutex::new(vec![None; NUM_KEYS])),
+            key_renders: Arc::new(Mutex::new(vec![None; NUM_KEYS])),
+            key_streams: Arc::new(Mutex::new(vec![None; NUM_KEYS])),
+            memory_budget: Arc::new(AtomicUsize::new(MEMORY_BUDGET_MB_DEFAULT as usize * BYTES_PER_MB)),
+            render_cache: Arc::new(Mutex::new(None)),
+            render_snapshot: Arc::new(Mutex::new(Arc::default())),
+            render_method: Arc::new(Mutex::new(RenderMethod::default())),
+            playback_mode: Arc::new(Mutex::new(PlaybackMode::default())),
+            mip_tables: Arc::new(mip_levels().iter().map(|_| Mutex::new(None)).collect()),
+            job_queue: Arc::new(JobQueue::new()),
+            render_failures: Arc::new(FailureLog::new()),
+            unpublished_sounds: Arc::new(Mutex::new(vec![false; NUM_KEYS])),
+
+            audio_commands: Arc::new(Mutex::new(command_producer)),
+            audio_garbage: Arc::new(Mutex::new(garbage_consumer)),
+            voice_bank: Arc::new(Mutex::new(Some(voice_bank))),
+            voice_activity,
             
             // Chart view control
             should_reset_chart_view: Arc::new(AtomicBool::new(false)),
+            preview_key: Arc::new(Mutex::new(Some(DEFAULT_PREVIEW_KEY))),
+            compare_key: Arc::new(Mutex::new(None)),
+            last_played_key: Arc::new(AtomicUsize::new(DEFAULT_PREVIEW_KEY)),
+            last_preview_request: Arc::new(Mutex::new(None)),
+            preview_generation: Arc::new(AtomicU64::new(0)),
+            assembled_sound_generation: Arc::new(AtomicU64::new(0)),
+
+            output_tap: Arc::new(OutputTap::new()),
+        }
+    }
+
+    /// Snapshot of the current harmonic data, reusing the previous one if nothing changed
+    pub fn capture_render_snapshot(&self) -> Arc<RenderSnapshot> {
+        let snapshot = {
+            let amplitude_data = self.amplitude_data.lock_recover();
+            let phase_data = self.phase_data.lock_recover();
+            let harmonic_ampl_enabled = self.harmonic_ampl_enabled.lock_recover();
+            let harmonic_phase_enabled = self.harmonic_phase_enabled.lock_recover();
+            let method = *self.render_method.lock_recover();
+            RenderSnapshot::new(&amplitude_data, &phase_data, &harmonic_ampl_enabled, &harmonic_phase_enabled, method)
+        };
+
+        let mut latest = self.render_snapshot.lock_recover();
+        if **latest != snapshot {
+            *latest = Arc::new(snapshot);
         }
+        latest.clone()
     }
 
-    fn populate_piano_periods() -> Vec<u32> {
-        let sample_rate: f64 = 44100.0;
+    /// Number of time buckets in the harmonic data
+    pub fn num_buckets(&self) -> usize {
+        self.amplitude_data.lock_recover().first().map_or(0, Vec::len)
+    }
+
+    /// Whether key buffers are streamed because rendering all of them would not fit `budget`
+    pub fn streams_within(&self, budget: usize) -> bool {
+        *self.playback_mode.lock_recover() == PlaybackMode::KeyBuffers
+            && eager_memory_bytes(self.num_buckets(), &self.piano_periods.lock_recover()) > budget
+    }
+
+    /// Whether key buffers are currently streamed
+    pub fn streaming(&self) -> bool {
+        self.streams_within(self.memory_budget.load(Ordering::Relaxed))
+    }
+
+    /// Memory held by rendered sounds
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let key_buffers = self.key_sounds.lock_recover().iter().flatten().map(KeySound::memory_bytes).sum();
+        let render_sums = self
+            .key_renders
+            .lock_recover()
+            .iter()
+            .flatten()
+            .map(|render| std::mem::size_of_val(render.sums.as_slice()))
+            .sum();
+        // Levels locked for rendering are counted once they are done
+        let wavetables = self
+            .mip_tables
+            .iter()
+            .filter_map(|level| level.try_lock().ok()?.as_ref().map(|mip| mip.table.memory_bytes()))
+            .sum();
+        MemoryUsage {
+            key_buffers,
+            render_sums,
+            wavetables,
+            budget: self.memory_budget.load(Ordering::Relaxed),
+            streaming: self.streaming(),
+        }
+    }
+
+    /// Free memory of the least recently played streamed keys until usage fits the budget:
+    /// first the chunks after their beginnings, then whole keys. Sounding and visible keys
+    /// are kept.
+    pub fn enforce_memory_budget(&self) {
+        if !self.streaming() {
+            return;
+        }
+        let budget = self.memory_budget.load(Ordering::Relaxed);
+        let mut usage = self.memory_usage().total();
+        if usage <= budget {
+            return;
+        }
+
+        let played = self.voice_activity.recently_played(NUM_KEYS);
+        let visible = [Some(self.resolved_preview_key()), *self.compare_key.lock_recover()];
+        let candidates: Vec<usize> = (0..NUM_KEYS)
+            .filter(|key| !played.contains(key))
+            .chain(played.iter().rev().copied())
+            .filter(|&key| !self.voice_activity.is_active(key) && !visible.contains(&Some(key)))
+            .collect();
+
+        for evict in [false, true] {
+            for &key in &candidates {
+                if usage <= budget {
+                    return;
+                }
+                let freed = if evict { self.evict_key(key) } else { self.trim_key(key) };
+                if freed > 0 {
+                    usage = usage.saturating_sub(freed);
+                    self.publish_key_sound(key);
+                }
+            }
+        }
+    }
+
+    // Keep only the first chunk of a streamed key. Returns the bytes freed.
+    fn trim_key(&self, key: usize) -> usize {
+        let mut key_sounds = self.key_sounds.lock_recover();
+        let Some(KeySound::Chunked(buffer)) = &key_sounds[key] else {
+            return 0;
+        };
+        let trimmed = Arc::new(buffer.retain_chunks(|chunk| chunk == 0));
+        let freed = (buffer.rendered_len() - trimmed.rendered_len()) * std::mem::size_of::<f32>();
+        if freed == 0 {
+            return 0;
+        }
+        if let Some(stream) = self.key_streams.lock_recover()[key].as_mut() {
+            stream.buffer = trimmed.clone();
+        }
+        key_sounds[key] = Some(KeySound::Chunked(trimmed));
+        freed
+    }
+
+    // Drop a key's sound entirely. Returns the bytes freed.
+    fn evict_key(&self, key: usize) -> usize {
+        let mut key_sounds = self.key_sounds.lock_recover();
+        let freed = key_sounds[key].as_ref().map_or(0, KeySound::memory_bytes);
+        if freed == 0 || !self.job_queue.evict_key(key) {
+            return 0;
+        }
+        key_sounds[key] = None;
+        self.key_renders.lock_recover()[key] = None;
+        self.key_streams.lock_recover()[key] = None;
+        freed
+    }
+
+    /// Period in samples of every key at `sample_rate`
+    pub fn populate_piano_periods(sample_rate: f64) -> Vec<u32> {
         let mut piano_periods = Vec::with_capacity(NUM_KEYS);
         for key in 0..NUM_KEYS {
             // Calculate the frequency for the given key.
@@ -86,55 +301,136 @@ impl SharedParams {
         piano_periods
     }
     
-    /// Mark all buffers as dirty and cancel any ongoing computations
-    pub fn mark_all_buffers_dirty(&self) {
-        self.computation_cancel.store(true, Ordering::Relaxed);
-        
-        let mut buffer_states = self.buffer_states.lock().unwrap();
-        for state in buffer_states.iter_mut() {
-            if *state != BufferState::Dirty {
-                *state = BufferState::Dirty;
+    /// Key shown in the assembled chart: the selected one or the last played key
+    pub fn resolved_preview_key(&self) -> usize {
+        self.preview_key
+            .lock_recover()
+            .unwrap_or_else(|| self.last_played_key.load(Ordering::Relaxed))
+    }
+
+    /// Queue a render of the current preview and comparison keys, replacing any pending request
+    pub fn request_preview(&self) {
+        let request = PreviewRequest {
+            key: self.resolved_preview_key(),
+            compare_key: *self.compare_key.lock_recover(),
+            generation: self.preview_generation.fetch_add(1, Ordering::AcqRel) + 1,
+        };
+        *self.last_preview_request.lock_recover() = Some(request);
+        self.job_queue.submit_preview(request);
+    }
+
+    /// Keys the background thread should render first
+    pub fn job_priority(&self) -> JobPriority {
+        let activity = &self.voice_activity;
+        let preview_key = self.resolved_preview_key();
+        let compare_key = *self.compare_key.lock_recover();
+        JobPriority {
+            chunks: self.chunks_ahead(),
+            held: (0..NUM_KEYS).filter(|&key| activity.is_held(key)).collect(),
+            visible: std::iter::once(preview_key).chain(compare_key).collect(),
+            recent: activity.recently_played(RECENT_KEYS_PRIORITIZED),
+        }
+    }
+
+    /// Missing chunks of sounding streamed keys ahead of their playheads, nearest first
+    fn chunks_ahead(&self) -> Vec<(usize, u64, usize)> {
+        let activity = &self.voice_activity;
+        let streams = self.key_streams.lock_recover();
+        let mut chunks = Vec::new();
+        for key in (0..NUM_KEYS).filter(|&key| activity.is_active(key)) {
+            let Some(stream) = streams[key].as_ref().filter(|s| self.job_queue.is_current(key, s.generation)) else {
+                continue;
+            };
+            let buffer = &stream.buffer;
+            let position = activity.position(key);
+            let first = buffer.chunk_of(position % buffer.len().max(1));
+            for chunk in missing_chunks_ahead(buffer, position, STREAM_LOOKAHEAD) {
+                let distance = (chunk + buffer.num_chunks() - first) % buffer.num_chunks();
+                chunks.push((distance, key, stream.generation, chunk));
+            }
+        }
+        chunks.sort_unstable();
+        chunks.into_iter().map(|(_, key, generation, chunk)| (key, generation, chunk)).collect()
+    }
+
+    /// Take the audio side of the handoff; only the first caller gets it
+    pub fn take_voice_bank(&self) -> Option<VoiceBank> {
+        self.voice_bank.lock_recover().take()
+    }
+
+    /// Queue a command for the audio thread. Returns `false` if its queue is full.
+    pub fn send_audio_command(&self, command: AudioCommand) -> bool {
+        self.audio_commands.lock_recover().push(command).is_ok()
+    }
+
+    /// Hand a freshly rendered key sound to the audio thread
+    pub fn publish_key_sound(&self, key: usize) {
+        self.unpublished_sounds.lock_recover()[key] = true;
+        self.flush_key_sounds();
+    }
+
+    /// Send rendered sounds that did not fit into the audio queue earlier
+    pub fn flush_key_sounds(&self) {
+        let mut unpublished = self.unpublished_sounds.lock_recover();
+        let key_sounds = self.key_sounds.lock_recover();
+        let mut commands = self.audio_commands.lock_recover();
+        for key in 0..NUM_KEYS {
+            if !unpublished[key] {
+                continue;
+            }
+            // An evicted key gets an empty sound so the audio thread lets go of the old one
+            let sound = key_sounds[key].clone().unwrap_or_else(|| Vec::new().into());
+            if commands.push(AudioCommand::SetSound(key, sound)).is_err() {
+                break;
             }
+            unpublished[key] = false;
         }
     }
+
+    /// Free sounds the audio thread has replaced
+    pub fn collect_audio_garbage(&self) {
+        let mut garbage = self.audio_garbage.lock_recover();
+        while garbage.pop().is_ok() {}
+    }
+
+    /// Mark all buffers as dirty and cancel any ongoing computations
+    pub fn mark_all_buffers_dirty(&self) {
+        self.job_queue.mark_all_dirty();
+    }
     
     /// Mark a specific buffer as dirty
     pub fn mark_buffer_dirty(&self, key: usize) {
-        if key < NUM_KEYS {
-            let mut buffer_states = self.buffer_states.lock().unwrap();
-            if buffer_states[key] != BufferState::Dirty {
-                buffer_states[key] = BufferState::Dirty;
-            }
-        }
+        self.job_queue.mark_dirty(key);
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::voice::ChunkedBuffer;
 
     #[test]
     fn test_shared_params_new() {
         let params = SharedParams::new(8, 50);
         
         // Test amplitude data initialization
-        let amp_data = params.amplitude_data.lock().unwrap();
+        let amp_data = params.amplitude_data.lock_recover();
         assert_eq!(amp_data.len(), 8);
         assert_eq!(amp_data[0].len(), 50);
         
         // Test phase data initialization
-        let phase_data = params.phase_data.lock().unwrap();
+        let phase_data = params.phase_data.lock_recover();
         assert_eq!(phase_data.len(), 8);
         assert_eq!(phase_data[0].len(), 50);
         
-        // Test voices initialization
-        let voices = params.voices.lock().unwrap();
-        assert_eq!(voices.len(), NUM_KEYS);
-        assert!(voices.iter().all(|v| v.is_none()));
+        // Test voice handoff initialization
+        assert!((0..NUM_KEYS).all(|key| !params.voice_activity.is_active(key)));
+        assert!(params.take_voice_bank().is_some());
+        assert!(params.take_voice_bank().is_none());
         
         // Test harmonic enabled flags
-        let amp_enabled = params.harmonic_ampl_enabled.lock().unwrap();
-        let phase_enabled = params.harmonic_phase_enabled.lock().unwrap();
+        let amp_enabled = params.harmonic_ampl_enabled.lock_recover();
+        let phase_enabled = params.harmonic_phase_enabled.lock_recover();
         assert_eq!(amp_enabled.len(), 8);
         assert_eq!(phase_enabled.len(), 8);
         assert!(amp_enabled.iter().all(|&enabled| enabled));
@@ -146,7 +442,7 @@ mod tests {
 
     #[test]
     fn test_populate_piano_periods() {
-        let periods = SharedParams::populate_piano_periods();
+        let periods = SharedParams::populate_piano_periods(SAMPLE_RATE);
         
         assert_eq!(periods.len(), NUM_KEYS);
         
@@ -171,7 +467,7 @@ mod tests {
 
     #[test]
     fn test_piano_periods_mathematical_relationship() {
-        let periods = SharedParams::populate_piano_periods();
+        let periods = SharedParams::populate_piano_periods(SAMPLE_RATE);
         
         // Test that each octave (12 keys) doubles the period (halves frequency)
         for i in 0..NUM_KEYS-12 {
@@ -181,29 +477,184 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_preview_requests_coalesce() {
+        let params = SharedParams::new(4, 10);
+        assert!(params.job_queue.pending_preview().is_none());
+
+        params.request_preview();
+        *params.compare_key.lock_recover() = Some(40);
+        params.request_preview();
+
+        // Only the latest request is pending and it carries the newest generation
+        let pending = params.job_queue.pending_preview().unwrap();
+        assert_eq!(pending.key, DEFAULT_PREVIEW_KEY);
+        assert_eq!(pending.compare_key, Some(40));
+        assert_eq!(pending.generation, 2);
+        assert_eq!(*params.last_preview_request.lock_recover(), Some(pending));
+    }
+
+    #[test]
+    fn test_key_sounds_reach_audio_thread() {
+        let params = SharedParams::new(4, 10);
+        let mut bank = params.take_voice_bank().unwrap();
+
+        params.key_sounds.lock_recover()[5] = Some(vec![0.5; 32].into());
+        params.publish_key_sound(5);
+        assert!(params.send_audio_command(AudioCommand::NoteOn(5)));
+        bank.apply_commands();
+        bank.next_sample();
+        assert!(bank.next_sample() > 0.0);
+
+        // Replacing the buffer retires the old one to the garbage queue
+        params.key_sounds.lock_recover()[5] = Some(vec![0.25; 32].into());
+        params.publish_key_sound(5);
+        bank.apply_commands();
+        assert_eq!(params.audio_garbage.lock_recover().slots(), 1);
+        params.collect_audio_garbage();
+        assert_eq!(params.audio_garbage.lock_recover().slots(), 0);
+    }
+
+    #[test]
+    fn test_unpublished_sounds_are_retried() {
+        let params = SharedParams::new(4, 10);
+        let mut bank = params.take_voice_bank().unwrap();
+
+        // Fill the queue while the audio thread is not running
+        while params.send_audio_command(AudioCommand::NoteOff(0)) {}
+        params.key_sounds.lock_recover()[2] = Some(vec![0.5; 32].into());
+        params.publish_key_sound(2);
+        assert!(params.unpublished_sounds.lock_recover()[2]);
+
+        bank.apply_commands();
+        params.flush_key_sounds();
+        assert!(!params.unpublished_sounds.lock_recover()[2]);
+    }
+
+    #[test]
+    fn test_render_snapshot_is_shared_until_data_changes() {
+        let params = SharedParams::new(4, 10);
+        let first = params.capture_render_snapshot();
+        assert!(Arc::ptr_eq(&first, &params.capture_render_snapshot()));
+        assert_eq!(first.num_buckets(), 10);
+
+        params.amplitude_data.lock_recover()[2][5] = 0.5;
+        let second = params.capture_render_snapshot();
+        assert!(!Arc::ptr_eq(&first, &second));
+        assert_eq!(second.changed_cells(&first, 4), vec![(2, 5)]);
+    }
+
+    #[test]
+    fn test_job_priority() {
+        let params = SharedParams::new(4, 10);
+        let mut bank = params.take_voice_bank().unwrap();
+        *params.compare_key.lock_recover() = Some(60);
+
+        bank.note_on(30);
+        bank.note_on(40);
+        bank.note_off(40);
+        bank.publish_activity();
+
+        let priority = params.job_priority();
+        assert_eq!(priority.held, vec![30]);
+        assert_eq!(priority.visible, vec![DEFAULT_PREVIEW_KEY, 60]);
+        assert_eq!(priority.recent, vec![40, 30]);
+    }
+
+    #[test]
+    fn test_streaming_follows_budget() {
+        let params = SharedParams::new(4, 10);
+        let eager = eager_memory_bytes(10, &params.piano_periods.lock_recover());
+        assert!(!params.streaming());
+        assert!(params.streams_within(eager - 1));
+        assert!(!params.streams_within(eager));
+
+        // Wavetables are small enough to never be streamed
+        *params.playback_mode.lock_recover() = PlaybackMode::Wavetable;
+        assert!(!params.streams_within(0));
+    }
+
+    #[test]
+    fn test_chunks_ahead_of_sounding_keys() {
+        let params = SharedParams::new(4, 10);
+        let mut bank = params.take_voice_bank().unwrap();
+        let buffer = Arc::new(ChunkedBuffer::new(10 * STREAM_LOOKAHEAD, STREAM_LOOKAHEAD / 2));
+        buffer.fill(0, vec![0.0; STREAM_LOOKAHEAD / 2].into());
+        params.key_streams.lock_recover()[30] = Some(KeyStream {
+            generation: params.job_queue.key_generation(30),
+            snapshot: params.capture_render_snapshot(),
+            buffer,
+        });
+        assert!(params.job_priority().chunks.is_empty());
+
+        bank.note_on(30);
+        bank.publish_activity();
+        let generation = params.job_queue.key_generation(30);
+        assert_eq!(params.job_priority().chunks, vec![(30, generation, 1), (30, generation, 2)]);
+
+        // Streams of an outdated render are left alone
+        params.mark_buffer_dirty(30);
+        assert!(params.job_priority().chunks.is_empty());
+    }
+
+    #[test]
+    fn test_memory_budget_trims_then_evicts() {
+        let params = SharedParams::new(4, 10);
+        let mut bank = params.take_voice_bank().unwrap();
+        params.memory_budget.store(BYTES_PER_MB, Ordering::Relaxed);
+        assert!(params.streaming());
+        for key in [10, 20] {
+            let buffer = ChunkedBuffer::from_samples(&vec![0.1; 200_000], 50_000);
+            params.key_sounds.lock_recover()[key] = Some(KeySound::Chunked(Arc::new(buffer)));
+            assert!(params.job_queue.finish_key(key, params.job_queue.key_generation(key)));
+        }
+        params.publish_key_sound(20);
+        bank.apply_commands();
+        bank.note_on(20);
+        bank.note_off(20);
+        for _ in 0..params.fade_duration + 1 {
+            bank.next_sample();
+        }
+        bank.publish_activity();
+        assert_eq!(params.memory_usage().total(), 1_600_000);
+
+        // Keys never played go first
+        params.enforce_memory_budget();
+        let chunked_len = |key: usize| match &params.key_sounds.lock_recover()[key] {
+            Some(KeySound::Chunked(buffer)) => buffer.rendered_len(),
+            _ => 0,
+        };
+        assert_eq!((chunked_len(10), chunked_len(20)), (50_000, 200_000));
+
+        params.memory_budget.store(500_000, Ordering::Relaxed);
+        params.enforce_memory_budget();
+        assert_eq!((chunked_len(10), chunked_len(20)), (50_000, 50_000));
+
+        // Whole keys are evicted once trimming is not enough
+        params.memory_budget.store(100_000, Ordering::Relaxed);
+        params.enforce_memory_budget();
+        assert_eq!(params.memory_usage().total(), 0);
+        assert_eq!(params.job_queue.buffer_state(10), BufferState::Evicted);
+        assert!(params.key_sounds.lock_recover()[20].is_none());
+    }
+
     #[test]
     fn test_shared_params_thread_safety() {
         let params = SharedParams::new(4, 10);
         
         // Test that we can lock and modify different mutexes independently
         {
-            let mut amp_data = params.amplitude_data.lock().unwrap();
+            let mut amp_data = params.amplitude_data.lock_recover();
             amp_data[0][0] = 1.0;
         }
         
         {
-            let mut phase_data = params.phase_data.lock().unwrap();
+            let mut phase_data = params.phase_data.lock_recover();
             phase_data[0][0] = 2.0;
         }
         
-        {
-            let mut normalization = params.normalization_needed.lock().unwrap();
-            *normalization = true;
-        }
-        
         // Verify changes were applied
-        assert_eq!(params.amplitude_data.lock().unwrap()[0][0], 1.0);
-        assert_eq!(params.phase_data.lock().unwrap()[0][0], 2.0);
-        assert_eq!(*params.normalization_needed.lock().unwrap(), true);
+        assert_eq!(params.amplitude_data.lock_recover()[0][0], 1.0);
+        assert_eq!(params.phase_data.lock_recover()[0][0], 2.0);
     }
 }
@@ -0,0 +1,136 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use crate::constants::TWO_PI;
+
+// Floor of the analyzer range; silence and numerical noise are clamped here
+pub const SPECTRUM_FLOOR_DB: f32 = -120.0;
+
+/// Hann-windowed magnitude spectrum of the live output
+#[derive(Clone)]
+pub struct SpectrumAnalyzer {
+    fft_size: usize,
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    window_gain: f32,
+}
+
+impl SpectrumAnalyzer {
+    pub fn new(fft_size: usize) -> Self {
+        let fft = FftPlanner::new().plan_fft_forward(fft_size);
+        let window: Vec<f32> = (0..fft_size)
+            .map(|i| 0.5 - 0.5 * (TWO_PI * i as f32 / fft_size as f32).cos())
+            .collect();
+        let window_gain = window.iter().sum::<f32>();
+
+        Self {
+            fft_size,
+            fft,
+            window,
+            window_gain,
+        }
+    }
+
+    pub fn fft_size(&self) -> usize {
+        self.fft_size
+    }
+
+    /// Frequency in Hz of the given spectrum bin
+    pub fn bin_frequency(&self, bin: usize, sample_rate: f32) -> f32 {
+        bin as f32 * sample_rate / self.fft_size as f32
+    }
+
+    /// Magnitudes in dBFS for bins `0..=fft_size / 2`, so a full-scale sine reads ~0 dB.
+    /// Uses the newest `fft_size` samples; shorter input is zero-padded at the front.
+    pub fn magnitude_db(&self, samples: &[f32]) -> Vec<f32> {
+        let start = samples.len().saturating_sub(self.fft_size);
+        let samples = &samples[start..];
+        let padding = self.fft_size - samples.len();
+
+        let mut buffer: Vec<Complex<f32>> = (0..self.fft_size)
+            .map(|i| {
+                let sample = if i < padding { 0.0 } else { samples[i - padding] };
+                Complex::new(sample * self.window[i], 0.0)
+            })
+            .collect();
+        self.fft.process(&mut buffer);
+
+        buffer[..=self.fft_size / 2]
+            .iter()
+            .map(|bin| {
+                let magnitude = 2.0 * bin.norm() / self.window_gain;
+                (20.0 * magnitude.max(1e-9).log10()).max(SPECTRUM_FLOOR_DB)
+            })
+            .collect()
+    }
+}
+
+/// Update peak-hold values in place: follow new maxima immediately, otherwise fall by `decay_db`
+pub fn apply_peak_hold(peaks: &mut Vec<f32>, current: &[f32], decay_db: f32) {
+    if peaks.len() != current.len() {
+        *peaks = current.to_vec();
+        return;
+    }
+    for (peak, &value) in peaks.iter_mut().zip(current) {
+        *peak = value.max((*peak - decay_db).max(SPECTRUM_FLOOR_DB));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_full_scale_sine_reads_zero_db() {
+        let analyzer = SpectrumAnalyzer::new(1024);
+        let sample_rate = 44100.0;
+        // Pick a frequency exactly on bin 64 to avoid leakage
+        let freq = analyzer.bin_frequency(64, sample_rate);
+        let samples: Vec<f32> = (0..1024)
+            .map(|i| (TWO_PI * freq * i as f32 / sample_rate).sin())
+            .collect();
+
+        let spectrum = analyzer.magnitude_db(&samples);
+        assert_eq!(spectrum.len(), 513);
+
+        let (peak_bin, peak_db) = spectrum
+            .iter()
+            .copied()
+            .enumerate()
+            .fold((0, f32::NEG_INFINITY), |best, (i, v)| if v > best.1 { (i, v) } else { best });
+        assert_eq!(peak_bin, 64);
+        assert!(peak_db.abs() < 0.5, "Expected ~0 dB, got {}", peak_db);
+        assert!(spectrum[200] < -60.0);
+    }
+
+    #[test]
+    fn test_silence_is_clamped_to_floor() {
+        let analyzer = SpectrumAnalyzer::new(256);
+        let spectrum = analyzer.magnitude_db(&[0.0; 100]);
+        assert!(spectrum.iter().all(|&v| v == SPECTRUM_FLOOR_DB));
+    }
+
+    #[test]
+    fn test_peak_hold() {
+        let mut peaks = Vec::new();
+        apply_peak_hold(&mut peaks, &[-10.0, -20.0], 1.0);
+        assert_eq!(peaks, vec![-10.0, -20.0]);
+
+        apply_peak_hold(&mut peaks, &[-30.0, -5.0], 1.0);
+        assert_eq!(peaks, vec![-11.0, -5.0]);
+    }
+}
@@ -0,0 +1,149 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Key buffers streamed in chunks when rendering all of them would exceed the memory budget.
+//!
+//! A streamed key starts with only its first chunk rendered; the rest is rendered while the
+//! key is played, a little ahead of its playhead, and freed again once the key has not been
+//! played for a while and memory runs short.
+
+use std::sync::Arc;
+use crate::constants::max_harmonic_for_key;
+use crate::voice::ChunkedBuffer;
+use super::render_snapshot::RenderSnapshot;
+
+// Samples per chunk, rounded down to whole buckets of the key
+const CHUNK_SAMPLES: usize = 32768;
+pub const BYTES_PER_MB: usize = 1 << 20;
+// Samples rendered ahead of a playhead, two seconds at 44.1 kHz
+pub const STREAM_LOOKAHEAD: usize = 88200;
+
+/// Streamed key buffer with the snapshot its remaining chunks are rendered from
+#[derive(Debug, Clone)]
+pub struct KeyStream {
+    pub generation: u64,
+    pub snapshot: Arc<RenderSnapshot>,
+    pub buffer: Arc<ChunkedBuffer>,
+}
+
+/// Memory held by rendered sounds, in bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MemoryUsage {
+    pub key_buffers: usize,
+    // Unnormalized sums kept for patching key buffers
+    pub render_sums: usize,
+    pub wavetables: usize,
+    pub budget: usize,
+    pub streaming: bool,
+}
+
+impl MemoryUsage {
+    pub fn total(&self) -> usize {
+        self.key_buffers + self.render_sums + self.wavetables
+    }
+}
+
+/// Chunk length for a key with the given period
+pub fn chunk_len(period: usize) -> usize {
+    let period = period.max(1);
+    (CHUNK_SAMPLES / period).max(1) * period
+}
+
+/// Memory needed to keep every key buffer fully rendered, including the sums behind it
+pub fn eager_memory_bytes(num_buckets: usize, periods: &[u32]) -> usize {
+    let samples: usize = periods.iter().map(|&period| num_buckets * period as usize).sum();
+    samples * 2 * std::mem::size_of::<f32>()
+}
+
+/// Missing chunks of `buffer` from `position` up to `lookahead` samples ahead, nearest first.
+/// Voices loop, so the range wraps around the end of the buffer.
+pub fn missing_chunks_ahead(buffer: &ChunkedBuffer, position: usize, lookahead: usize) -> Vec<usize> {
+    if buffer.is_empty() {
+        return Vec::new();
+    }
+    let first = buffer.chunk_of(position % buffer.len());
+    let count = lookahead.div_ceil(buffer.chunk_len()) + 1;
+    (0..count.min(buffer.num_chunks()))
+        .map(|offset| (first + offset) % buffer.num_chunks())
+        .filter(|&chunk| !buffer.is_rendered(chunk))
+        .collect()
+}
+
+/// Render one chunk of a key's buffer from `snapshot` into `buffer`.
+/// Returns `false` if cancelled.
+pub fn render_chunk(
+    snapshot: &RenderSnapshot,
+    buffer: &ChunkedBuffer,
+    key: usize,
+    period: usize,
+    chunk: usize,
+    is_cancelled: &dyn Fn() -> bool,
+) -> bool {
+    let chunk_buckets = buffer.chunk_len() / period.max(1);
+    let first_bucket = chunk * chunk_buckets;
+    let buckets = first_bucket..first_bucket + chunk_buckets;
+    let Some(sums) = snapshot.render(period, max_harmonic_for_key(key), buckets, is_cancelled) else {
+        return false;
+    };
+    buffer.fill(chunk, snapshot.finish_from(period, first_bucket, &sums).into());
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::RenderMethod;
+
+    #[test]
+    fn test_chunk_len_holds_whole_buckets() {
+        assert_eq!(chunk_len(1000), 32000);
+        assert_eq!(chunk_len(1604) % 1604, 0);
+        // Keys with a period longer than a chunk still get one bucket per chunk
+        assert_eq!(chunk_len(40000), 40000);
+    }
+
+    #[test]
+    fn test_missing_chunks_ahead_wraps_around() {
+        let buffer = ChunkedBuffer::new(100, 10);
+        buffer.fill(9, Arc::from(vec![0.0; 10]));
+        assert_eq!(missing_chunks_ahead(&buffer, 85, 25), vec![8, 0, 1]);
+        assert_eq!(missing_chunks_ahead(&buffer, 185, 0), vec![8]);
+        assert_eq!(missing_chunks_ahead(&buffer, 0, 1000).len(), 9);
+    }
+
+    #[test]
+    fn test_chunks_match_whole_render() {
+        let amplitudes: Vec<Vec<f32>> = (0..4).map(|n| vec![0.3, 0.2 * n as f32, 0.1, 0.0, 0.5]).collect();
+        let phases = vec![vec![0.5; 5]; 4];
+        let enabled = vec![true; 4];
+        let snapshot = RenderSnapshot::new(&amplitudes, &phases, &enabled, &enabled, RenderMethod::DirectSum);
+        let (key, period) = (30, 400);
+        let expected = snapshot.finish(period, &snapshot.render(period, 64, 0..5, &|| false).unwrap());
+
+        let buffer = ChunkedBuffer::new(expected.len(), 2 * period);
+        assert_eq!(buffer.num_chunks(), 3);
+        for chunk in 0..buffer.num_chunks() {
+            assert!(render_chunk(&snapshot, &buffer, key, period, chunk, &|| false));
+        }
+        for (idx, expected) in expected.iter().enumerate() {
+            assert!((buffer.sample(idx) - expected).abs() < 1e-6);
+        }
+        assert!(!render_chunk(&snapshot, &ChunkedBuffer::new(expected.len(), period), key, period, 0, &|| true));
+    }
+
+    #[test]
+    fn test_eager_memory_counts_buffers_and_sums() {
+        assert_eq!(eager_memory_bytes(10, &[100, 50]), 1500 * 8);
+    }
+}
@@ -12,450 +12,965 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::sync::Arc;
-use std::sync::atomic::Ordering;
-use std::thread;
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
 use std::time::Duration;
-use crate::constants::{NUM_HARMONICS, NUM_OF_BUCKETS_DEFAULT, TWO_PI, NUM_KEYS, max_harmonic_for_key};
-use crate::params::LeSynthParams;
+use crate::constants::{NUM_HARMONICS, NUM_OF_BUCKETS_DEFAULT, NUM_KEYS, key_name, max_harmonic_for_key};
+use crate::params::RenderMethod;
 use super::{ChartType, SharedParams};
-use super::shared_params::BufferState;
+use super::shared_params::{BufferState, PreviewRequest, PreviewTrace};
+use super::job_queue::Job;
+use super::render_snapshot::{KeyRender, RenderSnapshot};
+use super::recovery::{FailureLog, LockRecover};
+use super::render_cache::{cache_key, RenderCache};
+use super::streaming::{chunk_len, render_chunk, KeyStream, MemoryUsage, BYTES_PER_MB};
+use super::wavetable::{mip_level_for_key, render_mip_level};
+use crate::params::PlaybackMode;
+use crate::patch::{HarmonicCurves, Patch, PatchError, RenderSettings, SineCurve};
+use crate::voice::{AudioCommand, ChunkedBuffer, KeySound, VoiceBank};
+
+// How often idle computation threads wake up to free retired buffers
+const HOUSEKEEPING_INTERVAL: Duration = Duration::from_millis(100);
+// Key buffers longer than this are split by bucket range across the worker pool
+const SPLIT_MIN_SAMPLES: usize = 16384;
+// Deltas applied to a key buffer before it is rendered from scratch again to shed rounding errors
+const FULL_RENDER_INTERVAL: u32 = 64;
+
+// Result of rendering a key in the current playback mode
+struct RenderedKey {
+    sound: KeySound,
+    // Sums behind a whole key buffer, to patch it after small edits
+    render: Option<KeyRender>,
+    // Snapshot the remaining chunks of a streamed key buffer are rendered from
+    stream_snapshot: Option<Arc<RenderSnapshot>>,
+}
+
+// Background computation threads sharing one stop signal
+struct WorkerPool {
+    stop: Arc<AtomicBool>,
+    handles: Vec<JoinHandle<()>>,
+}
 
-#[derive(Clone)]
 pub struct SynthComputeEngine {
-    synth_params: Arc<LeSynthParams>,
     pub shared_params: Arc<SharedParams>,
+    workers: Mutex<Option<WorkerPool>>,
+    // Render settings last applied, to act only on the ones that change
+    settings: Mutex<RenderSettings>,
 }
 
 impl SynthComputeEngine {
-    pub fn new(synth_params_p: Arc<LeSynthParams>) -> Self {
+    pub fn new(settings: RenderSettings) -> Self {
         let buckets = NUM_OF_BUCKETS_DEFAULT;
         let engine = Self {
-            synth_params: synth_params_p,
             shared_params: Arc::new(SharedParams::new(NUM_HARMONICS, buckets)),
+            workers: Mutex::new(None),
+            settings: Mutex::new(RenderSettings::default()),
         };
+        engine.set_render_settings(settings);
         
-        // Start background computation thread
-        engine.start_async_computation_thread();
+        // Start background computation threads
+        engine.start_workers();
         
         engine
     }
 
+    /// Number of rendering threads allowed by the render threads setting, keeping at least
+    /// one core free for the host
+    pub fn render_threads(&self) -> usize {
+        let available = thread::available_parallelism().map_or(1, |n| n.get());
+        Self::clamp_render_threads(self.settings.lock_recover().render_threads, available)
+    }
+
+    fn clamp_render_threads(requested: usize, available: usize) -> usize {
+        requested.max(1).min(available.saturating_sub(1).max(1))
+    }
+
+    /// Start the background computation threads if they are not running already
+    pub fn start_workers(&self) {
+        let mut workers = self.workers.lock_recover();
+        if workers.as_ref().is_some_and(|pool| pool.handles.iter().any(|h| !h.is_finished())) {
+            return;
+        }
+
+        let threads = self.render_threads();
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut handles = Vec::with_capacity(threads);
+        for index in 0..threads {
+            match Self::start_async_computation_thread(self.shared_params.clone(), stop.clone(), index, threads) {
+                Ok(handle) => handles.push(handle),
+                Err(err) => log::error!("Failed to start computation thread {}: {}", index, err),
+            }
+        }
+        log::debug!("Started {} computation threads", handles.len());
+        *workers = Some(WorkerPool { stop, handles });
+    }
+
+    /// Stop the background computation threads and wait for them to exit.
+    /// Interrupted renders stay queued and are picked up again by `start_workers`.
+    pub fn stop_workers(&self) {
+        let Some(pool) = self.workers.lock_recover().take() else {
+            return;
+        };
+        pool.stop.store(true, Ordering::Release);
+        self.shared_params.job_queue.wake_all();
+        for handle in pool.handles {
+            if handle.join().is_err() {
+                log::error!("Computation thread panicked");
+            }
+        }
+    }
+
+    /// Restart computation threads that ended without being stopped, which only a panic outside
+    /// of `run_guarded` can cause. Returns the number of threads restarted.
+    pub fn supervise_workers(&self) -> usize {
+        let mut workers = self.workers.lock_recover();
+        let Some(pool) = workers.as_mut().filter(|pool| !pool.stop.load(Ordering::Acquire)) else {
+            return 0;
+        };
+        let parallelism = pool.handles.len();
+        let mut restarted = 0;
+        for index in 0..parallelism {
+            if !pool.handles[index].is_finished() {
+                continue;
+            }
+            match Self::start_async_computation_thread(self.shared_params.clone(), pool.stop.clone(), index, parallelism) {
+                Ok(handle) => {
+                    if let Err(payload) = std::mem::replace(&mut pool.handles[index], handle).join() {
+                        self.shared_params.render_failures.record("Computation thread", payload.as_ref());
+                    }
+                    log::warn!("Restarted computation thread {}", index);
+                    restarted += 1;
+                }
+                Err(err) => log::error!("Failed to restart computation thread {}: {}", index, err),
+            }
+        }
+        restarted
+    }
+
+    /// Panics caught in the computation threads
+    pub fn render_failures(&self) -> &FailureLog {
+        &self.shared_params.render_failures
+    }
+
+    /// Whether any background computation thread is running
+    pub fn workers_running(&self) -> bool {
+        self.worker_count() > 0
+    }
+
+    /// Number of running background computation threads
+    pub fn worker_count(&self) -> usize {
+        self.workers
+            .lock_recover()
+            .as_ref()
+            .map_or(0, |pool| pool.handles.iter().filter(|h| !h.is_finished()).count())
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.shared_params.output_tap.sample_rate()
+    }
+
+    /// Tune the keys for `sample_rate`, rendering all of them again if it changed
+    pub fn set_sample_rate(&self, sample_rate: f32) {
+        self.shared_params.output_tap.set_sample_rate(sample_rate);
+        let periods = SharedParams::populate_piano_periods(sample_rate as f64);
+        {
+            let mut current = self.shared_params.piano_periods.lock_recover();
+            if *current == periods {
+                return;
+            }
+            *current = periods;
+        }
+        log::debug!("Sample rate changed to {}", sample_rate);
+        self.shared_params.mark_all_buffers_dirty();
+        self.update_assembled_chart_preview();
+    }
+
+    pub fn render_settings(&self) -> RenderSettings {
+        self.settings.lock_recover().clone()
+    }
+
+    /// Apply render settings, rendering keys again only if a changed setting requires it
+    pub fn set_render_settings(&self, settings: RenderSettings) {
+        let previous = std::mem::replace(&mut *self.settings.lock_recover(), settings.clone());
+        if previous == settings {
+            return;
+        }
+        self.apply_render_threads();
+        self.apply_render_method(settings.render_method);
+        self.apply_playback_mode(settings.playback_mode);
+        self.apply_memory_budget(settings.memory_budget_mb);
+        if previous.render_cache_dir != settings.render_cache_dir {
+            self.apply_render_cache(settings.render_cache_dir);
+        }
+    }
+
+    /// Resize a running worker pool after the render threads setting changed
+    fn apply_render_threads(&self) {
+        let running = self.worker_count();
+        if running > 0 && running != self.render_threads() {
+            self.stop_workers();
+            self.start_workers();
+        }
+    }
+
+    /// Render all keys again after the render method setting changed
+    fn apply_render_method(&self, method: RenderMethod) {
+        {
+            let mut current = self.shared_params.render_method.lock_recover();
+            if *current == method {
+                return;
+            }
+            *current = method;
+        }
+        log::debug!("Render method changed to {:?}", method);
+        self.shared_params.mark_all_buffers_dirty();
+        self.update_assembled_chart_preview();
+    }
+
+    /// Render all keys again after the playback mode setting changed
+    fn apply_playback_mode(&self, mode: PlaybackMode) {
+        {
+            let mut current = self.shared_params.playback_mode.lock_recover();
+            if *current == mode {
+                return;
+            }
+            *current = mode;
+        }
+        log::debug!("Playback mode changed to {:?}", mode);
+        if mode != PlaybackMode::Wavetable {
+            // Keys no longer hold the tables, so don't keep them alive
+            for mip_table in self.shared_params.mip_tables.iter() {
+                *mip_table.lock_recover() = None;
+            }
+        }
+        self.shared_params.mark_all_buffers_dirty();
+        self.update_assembled_chart_preview();
+    }
+
+    /// Apply a changed memory budget, rendering all keys again if that starts or stops streaming
+    fn apply_memory_budget(&self, budget_mb: usize) {
+        let budget = budget_mb.max(1) * BYTES_PER_MB;
+        let previous = self.shared_params.memory_budget.swap(budget, Ordering::Relaxed);
+        if previous == budget {
+            return;
+        }
+        let streaming = self.shared_params.streams_within(budget);
+        if streaming != self.shared_params.streams_within(previous) {
+            log::debug!("Key buffer streaming {}", if streaming { "enabled" } else { "disabled" });
+            self.shared_params.mark_all_buffers_dirty();
+            self.update_assembled_chart_preview();
+        }
+    }
+
+    /// Render keys whole instead of streaming them, whatever the memory budget, until the
+    /// render settings change again
+    pub fn render_whole_keys(&self) {
+        self.apply_memory_budget(usize::MAX / BYTES_PER_MB);
+    }
+
+    /// Open or close the render cache after its directory setting changed
+    fn apply_render_cache(&self, dir: Option<String>) {
+        let cache = dir.and_then(|dir| match RenderCache::open(&dir) {
+            Ok(cache) => {
+                log::debug!("Using render cache in {}", dir);
+                Some(cache)
+            }
+            Err(err) => {
+                log::warn!("Failed to open render cache in {}: {}", dir, err);
+                None
+            }
+        });
+        *self.shared_params.render_cache.lock_recover() = cache;
+    }
+
+    /// Number of keys in the open render cache and their size in bytes
+    pub fn render_cache_usage(&self) -> Option<(usize, u64)> {
+        let cache = self.shared_params.render_cache.lock_recover().clone()?;
+        cache.usage().ok()
+    }
+
+    /// Delete everything in the render cache
+    pub fn clear_render_cache(&self) {
+        let Some(cache) = self.shared_params.render_cache.lock_recover().clone() else {
+            return;
+        };
+        if let Err(err) = cache.clear() {
+            log::warn!("Failed to clear render cache in {}: {}", cache.dir().display(), err);
+        }
+    }
+
+    /// Memory held by rendered sounds, for the diagnostics readout
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.shared_params.memory_usage()
+    }
+
     pub fn fill_constant_curve(&self, n: usize, value: f32, chart_type: ChartType) {
         let mut data = match chart_type {
-            ChartType::Amp => self.shared_params.amplitude_data.lock().unwrap(),
-            ChartType::Phase => self.shared_params.phase_data.lock().unwrap(),
+            ChartType::Amp => self.shared_params.amplitude_data.lock_recover(),
+            ChartType::Phase => self.shared_params.phase_data.lock_recover(),
         };
         // TODO probably remove this condition?
         if data[n][0] != value {
             for bucket in 0..data[n].len() {
                 data[n][bucket] = value;
             }
-            self.set_normalization_needed(true);
             // Mark all buffers as dirty since harmonic parameters changed
             drop(data); // Release the lock before calling mark_all_buffers_dirty
             self.shared_params.mark_all_buffers_dirty();
-            // Update assembled chart with key 24 for immediate preview
-            self.update_assembled_chart_with_key24();
+            // Update assembled chart for immediate preview
+            self.update_assembled_chart_preview();
         }
     }
 
-    pub fn fill_sin_curve(&self, n: usize, chart_type: ChartType) {
-        let a = match chart_type {
-            ChartType::Amp => self.synth_params.harmonics[n].sine_curve_amp_amp.value(),
-            ChartType::Phase => self.synth_params.harmonics[n].sine_curve_amp_phase.value(),
-        };
-        let b = match chart_type {
-            ChartType::Amp => self.synth_params.harmonics[n].sine_curve_freq_amp.value(),
-            ChartType::Phase => self.synth_params.harmonics[n].sine_curve_freq_phase.value(),
-        };
-        let amp_off = match chart_type {
-            ChartType::Amp => self.synth_params.harmonics[n].curve_offset_amp.value(),
-            ChartType::Phase => self.synth_params.harmonics[n].curve_offset_phase.value(),
-        };
-
+    pub fn fill_sin_curve(&self, n: usize, curve: SineCurve, chart_type: ChartType) {
         let mut data = match chart_type {
-            ChartType::Amp => self.shared_params.amplitude_data.lock().unwrap(),
-            ChartType::Phase => self.shared_params.phase_data.lock().unwrap(),
+            ChartType::Amp => self.shared_params.amplitude_data.lock_recover(),
+            ChartType::Phase => self.shared_params.phase_data.lock_recover(),
         };
-        for bucket in 0..data[n].len() {
-            // compute and clamp
-            let raw = a * (b as f32 * bucket as f32).sin();
-            let val = (raw + amp_off).clamp(0.0, 1.0);
-            data[n][bucket] = val;
+        for (bucket, value) in data[n].iter_mut().enumerate() {
+            *value = curve.value(bucket);
         }
-        self.set_normalization_needed(true);
         // Mark all buffers as dirty since harmonic parameters changed
         drop(data); // Release the lock before calling mark_all_buffers_dirty
         self.shared_params.mark_all_buffers_dirty();
-        // Update assembled chart with key 24 for immediate preview
-        self.update_assembled_chart_with_key24();
+        // Update assembled chart for immediate preview
+        self.update_assembled_chart_preview();
     }
 
-    pub fn normalize_amplitude_data(&self) {
-        let ampl_data = self.shared_params.amplitude_data.lock().unwrap();
-        let mut ampl_data_normalized = self.shared_params.amplitude_data_normalized.lock().unwrap();
-        let maximums: Vec<f32> = ampl_data
-            .iter()
-            .map(|row| row.iter().copied().fold(f32::NEG_INFINITY, f32::max))
-            .collect();
-        let sum: f32 = maximums.iter().copied().sum();
+    /// Replace the curves for which `use_curve(harmonic, chart_type)` holds, resampling them to
+    /// the current number of buckets. Keys are rendered again only if a curve changed.
+    pub fn load_curves(&self, curves: &HarmonicCurves, use_curve: impl Fn(usize, ChartType) -> bool) {
+        let curves = curves.resampled(self.shared_params.num_buckets());
+        let mut changed = false;
+        {
+            let mut amplitude_data = self.shared_params.amplitude_data.lock_recover();
+            let mut phase_data = self.shared_params.phase_data.lock_recover();
+            let rows = [
+                (ChartType::Amp, &mut *amplitude_data, &curves.amplitudes),
+                (ChartType::Phase, &mut *phase_data, &curves.phases),
+            ];
+            for (chart_type, data, new_rows) in rows {
+                for (n, (row, new_row)) in data.iter_mut().zip(new_rows).enumerate() {
+                    if use_curve(n, chart_type.clone()) && row != new_row {
+                        row.clone_from(new_row);
+                        changed = true;
+                    }
+                }
+            }
+        }
+        if !changed {
+            return;
+        }
+        self.shared_params.mark_all_buffers_dirty();
+        self.update_assembled_chart_preview();
+    }
 
-        if ampl_data_normalized.len() != ampl_data.len() {
-            *ampl_data_normalized = vec![vec![0.0; ampl_data[0].len()]; ampl_data.len()];
+    /// Harmonic data and render settings of the current sound
+    pub fn patch(&self) -> Patch {
+        Patch {
+            amplitudes: self.shared_params.amplitude_data.lock_recover().clone(),
+            phases: self.shared_params.phase_data.lock_recover().clone(),
+            amplitude_enabled: self.shared_params.harmonic_ampl_enabled.lock_recover().clone(),
+            phase_enabled: self.shared_params.harmonic_phase_enabled.lock_recover().clone(),
+            render: self.render_settings(),
         }
+    }
 
-        for (a, row) in ampl_data.iter().enumerate() {
-            for (b, &val) in row.iter().enumerate() {
-                ampl_data_normalized[a][b] = if sum > 1.0 { val / sum } else { val };
-            }
+    /// Replace the current sound with `patch` and render every key again
+    pub fn load_patch(&self, patch: &Patch) -> Result<(), PatchError> {
+        patch.validate()?;
+        {
+            let shared = &self.shared_params;
+            shared.amplitude_data.lock_recover().clone_from(&patch.amplitudes);
+            shared.phase_data.lock_recover().clone_from(&patch.phases);
+            shared.harmonic_ampl_enabled.lock_recover().clone_from(&patch.amplitude_enabled);
+            shared.harmonic_phase_enabled.lock_recover().clone_from(&patch.phase_enabled);
         }
+        self.set_render_settings(patch.render.clone());
+        self.shared_params.mark_all_buffers_dirty();
+        self.update_assembled_chart_preview();
+        Ok(())
     }
 
-    pub fn assemble_buffer_for_key(&self, key: usize) -> Vec<f32> {
-        let start_time = std::time::Instant::now();
-        
-        if *self.shared_params.normalization_needed.lock().unwrap() {
-            self.normalize_amplitude_data();
-            *self.shared_params.normalization_needed.lock().unwrap() = false;
+    /// Resample every curve to `num_buckets`, e.g. to follow the bucket count parameter of a
+    /// restored plugin state
+    pub fn set_num_buckets(&self, num_buckets: usize) {
+        if num_buckets == 0 || num_buckets == self.shared_params.num_buckets() {
+            return;
+        }
+        {
+            let mut amplitude_data = self.shared_params.amplitude_data.lock_recover();
+            let mut phase_data = self.shared_params.phase_data.lock_recover();
+            let curves = HarmonicCurves { amplitudes: amplitude_data.clone(), phases: phase_data.clone() };
+            let curves = curves.resampled(num_buckets);
+            *amplitude_data = curves.amplitudes;
+            *phase_data = curves.phases;
         }
+        self.shared_params.mark_all_buffers_dirty();
+        self.update_assembled_chart_preview();
+    }
+
+    /// The voice bank to play the rendered keys with, handed out once
+    pub fn take_voice_bank(&self) -> Option<VoiceBank> {
+        self.shared_params.take_voice_bank()
+    }
 
-        let num_harmonics = self.shared_params.amplitude_data.lock().unwrap().len();
-        let ampl_data_normalized = self.shared_params.amplitude_data_normalized.lock().unwrap();
-        let phase_data = self.shared_params.phase_data.lock().unwrap();
-        let piano_periods = self.shared_params.piano_periods.lock().unwrap();
-        let period = piano_periods[key] as usize;
+    /// One single-cycle frame of `frame_size` samples per bucket, normalized like the key buffers
+    pub fn render_bucket_frames(&self, frame_size: usize) -> Vec<f32> {
+        let snapshot = self.shared_params.capture_render_snapshot();
+        let max_harmonic = NUM_HARMONICS.min(frame_size / 2);
+        let sums = snapshot
+            .render(frame_size, max_harmonic, 0..snapshot.num_buckets(), &|| false)
+            .unwrap_or_default();
+        snapshot.finish(frame_size, &sums)
+    }
 
-        // Calculate maximum usable harmonic for this key to prevent aliasing
+    /// Render a key buffer on the calling thread, the same way the render threads do
+    pub fn assemble_buffer_for_key(&self, key: usize) -> Vec<f32> {
+        let start_time = std::time::Instant::now();
+        let snapshot = self.shared_params.capture_render_snapshot();
+        let period = self.shared_params.piano_periods.lock_recover()[key] as usize;
         let max_harmonic = max_harmonic_for_key(key);
+        let sums = snapshot
+            .render(period, max_harmonic, 0..snapshot.num_buckets(), &|| false)
+            .unwrap_or_default();
+        let sound = snapshot.finish(period, &sums);
 
-        let mut sound = Vec::new();
-        for bucket in 0..ampl_data_normalized[0].len() {
-            for t in 0..period {
-                let mut sample = 0.0;
-                let harmonic_ampl_enabled = self.shared_params.harmonic_ampl_enabled.lock().unwrap();
-                let harmonic_phase_enabled = self.shared_params.harmonic_phase_enabled.lock().unwrap();
-                for n in 0..num_harmonics.min(max_harmonic) {
-                    let amp = ampl_data_normalized[n][bucket];
-                    if !harmonic_ampl_enabled[n] || amp == 0.0 {
-                        continue;
-                    }
-                    let phase = if harmonic_phase_enabled[n] {
-                        phase_data[n][bucket]
-                    } else {
-                        0.0
-                    };
-                    sample += amp
-                        * (TWO_PI * (n as f32 + 1.0) * (t as f32) / (period as f32) + phase).cos();
-                }
-                sound.push(sample.clamp(-1.0, 1.0));
-            }
-        }
-        
-        let elapsed = start_time.elapsed();
-        log::trace!("assemble_buffer_for_key(key={}) took: {:?} (period={}, total_samples={}, max_harmonic={}/{})",
-                 key, elapsed, piano_periods[key], sound.len(), max_harmonic, num_harmonics);
-        
+        log::trace!("assemble_buffer_for_key(key={}) took: {:?} (period={}, total_samples={}, max_harmonic={})",
+                 key, start_time.elapsed(), period, sound.len(), max_harmonic);
         sound
     }
 
-    // Quick mixdown of active voices for plotting
-    pub fn update_plotted_mix(&self) {
-        let voices = self.shared_params.voices.lock().unwrap();
-        // choose a reasonable window length to visualize
-        let target_len = voices
-            .iter()
-            .filter_map(|v| v.as_ref().map(|vv| vv.buffer.len()))
-            .max()
-            .unwrap_or(0);
-        
-        if target_len == 0 {
-            // No active voices - generate a sample waveform using middle C (key 48) for visualization
-            drop(voices); // Release the lock before calling get_buffer_for_key
-            let sample_buffer = self.get_buffer_for_key(48); // Middle C
-            if !sample_buffer.is_empty() {
-                // Clamp the sample buffer for display
-                let clamped_buffer: Vec<f32> = sample_buffer.iter().map(|&s| s.clamp(-1.0, 1.0)).collect();
-                
-                *self.shared_params.assembled_sound_plotted.lock().unwrap() = clamped_buffer;
-            } else {
-                self.shared_params
-                    .assembled_sound_plotted
-                    .lock()
-                    .unwrap()
-                    .clear();
-            }
+    /// Key shown in the assembled chart
+    pub fn preview_key(&self) -> usize {
+        self.shared_params.resolved_preview_key()
+    }
+
+    /// Select the preview key, `None` follows the last played key
+    pub fn set_preview_key(&self, key: Option<usize>) {
+        *self.shared_params.preview_key.lock_recover() = key.filter(|&k| k < NUM_KEYS);
+        self.update_assembled_chart_preview();
+    }
+
+    /// Select a second key to overlay in the assembled chart
+    pub fn set_compare_key(&self, key: Option<usize>) {
+        *self.shared_params.compare_key.lock_recover() = key.filter(|&k| k < NUM_KEYS);
+        self.update_assembled_chart_preview();
+    }
+
+    /// Remember the last played key; the chart follows it when no preview key is selected
+    pub fn note_played(&self, key: usize) {
+        if key >= NUM_KEYS {
             return;
         }
-        let mut mix = vec![0.0f32; target_len];
-        for v in voices.iter().filter_map(|o| o.as_ref()) {
-            // add unclipped (plotting only); clamp for display later
-            for i in 0..v.buffer.len() {
-                mix[i] += v.buffer[i];
-            }
+        self.shared_params.last_played_key.store(key, Ordering::Relaxed);
+    }
+
+    /// Start a note from the editor; the audio thread picks it up on its next block
+    pub fn note_on(&self, key: usize) {
+        if key >= NUM_KEYS {
+            return;
         }
-        for s in &mut mix {
-            *s = s.clamp(-1.0, 1.0);
+        self.note_played(key);
+        if !self.shared_params.send_audio_command(AudioCommand::NoteOn(key)) {
+            log::warn!("Audio command queue full, dropping note on for key {}", key);
         }
-        *self
-            .shared_params
-            .assembled_sound_plotted
-            .lock()
-            .unwrap() = mix;
     }
 
-    pub fn set_normalization_needed(&self, normalization_needed: bool) {
-        *self
-            .shared_params
-            .normalization_needed
-            .lock()
-            .unwrap() = normalization_needed;
-    }
-    
-    /// Update the assembled chart with key 24's waveform for immediate preview
-    pub fn update_assembled_chart_with_key24(&self) {
-        // Force synchronous recomputation instead of using cached buffer
-        let sample_buffer = self.assemble_buffer_for_key(24); // Key 24 (one octave up from key 0)
-        if !sample_buffer.is_empty() {
-            // Clamp the sample buffer for display
-            let clamped_buffer: Vec<f32> = sample_buffer.iter().map(|&s| s.clamp(-1.0, 1.0)).collect();
-            
-            *self.shared_params.assembled_sound_plotted.lock().unwrap() = clamped_buffer;
-            
-            // Signal that the chart view should be reset to default range (0-2000)
-            self.shared_params.should_reset_chart_view.store(true, std::sync::atomic::Ordering::Relaxed);
-            
-            log::debug!("Updated assembled chart with key 24 preview (samples: {})", sample_buffer.len());
-        } else {
-            // If no buffer available, clear the display
-            self.shared_params
-                .assembled_sound_plotted
-                .lock()
-                .unwrap()
-                .clear();
-            log::debug!("Cleared assembled chart (no key 24 buffer available yet)");
+    /// Release a note started from the editor
+    pub fn note_off(&self, key: usize) {
+        if key >= NUM_KEYS {
+            return;
+        }
+        if !self.shared_params.send_audio_command(AudioCommand::NoteOff(key)) {
+            log::warn!("Audio command queue full, dropping note off for key {}", key);
         }
     }
+
+    /// Whether the last requested preview was for different keys than the current selection
+    pub fn preview_is_stale(&self) -> bool {
+        let preview_key = self.preview_key();
+        let compare_key = *self.shared_params.compare_key.lock_recover();
+        let requested = *self.shared_params.last_preview_request.lock_recover();
+        requested.map(|r| (r.key, r.compare_key)) != Some((preview_key, compare_key))
+    }
+
+    /// Whether a preview render is queued or in progress
+    pub fn preview_pending(&self) -> bool {
+        let requested = *self.shared_params.last_preview_request.lock_recover();
+        let plotted = self.shared_params.assembled_sound_generation.load(Ordering::Acquire);
+        requested.is_some_and(|r| r.generation != plotted)
+    }
+
+    /// Fractional bucket position of the most recently started voice that is still held,
+    /// falling back to released voices while they fade out
+    pub fn playback_position(&self) -> Option<f64> {
+        let activity = &self.shared_params.voice_activity;
+        let piano_periods = self.shared_params.piano_periods.lock_recover();
+
+        (0..NUM_KEYS)
+            .filter(|&key| activity.is_active(key))
+            .min_by_key(|&key| (activity.is_released(key), activity.position(key)))
+            .map(|key| activity.position(key) as f64 / piano_periods[key] as f64)
+    }
+
+    /// Queue a background render of the preview key (and the comparison key, if any).
+    /// Repeated calls coalesce: only the latest request is rendered and an outdated render in
+    /// progress is abandoned.
+    pub fn update_assembled_chart_preview(&self) {
+        self.shared_params.request_preview();
+    }
     
-    /// Start the background thread that continuously computes dirty buffers
-    fn start_async_computation_thread(&self) {
-        let shared_params = self.shared_params.clone();
-        
-        thread::spawn(move || {
-            loop {
-                // Check if we need to cancel and reset
-                if shared_params.computation_cancel.load(Ordering::Relaxed) {
-                    shared_params.computation_cancel.store(false, Ordering::Relaxed);
-                    thread::sleep(Duration::from_millis(10));
+    /// Start a background thread that renders queued jobs until `stop` is set.
+    /// `parallelism` is the size of the pool, used to decide how far to split long keys.
+    fn start_async_computation_thread(
+        shared_params: Arc<SharedParams>,
+        stop: Arc<AtomicBool>,
+        index: usize,
+        parallelism: usize,
+    ) -> std::io::Result<JoinHandle<()>> {
+        thread::Builder::new().name(format!("lesynth-compute-{}", index)).spawn(move || {
+            while !stop.load(Ordering::Acquire) {
+                // Housekeeping for the audio thread: free retired buffers, retry pending handoffs
+                Self::run_guarded(&shared_params, None, || {
+                    shared_params.collect_audio_garbage();
+                    shared_params.flush_key_sounds();
+                    shared_params.enforce_memory_budget();
+                });
+
+                // Sleeps until a job is queued, `stop_workers` wakes it or housekeeping is due
+                let Some(job) = shared_params
+                    .job_queue
+                    .next_job(&stop, HOUSEKEEPING_INTERVAL, || shared_params.job_priority())
+                else {
                     continue;
-                }
-                
-                // Find the next dirty buffer to compute, prioritizing key 24 first, then lower keys
-                let mut next_key = None;
-                {
-                    let buffer_states = shared_params.buffer_states.lock().unwrap();
-                    
-                    // First priority: key 24 (for preview)
-                    if buffer_states[24] == BufferState::Dirty {
-                        next_key = Some(24);
-                    } else {
-                        // Second priority: lower keys (which take longer)
-                        for key in 0..NUM_KEYS {
-                            if key != 24 && buffer_states[key] == BufferState::Dirty {
-                                next_key = Some(key);
-                                break;
-                            }
-                        }
-                    }
-                }
-                
-                if let Some(key) = next_key {
-                    // Mark as computing
-                    {
-                        let mut buffer_states = shared_params.buffer_states.lock().unwrap();
-                        if buffer_states[key] == BufferState::Dirty {
-                            buffer_states[key] = BufferState::Computing;
-                        } else {
-                            // State changed while we were acquiring lock, continue
-                            continue;
-                        }
+                };
+
+                Self::run_guarded(&shared_params, job.key(), || match job {
+                    Job::Preview(request) => Self::render_preview_static(&shared_params, request, &stop),
+                    Job::Key { key, generation } => {
+                        Self::render_key_static(&shared_params, key, generation, parallelism, &stop)
                     }
-                    
-                    log::trace!("Starting async computation for key {}", key);
-                    
-                    // Compute the buffer (this is the expensive operation)
-                    let computed_buffer = Self::compute_buffer_for_key_static(&shared_params, key);
-                    
-                    // Check if we were cancelled during computation
-                    if !shared_params.computation_cancel.load(Ordering::Relaxed) {
-                        // Store the computed buffer and mark as clean
-                        {
-                            let mut key_buffers = shared_params.key_buffers.lock().unwrap();
-                            let mut buffer_states = shared_params.buffer_states.lock().unwrap();
-                            
-                            key_buffers[key] = Some(computed_buffer);
-                            buffer_states[key] = BufferState::Clean;
-                        }
-                        log::trace!("Completed async computation for key {}", key);
-                    } else {
-                        // Computation was cancelled, mark as dirty again
-                        let mut buffer_states = shared_params.buffer_states.lock().unwrap();
-                        buffer_states[key] = BufferState::Dirty;
-                        log::trace!("Cancelled async computation for key {}", key);
+                    part @ Job::Part { .. } => Self::render_part_static(&shared_params, part, &stop),
+                    Job::Chunk { key, generation, chunk } => {
+                        Self::render_chunk_static(&shared_params, key, generation, chunk, &stop)
                     }
-                } else {
-                    // No dirty buffers, sleep a bit
-                    thread::sleep(Duration::from_millis(50));
-                }
+                });
             }
-        });
+            log::debug!("Computation thread stopped");
+        })
     }
-    
-    /// Static version of assemble_buffer_for_key for use in background thread
-    fn compute_buffer_for_key_static(shared_params: &Arc<SharedParams>, key: usize) -> Vec<f32> {
-        let start_time = std::time::Instant::now();
-        
-        if *shared_params.normalization_needed.lock().unwrap() {
-            Self::normalize_amplitude_data_static(shared_params);
-            *shared_params.normalization_needed.lock().unwrap() = false;
+
+    /// Run a step of a computation thread, recording a panic instead of letting it end the
+    /// thread. A key whose render panicked is marked failed rather than rendered again.
+    fn run_guarded(shared_params: &SharedParams, key: Option<(usize, u64)>, step: impl FnOnce()) {
+        let Err(payload) = panic::catch_unwind(AssertUnwindSafe(step)) else {
+            return;
+        };
+        match key {
+            Some((key, generation)) => {
+                let context = format!("Rendering key {}", key_name(key));
+                shared_params.render_failures.record(&context, payload.as_ref());
+                shared_params.job_queue.fail_key(key, generation);
+            }
+            None => shared_params.render_failures.record("Computation thread", payload.as_ref()),
         }
-        
-        // Calculate maximum usable harmonic for this key to prevent aliasing
+    }
+
+    /// Split `num_buckets` into contiguous ranges so that each holds at least
+    /// `SPLIT_MIN_SAMPLES` samples, using at most `parallelism` ranges
+    fn split_buckets(num_buckets: usize, period: usize, parallelism: usize) -> Vec<Range<usize>> {
+        let max_parts = (num_buckets * period / SPLIT_MIN_SAMPLES).max(1);
+        let parts = parallelism.min(max_parts).min(num_buckets).max(1);
+        (0..parts)
+            .map(|part| part * num_buckets / parts..(part + 1) * num_buckets / parts)
+            .collect()
+    }
+
+    /// Previous render of a key and the cells that changed since, if patching it is cheaper
+    /// than rendering the key from scratch
+    fn delta_base_static(
+        shared_params: &Arc<SharedParams>,
+        key: usize,
+        snapshot: &RenderSnapshot,
+    ) -> Option<(KeyRender, Vec<(usize, usize)>)> {
+        let key_renders = shared_params.key_renders.lock_recover();
+        let previous = key_renders[key].as_ref()?;
+        if previous.deltas >= FULL_RENDER_INTERVAL || !previous.snapshot.same_shape(snapshot) {
+            return None;
+        }
+
         let max_harmonic = max_harmonic_for_key(key);
+        let cells = snapshot.changed_cells(&previous.snapshot, max_harmonic);
+        // A delta renders each cell twice, once for the old and once for the new contribution
+        if cells.len() * 2 >= snapshot.active_cells(max_harmonic) + previous.snapshot.active_cells(max_harmonic) {
+            return None;
+        }
+        Some((previous.clone(), cells))
+    }
 
-        // Copy all required data once and release locks immediately to avoid blocking GUI
-        let (num_harmonics, ampl_data_copy, phase_data_copy, harmonic_ampl_enabled_copy, harmonic_phase_enabled_copy, period) = {
-            let ampl_data_normalized = shared_params.amplitude_data_normalized.lock().unwrap();
-            let phase_data = shared_params.phase_data.lock().unwrap();
-            let piano_periods = shared_params.piano_periods.lock().unwrap();
-            let harmonic_ampl_enabled = shared_params.harmonic_ampl_enabled.lock().unwrap();
-            let harmonic_phase_enabled = shared_params.harmonic_phase_enabled.lock().unwrap();
-            
-            let num_harmonics = ampl_data_normalized.len();
-            let period = piano_periods[key] as usize;
-            
-            // Deep copy the data we need
-            let ampl_data_copy: Vec<Vec<f32>> = ampl_data_normalized.clone();
-            let phase_data_copy: Vec<Vec<f32>> = phase_data.clone();
-            let harmonic_ampl_enabled_copy: Vec<bool> = harmonic_ampl_enabled.clone();
-            let harmonic_phase_enabled_copy: Vec<bool> = harmonic_phase_enabled.clone();
-            
-            (num_harmonics, ampl_data_copy, phase_data_copy, harmonic_ampl_enabled_copy, harmonic_phase_enabled_copy, period)
-        }; // All locks are released here
-        
-        let mut sound = Vec::new();
-        for bucket in 0..ampl_data_copy[0].len() {
-            // Check for cancellation periodically
-            if shared_params.computation_cancel.load(Ordering::Relaxed) {
-                log::debug!("Computation cancelled for key {} during bucket {}", key, bucket);
-                return Vec::new(); // Return empty buffer on cancellation
+    /// Render a whole key from `snapshot`, patching the previous render when that is cheaper.
+    /// Returns `None` if `is_cancelled` reports that the result is no longer wanted.
+    fn render_whole_key_static(
+        shared_params: &Arc<SharedParams>,
+        key: usize,
+        snapshot: Arc<RenderSnapshot>,
+        is_cancelled: &dyn Fn() -> bool,
+    ) -> Option<KeyRender> {
+        let start_time = std::time::Instant::now();
+        let period = shared_params.piano_periods.lock_recover()[key] as usize;
+
+        if let Some((mut render, cells)) = Self::delta_base_static(shared_params, key, &snapshot) {
+            if !snapshot.apply_delta(&render.snapshot, &cells, period, &mut render.sums, is_cancelled) {
+                return None;
             }
-            
-            // Yield to other threads every few buckets to keep GUI responsive
-            if bucket % 10 == 0 && bucket > 0 {
-                thread::sleep(Duration::from_millis(1));
+            if !cells.is_empty() {
+                render.deltas += 1;
             }
-            
-            for t in 0..period {
-                let mut sample = 0.0;
-                for n in 0..num_harmonics.min(max_harmonic) {
-                    let amp = ampl_data_copy[n][bucket];
-                    if !harmonic_ampl_enabled_copy[n] || amp == 0.0 {
-                        continue;
+            render.snapshot = snapshot;
+            log::trace!("Patched {} cells of key {} in {:?}", cells.len(), key, start_time.elapsed());
+            return Some(render);
+        }
+
+        if let Some(render) = Self::load_cached_static(shared_params, key, &snapshot) {
+            return Some(render);
+        }
+
+        let max_harmonic = max_harmonic_for_key(key);
+        let sums = snapshot.render(period, max_harmonic, 0..snapshot.num_buckets(), is_cancelled)?;
+        log::trace!("async render(key={}) took: {:?} (period={}, total_samples={}, max_harmonic={})",
+                 key, start_time.elapsed(), period, sums.len(), max_harmonic);
+        let render = KeyRender { snapshot, sums, deltas: 0 };
+        Self::save_cached_static(shared_params, key, &render);
+        Some(render)
+    }
+
+    /// Render of a key from `snapshot` kept in the render cache, if there is one
+    fn load_cached_static(shared_params: &Arc<SharedParams>, key: usize, snapshot: &Arc<RenderSnapshot>) -> Option<KeyRender> {
+        let cache = shared_params.render_cache.lock_recover().clone()?;
+        let period = shared_params.piano_periods.lock_recover()[key] as usize;
+        let hash = cache_key(snapshot, key, period, shared_params.output_tap.sample_rate());
+        let sums = cache.load(hash).filter(|sums| sums.len() == snapshot.num_buckets() * period)?;
+        log::trace!("Loaded key {} from the render cache", key);
+        Some(KeyRender { snapshot: snapshot.clone(), sums, deltas: 0 })
+    }
+
+    /// Keep a full render of a key in the render cache. Patched renders are not written, as
+    /// they would rewrite every key on each small edit.
+    fn save_cached_static(shared_params: &Arc<SharedParams>, key: usize, render: &KeyRender) {
+        let Some(cache) = shared_params.render_cache.lock_recover().clone() else {
+            return;
+        };
+        let period = shared_params.piano_periods.lock_recover()[key] as usize;
+        let hash = cache_key(&render.snapshot, key, period, shared_params.output_tap.sample_rate());
+        if let Err(err) = cache.store(hash, &render.sums) {
+            log::warn!("Failed to write key {} to the render cache in {}: {}", key, cache.dir().display(), err);
+        }
+    }
+
+    /// Wavetable of the key's mip level for `snapshot`, rendering the level unless another key
+    /// already did. Returns `None` if cancelled.
+    fn mip_table_static(
+        shared_params: &Arc<SharedParams>,
+        key: usize,
+        snapshot: &Arc<RenderSnapshot>,
+        is_cancelled: &dyn Fn() -> bool,
+    ) -> Option<KeySound> {
+        let level = mip_level_for_key(key);
+        let period = shared_params.piano_periods.lock_recover()[key] as usize;
+        // Held while rendering, so keys of the same level wait for it instead of rendering it again
+        let mut mip_table = shared_params.mip_tables[level].lock_recover();
+        if let Some(current) = mip_table.as_ref().filter(|t| *t.snapshot == **snapshot) {
+            return Some(KeySound::Wavetable { table: current.table.clone(), period });
+        }
+
+        let start_time = std::time::Instant::now();
+        let rendered = render_mip_level(snapshot, level, is_cancelled)?;
+        log::trace!("Rendered mip level {} in {:?}", level, start_time.elapsed());
+        let table = rendered.table.clone();
+        *mip_table = Some(rendered);
+        Some(KeySound::Wavetable { table, period })
+    }
+
+    /// Render what the audio thread plays for a key in the current playback mode, without
+    /// splitting it. A streamed key gets only the chunks needed to start playing when `lazy`
+    /// is set and is rendered whole otherwise. Returns `None` if cancelled.
+    fn render_key_sound_static(
+        shared_params: &Arc<SharedParams>,
+        key: usize,
+        lazy: bool,
+        is_cancelled: &dyn Fn() -> bool,
+    ) -> Option<RenderedKey> {
+        let snapshot = shared_params.capture_render_snapshot();
+        if *shared_params.playback_mode.lock_recover() == PlaybackMode::Wavetable {
+            let sound = Self::mip_table_static(shared_params, key, &snapshot, is_cancelled)?;
+            return Some(RenderedKey { sound, render: None, stream_snapshot: None });
+        }
+
+        let period = shared_params.piano_periods.lock_recover()[key] as usize;
+        if shared_params.streaming() {
+            let len = snapshot.num_buckets() * period;
+            let buffer = if lazy {
+                let buffer = ChunkedBuffer::new(len, chunk_len(period));
+                // The beginning for new notes and the part under a voice that is sounding already
+                let activity = &shared_params.voice_activity;
+                let playing = activity.is_active(key).then(|| buffer.chunk_of(activity.position(key) % len.max(1)));
+                for chunk in std::iter::once(0).chain(playing) {
+                    if !buffer.is_rendered(chunk) && !render_chunk(&snapshot, &buffer, key, period, chunk, is_cancelled) {
+                        return None;
                     }
-                    let phase = if harmonic_phase_enabled_copy[n] {
-                        phase_data_copy[n][bucket]
-                    } else {
-                        0.0
-                    };
-                    sample += amp
-                        * (TWO_PI * (n as f32 + 1.0) * (t as f32) / (period as f32) + phase).cos();
                 }
-                sound.push(sample.clamp(-1.0, 1.0));
+                buffer
+            } else {
+                let sums = snapshot.render(period, max_harmonic_for_key(key), 0..snapshot.num_buckets(), is_cancelled)?;
+                ChunkedBuffer::from_samples(&snapshot.finish(period, &sums), chunk_len(period))
+            };
+            let sound = KeySound::Chunked(Arc::new(buffer));
+            return Some(RenderedKey { sound, render: None, stream_snapshot: Some(snapshot) });
+        }
+
+        let render = Self::render_whole_key_static(shared_params, key, snapshot, is_cancelled)?;
+        let sound = render.snapshot.finish(period, &render.sums).into();
+        Some(RenderedKey { sound, render: Some(render), stream_snapshot: None })
+    }
+
+    /// Render one key and hand it to the audio thread unless it became outdated meanwhile.
+    /// Long buffers that can't be patched are split into parts for the whole worker pool instead.
+    fn render_key_static(
+        shared_params: &Arc<SharedParams>,
+        key: usize,
+        generation: u64,
+        parallelism: usize,
+        stop: &AtomicBool,
+    ) {
+        let queue = &shared_params.job_queue;
+        if *shared_params.playback_mode.lock_recover() == PlaybackMode::KeyBuffers && !shared_params.streaming() {
+            let snapshot = shared_params.capture_render_snapshot();
+            let num_buckets = snapshot.num_buckets();
+            let period = shared_params.piano_periods.lock_recover()[key] as usize;
+            let parts = Self::split_buckets(num_buckets, period, parallelism);
+            if parts.len() > 1 && Self::delta_base_static(shared_params, key, &snapshot).is_none() {
+                if let Some(render) = Self::load_cached_static(shared_params, key, &snapshot) {
+                    let sound = snapshot.finish(period, &render.sums).into();
+                    let rendered = RenderedKey { sound, render: Some(render), stream_snapshot: None };
+                    Self::store_key_sound_static(shared_params, key, generation, rendered);
+                    return;
+                }
+                log::trace!("Splitting computation for key {} into {} parts", key, parts.len());
+                queue.split_key(key, generation, num_buckets * period, parts, snapshot);
+                return;
             }
         }
-        
-        let elapsed = start_time.elapsed();
-        log::trace!("async compute_buffer_for_key(key={}) took: {:?} (period={}, total_samples={}, max_harmonic={}/{})",
-                 key, elapsed, period, sound.len(), max_harmonic, num_harmonics);
-        
-        sound
+
+        log::trace!("Starting async computation for key {}", key);
+        let is_cancelled = || !queue.is_current(key, generation) || stop.load(Ordering::Acquire);
+        let Some(rendered) = Self::render_key_sound_static(shared_params, key, true, &is_cancelled) else {
+            // Outdated renders are already queued again; give back one interrupted by a shutdown
+            queue.abandon_key(key, generation);
+            log::trace!("Cancelled async computation for key {}", key);
+            return;
+        };
+
+        Self::store_key_sound_static(shared_params, key, generation, rendered);
     }
-    
-    /// Static version of normalize_amplitude_data for use in background thread
-    fn normalize_amplitude_data_static(shared_params: &Arc<SharedParams>) {
-        let amplitude_data = shared_params.amplitude_data.lock().unwrap();
-        let mut ampl_data_normalized = shared_params.amplitude_data_normalized.lock().unwrap();
-        
-        for a in 0..amplitude_data.len() {
-            for b in 0..amplitude_data[a].len() {
-                ampl_data_normalized[a][b] = amplitude_data[a][b];
+
+    /// Render a chunk of a streamed key buffer in place; the audio thread already holds it
+    fn render_chunk_static(shared_params: &Arc<SharedParams>, key: usize, generation: u64, chunk: usize, stop: &AtomicBool) {
+        let queue = &shared_params.job_queue;
+        let stream = shared_params.key_streams.lock_recover()[key].clone();
+        if let Some(stream) = stream.filter(|s| s.generation == generation) {
+            let period = shared_params.piano_periods.lock_recover()[key] as usize;
+            let is_cancelled = || !queue.is_current(key, generation) || stop.load(Ordering::Acquire);
+            if render_chunk(&stream.snapshot, &stream.buffer, key, period, chunk, &is_cancelled) {
+                log::trace!("Rendered chunk {} of key {}", chunk, key);
             }
         }
-        
-        for b in 0..ampl_data_normalized[0].len() {
-            let sum: f32 = ampl_data_normalized
-                .iter()
-                .map(|harmonic| harmonic[b])
-                .sum();
-            if sum > 1.0 {
-                for a in 0..ampl_data_normalized.len() {
-                    let val = ampl_data_normalized[a][b];
-                    ampl_data_normalized[a][b] = val / sum;
-                }
+        queue.finish_chunk(key, chunk);
+    }
+
+    /// Render a bucket range of a split key; whoever completes the last part stores the buffer
+    fn render_part_static(shared_params: &Arc<SharedParams>, part: Job, stop: &AtomicBool) {
+        let Job::Part { key, generation, buckets, snapshot } = part else {
+            return;
+        };
+        let queue = &shared_params.job_queue;
+        let period = shared_params.piano_periods.lock_recover()[key] as usize;
+        let is_cancelled = || !queue.is_current(key, generation) || stop.load(Ordering::Acquire);
+
+        let Some(sums) = snapshot.render(period, max_harmonic_for_key(key), buckets.clone(), &is_cancelled) else {
+            queue.requeue_part(Job::Part { key, generation, buckets, snapshot });
+            return;
+        };
+        if let Some(sums) = queue.complete_part(key, generation, buckets.start * period, &sums) {
+            let buffer = snapshot.finish(period, &sums);
+            let render = KeyRender { snapshot: snapshot.clone(), sums, deltas: 0 };
+            Self::save_cached_static(shared_params, key, &render);
+            let rendered = RenderedKey { sound: buffer.into(), render: Some(render), stream_snapshot: None };
+            Self::store_key_sound_static(shared_params, key, generation, rendered);
+        }
+    }
+
+    /// Store a finished sound unless it became outdated and hand it to the audio thread.
+    /// Returns whether the sound was stored.
+    fn store_key_sound_static(
+        shared_params: &Arc<SharedParams>,
+        key: usize,
+        generation: u64,
+        rendered: RenderedKey,
+    ) -> bool {
+        let RenderedKey { sound, render, stream_snapshot } = rendered;
+        let stream = match (&sound, stream_snapshot) {
+            (KeySound::Chunked(buffer), Some(snapshot)) => {
+                Some(KeyStream { generation, snapshot, buffer: buffer.clone() })
             }
+            _ => None,
+        };
+        let finished = {
+            let mut key_sounds = shared_params.key_sounds.lock_recover();
+            let finished = shared_params.job_queue.finish_key(key, generation);
+            if finished {
+                key_sounds[key] = Some(sound);
+                shared_params.key_renders.lock_recover()[key] = render;
+                shared_params.key_streams.lock_recover()[key] = stream;
+            }
+            finished
+        };
+        if finished {
+            shared_params.publish_key_sound(key);
+            log::trace!("Completed async computation for key {}", key);
         }
+        finished
     }
     
-    /// Get a buffer for a key, using pre-computed version if available
-    pub fn get_buffer_for_key(&self, key: usize) -> Vec<f32> {
+    /// Render a preview request on the background thread and publish it to the assembled chart
+    fn render_preview_static(shared_params: &Arc<SharedParams>, request: PreviewRequest, stop: &AtomicBool) {
+        let queue = &shared_params.job_queue;
+
+        // A newer request makes this render obsolete
+        let is_superseded = || shared_params.preview_generation.load(Ordering::Acquire) != request.generation;
+
+        // Interrupted by a parameter edit or shutdown: render the same request again on the next pass
+        let requeue = || {
+            if !is_superseded() {
+                queue.requeue_preview(request);
+            }
+        };
+
+        let mut traces = Vec::with_capacity(2);
+        let mut rendered_generations = Vec::with_capacity(2);
+        for key in std::iter::once(request.key).chain(request.compare_key) {
+            // An edit of the key's data makes this render obsolete too
+            let generation = queue.key_generation(key);
+            let is_cancelled = || is_superseded() || !queue.is_current(key, generation) || stop.load(Ordering::Acquire);
+            let Some(rendered) = Self::render_key_sound_static(shared_params, key, false, &is_cancelled) else {
+                log::trace!("Preview render for key {} interrupted", key);
+                requeue();
+                return;
+            };
+
+            // The preview is exactly what the key plays, so reuse it if the key is outdated too
+            let samples = rendered.sound.to_samples();
+            Self::store_key_sound_static(shared_params, key, generation, rendered);
+
+            traces.push(PreviewTrace { key, samples });
+            rendered_generations.push((key, generation));
+        }
+
+        let outdated = rendered_generations
+            .iter()
+            .any(|&(key, generation)| !queue.is_current(key, generation));
+        if is_superseded() || stop.load(Ordering::Acquire) || outdated {
+            requeue();
+            return;
+        }
+
+        let mut traces = traces.into_iter();
+        let trace = traces.next();
+        log::debug!("Updated assembled chart with key {} preview", request.key);
+        *shared_params.assembled_sound_plotted.lock_recover() = trace;
+        *shared_params.assembled_sound_compare.lock_recover() = traces.next();
+        shared_params
+            .assembled_sound_generation
+            .store(request.generation, Ordering::Release);
+
+        // Signal that the chart view should be reset to the first few buckets
+        shared_params.should_reset_chart_view.store(true, Ordering::Relaxed);
+    }
+
+    /// Get the sound of a key, using pre-computed version if available.
+    /// May render synchronously, so it must never be called from the audio thread.
+    pub fn get_sound_for_key(&self, key: usize) -> KeySound {
         if key >= NUM_KEYS {
-            return Vec::new();
+            return Vec::new().into();
         }
         
-        let buffer_states = self.shared_params.buffer_states.lock().unwrap();
-        let key_buffers = self.shared_params.key_buffers.lock().unwrap();
+        let buffer_state = self.shared_params.job_queue.buffer_state(key);
+        let key_sounds = self.shared_params.key_sounds.lock_recover();
         
-        match buffer_states[key] {
+        match buffer_state {
             BufferState::Clean => {
-                if let Some(ref buffer) = key_buffers[key] {
+                if let Some(ref buffer) = key_sounds[key] {
                     log::debug!("Using pre-computed buffer for key {}", key);
                     return buffer.clone();
                 }
             }
             BufferState::Computing => {
                 // Check if we have an old buffer we can use while waiting
-                if let Some(ref buffer) = key_buffers[key] {
+                if let Some(ref buffer) = key_sounds[key] {
                     log::debug!("Using old buffer for key {} while computing new one", key);
                     return buffer.clone();
                 }
             }
             BufferState::Dirty => {
                 // Check if we have an old buffer we can use
-                if let Some(ref buffer) = key_buffers[key] {
+                if let Some(ref buffer) = key_sounds[key] {
                     log::debug!("Using old buffer for key {} (marked dirty)", key);
                     return buffer.clone();
                 }
             }
+            // Freed to save memory, so it has to be rendered again
+            BufferState::Evicted => {}
+            // Rendering it panicked; keep whatever the key had rather than panic here too
+            BufferState::Failed => {
+                return key_sounds[key].clone().unwrap_or_else(|| Vec::new().into());
+            }
         }
         
         // Fallback to synchronous computation if no buffer available
-        drop(buffer_states);
-        drop(key_buffers);
+        drop(key_sounds);
         log::warn!("Fallback to synchronous computation for key {}", key);
-        self.assemble_buffer_for_key(key)
+        self.assemble_buffer_for_key(key).into()
+    }
+}
+
+impl Drop for SynthComputeEngine {
+    fn drop(&mut self) {
+        self.stop_workers();
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::params::LeSynthParams;
     use std::sync::Arc;
 
     fn create_test_engine() -> SynthComputeEngine {
-        let params = Arc::new(LeSynthParams::default());
-        SynthComputeEngine::new(params)
+        SynthComputeEngine::new(RenderSettings::default())
+    }
+
+    // Poll a condition set by the background thread
+    fn wait_until(mut condition: impl FnMut() -> bool) -> bool {
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(30);
+        while std::time::Instant::now() < deadline {
+            if condition() {
+                return true;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        false
     }
 
     #[test]
@@ -463,7 +978,7 @@ mod tests {
         let engine = create_test_engine();
         
         // Verify shared params were initialized correctly
-        let amp_data = engine.shared_params.amplitude_data.lock().unwrap();
+        let amp_data = engine.shared_params.amplitude_data.lock_recover();
         assert_eq!(amp_data.len(), NUM_HARMONICS);
         assert_eq!(amp_data[0].len(), NUM_OF_BUCKETS_DEFAULT);
     }
@@ -475,7 +990,7 @@ mod tests {
         
         engine.fill_constant_curve(0, test_value, ChartType::Amp);
         
-        let amp_data = engine.shared_params.amplitude_data.lock().unwrap();
+        let amp_data = engine.shared_params.amplitude_data.lock_recover();
         for &value in &amp_data[0] {
             assert_eq!(value, test_value);
         }
@@ -488,63 +1003,411 @@ mod tests {
         
         engine.fill_constant_curve(0, test_value, ChartType::Phase);
         
-        let phase_data = engine.shared_params.phase_data.lock().unwrap();
+        let phase_data = engine.shared_params.phase_data.lock_recover();
         for &value in &phase_data[0] {
             assert_eq!(value, test_value);
         }
     }
 
     #[test]
-    fn test_normalization_needed_flag() {
+    fn test_fill_sin_curve() {
         let engine = create_test_engine();
-        
-        // Initially should be false
-        assert_eq!(*engine.shared_params.normalization_needed.lock().unwrap(), false);
-        
-        // Set to true
-        engine.set_normalization_needed(true);
-        assert_eq!(*engine.shared_params.normalization_needed.lock().unwrap(), true);
-        
-        // Set back to false
-        engine.set_normalization_needed(false);
-        assert_eq!(*engine.shared_params.normalization_needed.lock().unwrap(), false);
+        let curve = SineCurve { amplitude: 0.2, frequency: 0.1, offset: 0.3 };
+        engine.fill_sin_curve(2, curve, ChartType::Amp);
+
+        let amplitude_data = engine.shared_params.amplitude_data.lock_recover();
+        for (bucket, &value) in amplitude_data[2].iter().enumerate() {
+            assert_eq!(value, curve.value(bucket));
+        }
     }
 
     #[test]
-    fn test_normalize_amplitude_data_empty() {
+    fn test_load_patch_renders_keys() {
         let engine = create_test_engine();
-        
-        // Set some test data
-        {
-            let mut amp_data = engine.shared_params.amplitude_data.lock().unwrap();
-            amp_data[0][0] = 0.5;
-            amp_data[1][0] = 0.3;
-        }
-        
-        engine.normalize_amplitude_data();
-        
-        let normalized = engine.shared_params.amplitude_data_normalized.lock().unwrap();
-        // Values should remain the same when sum <= 1.0
-        assert_eq!(normalized[0][0], 0.5);
-        assert_eq!(normalized[1][0], 0.3);
+        let mut patch = Patch::new(30);
+        patch.curve_mut(0, ChartType::Amp).fill(0.5);
+        patch.render.playback_mode = PlaybackMode::Wavetable;
+        engine.load_patch(&patch).unwrap();
+        assert_eq!(engine.patch(), patch);
+        assert_eq!(engine.shared_params.num_buckets(), 30);
+        assert_eq!(*engine.shared_params.playback_mode.lock_recover(), PlaybackMode::Wavetable);
+
+        let sound = engine.get_sound_for_key(40);
+        assert_eq!(sound.len(), 30 * engine.shared_params.piano_periods.lock_recover()[40] as usize);
+        assert!(sound.to_samples().iter().any(|&sample| sample != 0.0));
+
+        patch.amplitudes.pop();
+        assert!(engine.load_patch(&patch).is_err());
     }
 
     #[test]
-    fn test_normalize_amplitude_data_scaling() {
+    fn test_set_num_buckets_resamples_curves() {
+        let engine = create_test_engine();
+        engine.fill_constant_curve(2, 0.5, ChartType::Amp);
+        engine.set_num_buckets(40);
+        assert_eq!(engine.shared_params.num_buckets(), 40);
+        assert_eq!(engine.patch().amplitudes[2], vec![0.5; 40]);
+        assert_eq!(engine.patch().phases[2].len(), 40);
+    }
+
+    #[test]
+    fn test_assemble_buffer_scales_loud_patches() {
         let engine = create_test_engine();
-        
-        // Set test data that requires scaling
         {
-            let mut amp_data = engine.shared_params.amplitude_data.lock().unwrap();
+            let mut amp_data = engine.shared_params.amplitude_data.lock_recover();
             amp_data[0][0] = 1.0;
             amp_data[1][0] = 1.0;
-            // Sum of maximums = 2.0, should scale down by factor of 2
+            // Sum of maximums = 2.0, so the whole buffer is scaled down by a factor of 2
+            amp_data[0][1] = 0.5;
         }
-        
-        engine.normalize_amplitude_data();
-        
-        let normalized = engine.shared_params.amplitude_data_normalized.lock().unwrap();
-        assert_eq!(normalized[0][0], 0.5); // 1.0 / 2.0
-        assert_eq!(normalized[1][0], 0.5); // 1.0 / 2.0
+
+        let buffer = engine.assemble_buffer_for_key(40);
+        let period = engine.shared_params.piano_periods.lock_recover()[40] as usize;
+        assert!((buffer[0] - 1.0).abs() < 1e-6);
+        assert!((buffer[period] - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_preview_key_selection() {
+        let engine = create_test_engine();
+        assert_eq!(engine.preview_key(), crate::constants::DEFAULT_PREVIEW_KEY);
+
+        // A fixed selection ignores played keys
+        engine.note_played(50);
+        assert_eq!(engine.preview_key(), crate::constants::DEFAULT_PREVIEW_KEY);
+
+        engine.set_preview_key(None);
+        assert_eq!(engine.preview_key(), 50);
+        engine.set_compare_key(Some(60));
+        assert!(!engine.preview_is_stale());
+        assert!(wait_until(|| !engine.preview_pending()), "Preview was not rendered in time");
+        {
+            let plotted = engine.shared_params.assembled_sound_plotted.lock_recover();
+            let compare = engine.shared_params.assembled_sound_compare.lock_recover();
+            assert_eq!(plotted.as_ref().unwrap().key, 50);
+            assert_eq!(compare.as_ref().unwrap().key, 60);
+        }
+
+        // Following the last played key marks the chart stale until a new render is requested
+        engine.note_played(62);
+        assert!(engine.preview_is_stale());
+        engine.update_assembled_chart_preview();
+        assert!(!engine.preview_is_stale());
+    }
+
+    #[test]
+    fn test_preview_render_does_not_block_caller() {
+        let engine = create_test_engine();
+        engine.fill_constant_curve(0, 0.5, ChartType::Amp);
+
+        // Requesting a preview of the lowest key returns immediately; the render happens later
+        let start = std::time::Instant::now();
+        engine.set_preview_key(Some(0));
+        engine.update_assembled_chart_preview();
+        assert!(start.elapsed() < std::time::Duration::from_millis(50));
+
+        assert!(wait_until(|| !engine.preview_pending()), "Preview was not rendered in time");
+        let plotted = engine.shared_params.assembled_sound_plotted.lock_recover();
+        let trace = plotted.as_ref().unwrap();
+        assert_eq!(trace.key, 0);
+        assert!(trace.samples.iter().any(|&s| s != 0.0));
+    }
+
+    #[test]
+    fn test_rendered_buffers_are_handed_to_audio_thread() {
+        let engine = create_test_engine();
+        engine.fill_constant_curve(0, 0.5, ChartType::Amp);
+        let mut bank = engine.shared_params.take_voice_bank().unwrap();
+
+        // A note played before its buffer exists is silent until the render arrives
+        engine.note_on(45);
+        bank.apply_commands();
+        bank.publish_activity();
+        assert!(engine.shared_params.voice_activity.is_active(45));
+
+        let audible = wait_until(|| {
+            bank.apply_commands();
+            (0..64).any(|_| bank.next_sample() != 0.0)
+        });
+        assert!(audible, "Buffer for the played key never arrived");
+
+        engine.note_off(45);
+        bank.apply_commands();
+        for _ in 0..engine.shared_params.fade_duration + 1 {
+            bank.next_sample();
+        }
+        bank.publish_activity();
+        assert!(!engine.shared_params.voice_activity.is_active(45));
+    }
+
+    #[test]
+    fn test_worker_pause_and_restart() {
+        let engine = create_test_engine();
+        assert!(engine.workers_running());
+
+        engine.stop_workers();
+        assert!(!engine.workers_running());
+
+        // Nothing is rendered while paused
+        engine.fill_constant_curve(0, 0.5, ChartType::Amp);
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert!(engine.preview_pending());
+        {
+            let states = engine.shared_params.job_queue.buffer_states();
+            assert!(states.iter().all(|&s| s == BufferState::Dirty));
+        }
+
+        // Restarting picks up the queued preview and the dirty buffers
+        engine.start_workers();
+        engine.start_workers();
+        assert!(engine.workers_running());
+        assert!(wait_until(|| !engine.preview_pending()), "Preview was not rendered after restart");
+    }
+
+    #[test]
+    fn test_drop_joins_worker() {
+        let engine = create_test_engine();
+        let shared_params = engine.shared_params.clone();
+        drop(engine);
+
+        // The joined thread has released its handle on the shared state
+        assert_eq!(Arc::strong_count(&shared_params), 1);
+    }
+
+    #[test]
+    fn test_panicking_render_marks_key_failed() {
+        let engine = create_test_engine();
+        // Healthy threads are left alone
+        assert_eq!(engine.supervise_workers(), 0);
+        engine.stop_workers();
+
+        let shared_params = &engine.shared_params;
+        let generation = shared_params.job_queue.key_generation(5);
+        SynthComputeEngine::run_guarded(shared_params, Some((5, generation)), || {
+            let _periods = shared_params.piano_periods.lock_recover();
+            panic!("bucket out of range");
+        });
+        assert_eq!(shared_params.job_queue.buffer_state(5), BufferState::Failed);
+        assert_eq!(engine.render_failures().count(), 1);
+        assert!(engine.render_failures().last().unwrap().ends_with("bucket out of range"));
+        // The lock held by the panicking render is usable again
+        assert!(shared_params.piano_periods.lock_recover()[5] > 0);
+    }
+
+    #[test]
+    fn test_clamp_render_threads() {
+        assert_eq!(SynthComputeEngine::clamp_render_threads(4, 8), 4);
+        assert_eq!(SynthComputeEngine::clamp_render_threads(16, 8), 7);
+        assert_eq!(SynthComputeEngine::clamp_render_threads(0, 8), 1);
+        assert_eq!(SynthComputeEngine::clamp_render_threads(4, 1), 1);
+    }
+
+    #[test]
+    fn test_split_buckets() {
+        // Short buffers are rendered in one piece
+        assert_eq!(SynthComputeEngine::split_buckets(40, 100, 4), vec![0..40]);
+        assert_eq!(SynthComputeEngine::split_buckets(40, 2000, 1), vec![0..40]);
+
+        let parts = SynthComputeEngine::split_buckets(40, 2000, 3);
+        assert_eq!(parts, vec![0..13, 13..26, 26..40]);
+    }
+
+    #[test]
+    fn test_split_key_matches_single_render() {
+        let engine = create_test_engine();
+        engine.stop_workers();
+        engine.fill_constant_curve(0, 0.5, ChartType::Amp);
+        engine.fill_constant_curve(1, 0.25, ChartType::Amp);
+        let expected = engine.assemble_buffer_for_key(0);
+
+        let shared_params = &engine.shared_params;
+        let queue = &shared_params.job_queue;
+        let stop = AtomicBool::new(false);
+        let generation = queue.key_generation(0);
+        SynthComputeEngine::render_key_static(shared_params, 0, generation, 4, &stop);
+
+        // Parts are taken ahead of everything else and the last one stores the buffer
+        let mut parts = 0;
+        while let Some(part @ Job::Part { .. }) =
+            queue.next_job(&stop, Duration::ZERO, || shared_params.job_priority())
+        {
+            SynthComputeEngine::render_part_static(shared_params, part, &stop);
+            parts += 1;
+        }
+        assert!(parts > 1);
+        assert_eq!(queue.buffer_state(0), BufferState::Clean);
+        let rendered = shared_params.key_sounds.lock_recover()[0].clone().unwrap().to_samples();
+        assert_eq!(rendered.len(), expected.len());
+        for (rendered, expected) in rendered.iter().zip(&expected) {
+            assert!((rendered - expected).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_single_harmonic_edit_patches_key_buffer() {
+        let engine = create_test_engine();
+        engine.stop_workers();
+        let shared_params = &engine.shared_params;
+        let queue = &shared_params.job_queue;
+        let stop = AtomicBool::new(false);
+        let key = 40;
+        let render_key = || {
+            let generation = queue.key_generation(key);
+            SynthComputeEngine::render_key_static(shared_params, key, generation, 1, &stop);
+            shared_params.key_renders.lock_recover()[key].clone().unwrap()
+        };
+
+        for n in 0..8 {
+            engine.fill_constant_curve(n, 0.1, ChartType::Amp);
+        }
+        assert_eq!(render_key().deltas, 0);
+
+        // Only the edited harmonic is rendered again, on top of the previous sums
+        engine.fill_constant_curve(3, 0.05, ChartType::Amp);
+        assert_eq!(render_key().deltas, 1);
+        let patched = shared_params.key_sounds.lock_recover()[key].clone().unwrap().to_samples();
+        let expected = engine.assemble_buffer_for_key(key);
+        assert_eq!(patched.len(), expected.len());
+        for (patched, expected) in patched.iter().zip(&expected) {
+            assert!((patched - expected).abs() < 1e-4);
+        }
+
+        // An edit touching most cells renders the key from scratch
+        for n in 0..8 {
+            engine.fill_constant_curve(n, 0.11, ChartType::Amp);
+        }
+        assert_eq!(render_key().deltas, 0);
+    }
+
+    #[test]
+    fn test_streamed_key_renders_chunks_on_demand() {
+        let engine = create_test_engine();
+        engine.stop_workers();
+        let shared_params = &engine.shared_params;
+        shared_params.memory_budget.store(BYTES_PER_MB, Ordering::Relaxed);
+        assert!(shared_params.streaming());
+        engine.fill_constant_curve(0, 0.5, ChartType::Amp);
+        engine.fill_constant_curve(2, 0.25, ChartType::Amp);
+        let expected = engine.assemble_buffer_for_key(0);
+
+        let queue = &shared_params.job_queue;
+        let stop = AtomicBool::new(false);
+        let generation = queue.key_generation(0);
+        SynthComputeEngine::render_key_static(shared_params, 0, generation, 4, &stop);
+        assert_eq!(queue.buffer_state(0), BufferState::Clean);
+        assert!(shared_params.key_renders.lock_recover()[0].is_none());
+
+        // Only the beginning is rendered up front, the rest while the key plays
+        let Some(KeySound::Chunked(buffer)) = shared_params.key_sounds.lock_recover()[0].clone() else {
+            panic!("Key was not streamed");
+        };
+        assert!(buffer.num_chunks() > 1);
+        assert!(buffer.is_rendered(0) && !buffer.is_rendered(1));
+        for chunk in 1..buffer.num_chunks() {
+            SynthComputeEngine::render_chunk_static(shared_params, 0, generation, chunk, &stop);
+        }
+        assert_eq!(buffer.len(), expected.len());
+        for (idx, expected) in expected.iter().enumerate() {
+            assert!((buffer.sample(idx) - expected).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_render_cache_is_used_for_unchanged_data() {
+        let engine = create_test_engine();
+        engine.stop_workers();
+        let dir = std::env::temp_dir().join(format!("lesynth-engine-cache-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        engine.set_render_settings(RenderSettings {
+            render_cache_dir: Some(dir.display().to_string()),
+            ..engine.render_settings()
+        });
+        engine.fill_constant_curve(0, 0.5, ChartType::Amp);
+
+        let shared_params = &engine.shared_params;
+        let stop = AtomicBool::new(false);
+        let key = 40;
+        let render_key = || {
+            let generation = shared_params.job_queue.key_generation(key);
+            SynthComputeEngine::render_key_static(shared_params, key, generation, 1, &stop);
+        };
+        render_key();
+        assert_eq!(engine.render_cache_usage().map(|(keys, _)| keys), Some(1));
+
+        // Replace the entry so that loading it can be told apart from rendering again
+        let snapshot = shared_params.capture_render_snapshot();
+        let period = shared_params.piano_periods.lock_recover()[key] as usize;
+        let cache = shared_params.render_cache.lock_recover().clone().unwrap();
+        let hash = cache_key(&snapshot, key, period, shared_params.output_tap.sample_rate());
+        cache.store(hash, &vec![0.25; snapshot.num_buckets() * period]).unwrap();
+        shared_params.key_renders.lock_recover()[key] = None;
+        shared_params.mark_buffer_dirty(key);
+        render_key();
+        let sound = shared_params.key_sounds.lock_recover()[key].clone().unwrap();
+        assert!(sound.to_samples().iter().all(|&s| s == 0.25));
+
+        engine.clear_render_cache();
+        assert_eq!(engine.render_cache_usage().map(|(keys, _)| keys), Some(0));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_wavetable_mode_shares_mip_tables() {
+        let engine = create_test_engine();
+        engine.stop_workers();
+        *engine.shared_params.playback_mode.lock_recover() = PlaybackMode::Wavetable;
+        engine.fill_constant_curve(0, 0.5, ChartType::Amp);
+        engine.fill_constant_curve(1, 0.25, ChartType::Amp);
+
+        let shared_params = &engine.shared_params;
+        let queue = &shared_params.job_queue;
+        let stop = AtomicBool::new(false);
+        for key in [0, 1] {
+            let generation = queue.key_generation(key);
+            SynthComputeEngine::render_key_static(shared_params, key, generation, 4, &stop);
+            assert_eq!(queue.buffer_state(key), BufferState::Clean);
+            assert!(shared_params.key_renders.lock_recover()[key].is_none());
+        }
+
+        // Keys of one octave play the same table at their own period
+        let sounds = shared_params.key_sounds.lock_recover();
+        let (Some(KeySound::Wavetable { table: a, .. }), Some(KeySound::Wavetable { table: b, .. })) =
+            (&sounds[0], &sounds[1])
+        else {
+            panic!("Keys were not rendered as wavetables");
+        };
+        assert!(Arc::ptr_eq(a, b));
+
+        let rendered = sounds[0].as_ref().unwrap().to_samples();
+        let expected = engine.assemble_buffer_for_key(0);
+        assert_eq!(rendered.len(), expected.len());
+        for (rendered, expected) in rendered.iter().zip(&expected) {
+            assert!((rendered - expected).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_playback_position() {
+        let engine = create_test_engine();
+        assert_eq!(engine.playback_position(), None);
+
+        let period = engine.shared_params.piano_periods.lock_recover()[30] as usize;
+        let mut bank = engine.shared_params.take_voice_bank().unwrap();
+        assert!(engine
+            .shared_params
+            .send_audio_command(AudioCommand::SetSound(30, vec![0.1; period * 4].into())));
+        bank.apply_commands();
+        bank.note_on(30);
+        for _ in 0..period * 2 + period / 2 {
+            bank.next_sample();
+        }
+
+        // A released voice that started later should not take over the indicator
+        bank.note_on(40);
+        bank.note_off(40);
+        bank.publish_activity();
+
+        let position = engine.playback_position().unwrap();
+        assert!((position - 2.5).abs() < 0.01, "Expected ~2.5 buckets, got {}", position);
     }
 }
@@ -0,0 +1,129 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Mip-mapped wavetables for the wavetable playback mode.
+//!
+//! Every octave of keys gets a level whose frames only hold the harmonics that stay below
+//! Nyquist for the octave's highest key; octaves with the same limit share one level.
+
+use std::sync::Arc;
+use crate::constants::{max_harmonic_for_key, NUM_KEYS};
+use crate::voice::Wavetable;
+use super::render_snapshot::RenderSnapshot;
+
+// Frame points per cycle of the highest harmonic, enough for cubic interpolation
+const POINTS_PER_HARMONIC_CYCLE: usize = 32;
+const MIN_FRAME_SIZE: usize = 64;
+
+/// Rendered level together with the snapshot it was rendered from
+#[derive(Debug, Clone)]
+pub struct MipTable {
+    pub snapshot: Arc<RenderSnapshot>,
+    pub table: Arc<Wavetable>,
+}
+
+fn octave_limit(octave: usize) -> usize {
+    max_harmonic_for_key((octave * 12 + 11).min(NUM_KEYS - 1))
+}
+
+/// Harmonic limit of each mip level, from the lowest keys up
+pub fn mip_levels() -> Vec<usize> {
+    let mut levels: Vec<usize> = (0..NUM_KEYS.div_ceil(12)).map(octave_limit).collect();
+    levels.dedup();
+    levels
+}
+
+/// Mip level played by a key
+pub fn mip_level_for_key(key: usize) -> usize {
+    let limit = octave_limit(key.min(NUM_KEYS - 1) / 12);
+    mip_levels().iter().position(|&l| l == limit).unwrap_or(0)
+}
+
+/// Frame length for a level holding harmonics up to `max_harmonic`
+pub fn frame_size(max_harmonic: usize) -> usize {
+    (max_harmonic * POINTS_PER_HARMONIC_CYCLE).next_power_of_two().max(MIN_FRAME_SIZE)
+}
+
+/// Render the frames of one mip level. Returns `None` if cancelled.
+pub fn render_mip_level(
+    snapshot: &Arc<RenderSnapshot>,
+    level: usize,
+    is_cancelled: &dyn Fn() -> bool,
+) -> Option<MipTable> {
+    let max_harmonic = mip_levels()[level];
+    let size = frame_size(max_harmonic);
+    let sums = snapshot.render(size, max_harmonic, 0..snapshot.num_buckets(), is_cancelled)?;
+    Some(MipTable {
+        snapshot: snapshot.clone(),
+        table: Arc::new(Wavetable::new(snapshot.finish(size, &sums), size)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::RenderMethod;
+    use crate::voice::KeySound;
+
+    #[test]
+    fn test_mip_levels_cover_all_keys() {
+        let levels = mip_levels();
+        assert!(levels.len() > 1 && levels.len() <= NUM_KEYS.div_ceil(12));
+        // Limits fall towards the high keys and never exceed what a key can play
+        assert!(levels.windows(2).all(|w| w[0] > w[1]));
+        for key in 0..NUM_KEYS {
+            let level = mip_level_for_key(key);
+            assert!(levels[level] <= max_harmonic_for_key(key));
+        }
+        assert_eq!(mip_level_for_key(0), 0);
+        assert_eq!(mip_level_for_key(NUM_KEYS - 1), levels.len() - 1);
+    }
+
+    #[test]
+    fn test_frame_size() {
+        assert_eq!(frame_size(64), 2048);
+        assert_eq!(frame_size(5), 256);
+        assert_eq!(frame_size(1), MIN_FRAME_SIZE);
+    }
+
+    #[test]
+    fn test_wavetable_matches_key_buffer() {
+        let amplitudes: Vec<Vec<f32>> = (0..8).map(|n| vec![0.1 / (n + 1) as f32, 0.05, 0.0]).collect();
+        let phases: Vec<Vec<f32>> = (0..8).map(|n| vec![0.3 * n as f32; 3]).collect();
+        let enabled = vec![true; 8];
+        let snapshot = Arc::new(RenderSnapshot::new(&amplitudes, &phases, &enabled, &enabled, RenderMethod::DirectSum));
+
+        let key = 0;
+        let period = 1604;
+        let mip = render_mip_level(&snapshot, mip_level_for_key(key), &|| false).unwrap();
+        let sound = KeySound::Wavetable { table: mip.table, period };
+
+        let sums = snapshot.render(period, max_harmonic_for_key(key), 0..3, &|| false).unwrap();
+        let buffer = snapshot.finish(period, &sums);
+        assert_eq!(sound.len(), buffer.len());
+        for (idx, expected) in buffer.iter().enumerate() {
+            assert!((sound.sample(idx) - expected).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_render_mip_level_cancelled() {
+        let snapshot = Arc::new(RenderSnapshot::default());
+        let levels = mip_levels();
+        assert!(render_mip_level(&snapshot, levels.len() - 1, &|| false).is_some());
+        let amplitudes = vec![vec![0.5; 2]];
+        let snapshot = Arc::new(RenderSnapshot::new(&amplitudes, &amplitudes, &[true], &[true], RenderMethod::DirectSum));
+        assert!(render_mip_level(&snapshot, 0, &|| true).is_none());
+    }
+}
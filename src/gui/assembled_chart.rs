@@ -13,66 +13,230 @@
 // limitations under the License.
 
 use std::sync::Arc;
-use egui_plot::{Line, Plot, PlotBounds, PlotPoints};
-use crate::engine::SynthComputeEngine;
+use nih_plug_egui::egui::{self, Color32};
+use egui_plot::{Legend, Line, LineStyle, Plot, PlotBounds, PlotPoints, VLine};
+use crate::constants::{key_name, NUM_KEYS};
+use crate::engine::{LockRecover, SynthComputeEngine};
 
-pub fn draw_assembled_chart(ui: &mut nih_plug_egui::egui::Ui, synth_compute_engine: &Arc<SynthComputeEngine>) {
-    // Check if we should reset the view to default range (0-2000)
-    let should_reset_view = synth_compute_engine
-        .shared_params
+// Number of buckets shown after an edit or when the chart is first opened
+const DEFAULT_VIEW_BUCKETS: usize = 4;
+// Above this many visible samples the traces are decimated to a min/max envelope
+const MAX_PLOTTED_POINTS: usize = 4000;
+// Bucket boundaries are only drawn when zoomed in far enough for them to be readable
+const MAX_BOUNDARY_LINES: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ViewRequest {
+    SinglePeriod,
+    FirstBuckets,
+    WholeBuffer,
+}
+
+// Key periods follow the host's sample rate, so the time axis has to as well
+fn samples_to_ms(samples: f64, sample_rate: f64) -> f64 {
+    samples * 1000.0 / sample_rate
+}
+
+fn ms_to_samples(ms: f64, sample_rate: f64) -> f64 {
+    ms * sample_rate / 1000.0
+}
+
+fn key_label(key: usize) -> String {
+    format!("{} (key {})", key_name(key), key)
+}
+
+/// Points of the visible part of a trace, reduced to a min/max envelope when zoomed out
+fn visible_points(samples: &[f32], x_min_ms: f64, x_max_ms: f64, sample_rate: f64) -> PlotPoints {
+    let lo = (ms_to_samples(x_min_ms, sample_rate).floor().max(0.0) as usize).min(samples.len());
+    let hi = (ms_to_samples(x_max_ms, sample_rate).ceil().max(0.0) as usize + 1).min(samples.len());
+    if hi <= lo {
+        return PlotPoints::default();
+    }
+
+    let count = hi - lo;
+    if count <= MAX_PLOTTED_POINTS {
+        return (lo..hi)
+            .map(|i| [samples_to_ms(i as f64, sample_rate), samples[i] as f64])
+            .collect();
+    }
+
+    let stride = count.div_ceil(MAX_PLOTTED_POINTS / 2);
+    samples[lo..hi]
+        .chunks(stride)
+        .enumerate()
+        .flat_map(|(chunk_idx, chunk)| {
+            let start = (lo + chunk_idx * stride) as f64;
+            let (min, max) = chunk
+                .iter()
+                .fold((f32::INFINITY, f32::NEG_INFINITY), |(mn, mx), &s| (mn.min(s), mx.max(s)));
+            [
+                [samples_to_ms(start, sample_rate), min as f64],
+                [samples_to_ms(start + chunk.len() as f64 / 2.0, sample_rate), max as f64],
+            ]
+        })
+        .collect()
+}
+
+fn key_combo(ui: &mut egui::Ui, id: &str, label: &str, none_label: &str, selected: Option<usize>) -> Option<Option<usize>> {
+    let mut changed = None;
+    ui.label(label);
+    egui::ComboBox::from_id_salt(id)
+        .selected_text(selected.map(key_label).unwrap_or_else(|| none_label.to_string()))
+        .show_ui(ui, |ui| {
+            if ui.selectable_label(selected.is_none(), none_label).clicked() {
+                changed = Some(None);
+            }
+            for key in 0..NUM_KEYS {
+                if ui.selectable_label(selected == Some(key), key_label(key)).clicked() {
+                    changed = Some(Some(key));
+                }
+            }
+        });
+    changed
+}
+
+pub fn draw_assembled_chart(ui: &mut egui::Ui, synth_compute_engine: &Arc<SynthComputeEngine>) {
+    let shared = &synth_compute_engine.shared_params;
+
+    // Follow the last played key or a changed selection; rendering happens in the background
+    if synth_compute_engine.preview_is_stale() {
+        synth_compute_engine.update_assembled_chart_preview();
+    }
+    let preview_pending = synth_compute_engine.preview_pending();
+
+    // Start out showing the first few buckets, like after an edit
+    let initialized_id = egui::Id::new("assembled_chart_initialized");
+    let initialized = ui
+        .ctx()
+        .memory(|mem| mem.data.get_temp::<bool>(initialized_id).unwrap_or(false));
+    let mut view_request = (!initialized).then_some(ViewRequest::FirstBuckets);
+
+    // Chart controls
+    let selected_preview = *shared.preview_key.lock_recover();
+    let selected_compare = *shared.compare_key.lock_recover();
+    ui.horizontal(|ui| {
+        if let Some(key) = key_combo(ui, "preview_key_combo", "Preview key:", "Follow last played", selected_preview) {
+            synth_compute_engine.set_preview_key(key);
+        }
+        ui.add_space(10.0);
+        if let Some(key) = key_combo(ui, "compare_key_combo", "Compare with:", "None", selected_compare) {
+            synth_compute_engine.set_compare_key(key);
+        }
+        ui.add_space(10.0);
+        if ui.button("Single period").clicked() {
+            view_request = Some(ViewRequest::SinglePeriod);
+        }
+        if ui.button("First buckets").clicked() {
+            view_request = Some(ViewRequest::FirstBuckets);
+        }
+        if ui.button("Whole buffer").clicked() {
+            view_request = Some(ViewRequest::WholeBuffer);
+        }
+        if preview_pending {
+            ui.add_space(10.0);
+            ui.spinner();
+            ui.label("Rendering preview");
+        }
+    });
+
+    if preview_pending {
+        // Pick up the finished render without waiting for user input
+        ui.ctx().request_repaint();
+    }
+
+    // Reset the view to the first few buckets when parameters are edited
+    let should_reset_view = shared
         .should_reset_chart_view
-        .load(std::sync::atomic::Ordering::Relaxed);
+        .swap(false, std::sync::atomic::Ordering::Relaxed);
+    if should_reset_view {
+        view_request = Some(ViewRequest::FirstBuckets);
+    }
+
     // Use responsive dimensions based on available space
     let chart_width = ui.available_width() - 10.0;
     let chart_height = ui.available_height() - 10.0;
 
-    Plot::new("Assembled Sound Plot")
+    let has_trace = Plot::new("Assembled Sound Plot")
         .height(chart_height.max(100.0))
         .width(chart_width.max(200.0))
         .include_y(-1.0)
         .include_y(1.0)
-        .include_x(1.0)
-        .auto_bounds([true, false])
+        .include_x(0.0)
+        .auto_bounds([false, false])
         .allow_zoom([true, false])
         .allow_scroll([true, false])
         .allow_drag([true, false])
         .allow_boxed_zoom(false)
+        .x_axis_label("Time (ms)")
+        .legend(Legend::default())
         .show(ui, |plot_ui| {
-            let assembled = synth_compute_engine
-                .shared_params
-                .assembled_sound_plotted
-                .lock()
-                .unwrap();
-
-            let points: PlotPoints = assembled
-                .iter()
-                .enumerate()
-                .map(|(i, &sample)| [i as f64, sample as f64])
-                .collect();
+            let trace = shared.assembled_sound_plotted.lock_recover();
+            let compare = shared.assembled_sound_compare.lock_recover();
+            let Some(trace) = trace.as_ref() else {
+                return false;
+            };
+            let period = shared.piano_periods.lock_recover()[trace.key] as f64;
+            let sample_rate = synth_compute_engine.sample_rate() as f64;
+            let longest = trace
+                .samples
+                .len()
+                .max(compare.as_ref().map(|c| c.samples.len()).unwrap_or(0)) as f64;
 
             let bounds = plot_ui.plot_bounds();
             let x_min = bounds.min()[0];
             let x_max = bounds.max()[0];
-            let y_min = bounds.min()[1];
-            let y_max = bounds.max()[1];
 
-            // Reset view to default range (0-2000) when parameters are edited
-            if should_reset_view {
-                let default_bounds = PlotBounds::from_min_max([0.0, -1.0], [2000.0, 1.0]);
-                plot_ui.set_plot_bounds(default_bounds);
+            if let Some(request) = view_request {
+                let (start, end) = match request {
+                    ViewRequest::SinglePeriod => {
+                        // Snap to the bucket at the left edge of the current view
+                        let bucket = (ms_to_samples(x_min, sample_rate).max(0.0) / period).floor();
+                        (bucket * period, (bucket + 1.0) * period)
+                    }
+                    ViewRequest::FirstBuckets => (0.0, DEFAULT_VIEW_BUCKETS as f64 * period),
+                    ViewRequest::WholeBuffer => (0.0, longest.max(1.0)),
+                };
+                plot_ui.set_plot_bounds(PlotBounds::from_min_max(
+                    [samples_to_ms(start, sample_rate), -1.0],
+                    [samples_to_ms(end, sample_rate), 1.0],
+                ));
             } else if x_min < 0.0 {
-                let clamped_bounds = PlotBounds::from_min_max([0.0, y_min], [x_max, y_max]);
+                let clamped_bounds = PlotBounds::from_min_max([0.0, -1.0], [x_max, 1.0]);
                 plot_ui.set_plot_bounds(clamped_bounds);
             }
 
-            plot_ui.line(Line::new(points).name("Sound"));
-        });
-        
-    // Clear the reset flag after use
-    if should_reset_view {
-        synth_compute_engine
-            .shared_params
-            .should_reset_chart_view
-            .store(false, std::sync::atomic::Ordering::Relaxed);
+            // Mark where each bucket of the preview key starts
+            let first_boundary = (ms_to_samples(x_min, sample_rate).max(0.0) / period).ceil() as usize;
+            let last_boundary = (ms_to_samples(x_max, sample_rate).min(trace.samples.len() as f64) / period).floor() as usize;
+            if last_boundary >= first_boundary && last_boundary - first_boundary < MAX_BOUNDARY_LINES {
+                for bucket in first_boundary..=last_boundary {
+                    plot_ui.vline(
+                        VLine::new(samples_to_ms(bucket as f64 * period, sample_rate))
+                            .color(Color32::from_gray(90))
+                            .style(LineStyle::dashed_dense())
+                            .name("Bucket boundaries"),
+                    );
+                }
+            }
+
+            plot_ui.line(Line::new(visible_points(&trace.samples, x_min, x_max, sample_rate)).name(key_label(trace.key)));
+
+            if let Some(compare) = compare.as_ref() {
+                plot_ui.line(
+                    Line::new(visible_points(&compare.samples, x_min, x_max, sample_rate))
+                        .color(Color32::from_rgb(230, 150, 60))
+                        .name(key_label(compare.key)),
+                );
+            }
+            true
+        })
+        .inner;
+
+    if has_trace {
+        if view_request.is_some() {
+            // Points are built from the previous frame's bounds, so draw once more after a view change
+            ui.ctx().request_repaint();
+        }
+        ui.ctx().memory_mut(|mem| mem.data.insert_temp(initialized_id, true));
     }
-}
\ No newline at end of file
+}
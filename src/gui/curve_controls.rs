@@ -12,11 +12,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use nih_plug::prelude::ParamSetter;
 use crate::constants::*;
-use crate::engine::{ChartType, SynthComputeEngine};
+use crate::engine::{ChartType, LockRecover, RwLockRecover, SynthComputeEngine};
 use crate::params::{CurveType, GranularityLevel, HarmonicParam};
+use crate::patch::HarmonicCurves;
 
 pub fn draw_curve_controls(
     ui: &mut nih_plug_egui::egui::Ui,
@@ -24,6 +25,7 @@ pub fn draw_curve_controls(
     chart_type: ChartType,
     harmonic: &HarmonicParam,
     synth_compute_engine: Arc<SynthComputeEngine>,
+    custom_curves: &RwLock<Option<HarmonicCurves>>,
     setter: &ParamSetter,
     params_changed_action: &dyn Fn(),
     offset_min: f64,
@@ -74,8 +76,10 @@ pub fn draw_curve_controls(
             let response = cols[0].add(slider);
             if response.drag_stopped() {
                 match curve.value() {
-                    CurveType::Sine => engine.fill_sin_curve(idx, chart_type_clone.clone()),
+                    CurveType::Sine => engine.fill_sin_curve(idx, harmonic.sine_curve(chart_type_clone.clone()), chart_type_clone.clone()),
                     CurveType::Constant => engine.fill_constant_curve(idx, offset.value(), chart_type_clone.clone()),
+                    // Imported curves have no offset
+                    CurveType::Custom => {}
                 }
                 params_changed_action();
             }
@@ -106,7 +110,7 @@ pub fn draw_curve_controls(
             let response = cols[1].add(slider);
             if response.drag_stopped() {
                 if curve.value() == CurveType::Sine {
-                    engine.fill_sin_curve(idx, chart_type_clone.clone());
+                    engine.fill_sin_curve(idx, harmonic.sine_curve(chart_type_clone.clone()), chart_type_clone.clone());
                 }
                 params_changed_action();
             }
@@ -132,7 +136,7 @@ pub fn draw_curve_controls(
             let response = cols[2].add(slider);
             if response.drag_stopped() {
                 if curve.value() == CurveType::Sine {
-                    engine.fill_sin_curve(idx, chart_type_clone.clone());
+                    engine.fill_sin_curve(idx, harmonic.sine_curve(chart_type_clone.clone()), chart_type_clone.clone());
                 }
                 params_changed_action();
             }
@@ -157,7 +161,7 @@ pub fn draw_curve_controls(
                             setter.end_set_parameter(curve);
                             match variant {
                                 CurveType::Sine => {
-                                    synth_compute_engine.fill_sin_curve(idx, chart_type.clone());
+                                    synth_compute_engine.fill_sin_curve(idx, harmonic.sine_curve(chart_type.clone()), chart_type.clone());
                                 }
                                 CurveType::Constant => {
                                     let offset_value = match chart_type {
@@ -166,6 +170,11 @@ pub fn draw_curve_controls(
                                     };
                                     synth_compute_engine.fill_constant_curve(idx, offset_value, chart_type.clone());
                                 }
+                                CurveType::Custom => {
+                                    if let Some(curves) = custom_curves.read_recover().as_ref() {
+                                        synth_compute_engine.load_curves(curves, |n, chart| n == idx && chart == chart_type);
+                                    }
+                                }
                             }
                             params_changed_action();
                         }
@@ -181,13 +190,11 @@ pub fn draw_curve_controls(
                     ChartType::Amp => synth_compute_engine
                         .shared_params
                         .harmonic_ampl_enabled
-                        .lock()
-                        .unwrap(),
+                        .lock_recover(),
                     ChartType::Phase => synth_compute_engine
                         .shared_params
                         .harmonic_phase_enabled
-                        .lock()
-                        .unwrap(),
+                        .lock_recover(),
                 };
                 let checkbox = ui.checkbox(&mut enabled[idx], "Enabled");
                 if checkbox.changed() {
@@ -202,7 +209,7 @@ pub fn draw_curve_controls(
                 // Mark all buffers as dirty since enabled state affects audio generation
                 synth_compute_engine.shared_params.mark_all_buffers_dirty();
                 // Update assembled chart immediately to reflect the enable state change
-                synth_compute_engine.update_assembled_chart_with_key24();
+                synth_compute_engine.update_assembled_chart_preview();
                 params_changed_action();
             }
 
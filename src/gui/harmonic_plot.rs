@@ -16,7 +16,7 @@ use std::sync::Arc;
 use nih_plug_egui::egui::{Align2, Color32, RichText};
 use egui_plot::{Line, Plot, PlotPoints, Text};
 use crate::constants::{LABEL_FONT_SIZE, TWO_PI};
-use crate::engine::{ChartType, SynthComputeEngine};
+use crate::engine::{ChartType, LockRecover, SynthComputeEngine};
 
 pub fn draw_harmonic_plot(
     ui: &mut nih_plug_egui::egui::Ui,
@@ -53,25 +53,21 @@ pub fn draw_harmonic_plot(
                     synth_compute_engine
                         .shared_params
                         .amplitude_data
-                        .lock()
-                        .unwrap(),
+                        .lock_recover(),
                     synth_compute_engine
                         .shared_params
                         .harmonic_ampl_enabled
-                        .lock()
-                        .unwrap(),
+                        .lock_recover(),
                 ),
                 ChartType::Phase => (
                     synth_compute_engine
                         .shared_params
                         .phase_data
-                        .lock()
-                        .unwrap(),
+                        .lock_recover(),
                     synth_compute_engine
                         .shared_params
                         .harmonic_phase_enabled
-                        .lock()
-                        .unwrap(),
+                        .lock_recover(),
                 ),
             };
 
@@ -0,0 +1,109 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::Path;
+use nih_plug_egui::egui::{self, TextureHandle, TextureOptions};
+use crate::engine::ChartType;
+use crate::gui::spectrogram::curves_image;
+use crate::io::image::{import_image, ImageOptions};
+use crate::patch::HarmonicCurves;
+
+const PREVIEW_SIZE: egui::Vec2 = egui::vec2(256.0, 128.0);
+
+// Curves loaded from an image, previewed until they are applied or discarded
+#[derive(Clone)]
+struct PendingImport {
+    curves: HarmonicCurves,
+    options: ImageOptions,
+    amplitude_texture: TextureHandle,
+    phase_texture: TextureHandle,
+}
+
+/// Row loading a spectral image, followed by a preview of the curves it makes. Returns the
+/// curves and the options they were made with when the user applies them.
+pub fn draw_image_import(ui: &mut egui::Ui, num_buckets: usize) -> Option<(HarmonicCurves, ImageOptions)> {
+    let path_id = egui::Id::new("image_import_path");
+    let options_id = egui::Id::new("image_import_options");
+    let pending_id = egui::Id::new("image_import_pending");
+    let error_id = egui::Id::new("image_import_error");
+
+    ui.horizontal(|ui| {
+        let mut path = ui.memory(|mem| mem.data.get_temp::<String>(path_id)).unwrap_or_default();
+        let mut options = ui.memory(|mem| mem.data.get_temp::<ImageOptions>(options_id)).unwrap_or_default();
+
+        ui.label("Spectral image");
+        ui.add(
+            egui::TextEdit::singleline(&mut path)
+                .hint_text("PNG, columns are buckets, rows harmonics")
+                .desired_width(300.0),
+        );
+        ui.checkbox(&mut options.hue_to_phase, "Hue as phase");
+        if ui.button("Load").clicked() {
+            match import_image(Path::new(path.trim()), num_buckets, &options) {
+                Ok(curves) => {
+                    let texture = |chart_type: ChartType, rows: &[Vec<f32>]| {
+                        let name = format!("image_import_{:?}", chart_type);
+                        ui.ctx().load_texture(name, curves_image(rows, &chart_type), TextureOptions::NEAREST)
+                    };
+                    let pending = PendingImport {
+                        amplitude_texture: texture(ChartType::Amp, &curves.amplitudes),
+                        phase_texture: texture(ChartType::Phase, &curves.phases),
+                        curves,
+                        options,
+                    };
+                    ui.memory_mut(|mem| {
+                        mem.data.insert_temp(pending_id, pending);
+                        mem.data.remove::<String>(error_id);
+                    });
+                }
+                Err(err) => ui.memory_mut(|mem| {
+                    mem.data.insert_temp(error_id, err.to_string());
+                    mem.data.remove::<PendingImport>(pending_id);
+                }),
+            }
+        }
+        if let Some(message) = ui.memory(|mem| mem.data.get_temp::<String>(error_id)) {
+            ui.colored_label(egui::Color32::from_rgb(220, 60, 60), message);
+        }
+        ui.memory_mut(|mem| {
+            mem.data.insert_temp(path_id, path);
+            mem.data.insert_temp(options_id, options);
+        });
+    });
+
+    let pending = ui.memory(|mem| mem.data.get_temp::<PendingImport>(pending_id))?;
+    let mut applied = None;
+    ui.horizontal(|ui| {
+        ui.vertical(|ui| {
+            ui.label("Amplitude");
+            ui.image((pending.amplitude_texture.id(), PREVIEW_SIZE));
+        });
+        // Without the hue the phases stay as they are
+        if pending.options.hue_to_phase {
+            ui.vertical(|ui| {
+                ui.label("Phase");
+                ui.image((pending.phase_texture.id(), PREVIEW_SIZE));
+            });
+        }
+        ui.vertical(|ui| {
+            if ui.button("Apply").clicked() {
+                applied = Some((pending.curves.clone(), pending.options));
+            }
+            if ui.button("Discard").clicked() || applied.is_some() {
+                ui.memory_mut(|mem| mem.data.remove::<PendingImport>(pending_id));
+            }
+        });
+    });
+    applied
+}
@@ -16,8 +16,14 @@ pub mod piano_keyboard;
 pub mod harmonic_plot;
 pub mod assembled_chart;
 pub mod curve_controls;
+pub mod spectrogram;
+pub mod image_import;
+pub mod output_scope;
 
 pub use piano_keyboard::draw_piano_keyboard;
 pub use harmonic_plot::draw_harmonic_plot;
 pub use assembled_chart::draw_assembled_chart;
-pub use curve_controls::draw_curve_controls;
\ No newline at end of file
+pub use curve_controls::draw_curve_controls;
+pub use spectrogram::{draw_spectrogram, HarmonicView};
+pub use image_import::draw_image_import;
+pub use output_scope::draw_output_scope;
\ No newline at end of file
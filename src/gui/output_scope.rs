@@ -0,0 +1,143 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+use nih_plug_egui::egui::{self, Color32};
+use egui_plot::{Line, Plot, PlotPoints};
+use crate::engine::output_tap::find_trigger;
+use crate::engine::spectrum::{apply_peak_hold, SPECTRUM_FLOOR_DB};
+use crate::engine::{SpectrumAnalyzer, SynthComputeEngine};
+
+const FFT_SIZE: usize = 4096;
+const SCOPE_WINDOW: usize = 1024;
+const PEAK_DECAY_DB: f32 = 0.3;
+const MIN_DISPLAY_FREQ: f32 = 20.0;
+
+// Analyzer state kept in egui memory between frames
+#[derive(Clone)]
+struct AnalyzerState {
+    analyzer: SpectrumAnalyzer,
+    peaks: Vec<f32>,
+    peak_hold: bool,
+}
+
+fn format_frequency(freq: f64) -> String {
+    if freq >= 1000.0 {
+        format!("{:.1}k", freq / 1000.0)
+    } else {
+        format!("{:.0}", freq)
+    }
+}
+
+pub fn draw_output_scope(ui: &mut egui::Ui, synth_compute_engine: &Arc<SynthComputeEngine>) {
+    let tap = &synth_compute_engine.shared_params.output_tap;
+    let sample_rate = tap.sample_rate();
+    let history = tap.snapshot(FFT_SIZE);
+
+    let state_id = egui::Id::new("output_analyzer_state");
+    let mut state = ui
+        .ctx()
+        .memory(|mem| mem.data.get_temp::<AnalyzerState>(state_id))
+        .unwrap_or_else(|| AnalyzerState {
+            analyzer: SpectrumAnalyzer::new(FFT_SIZE),
+            peaks: Vec::new(),
+            peak_hold: true,
+        });
+
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut state.peak_hold, "Peak hold");
+        if ui.button("Reset peaks").clicked() {
+            state.peaks.clear();
+        }
+    });
+
+    let spectrum = state.analyzer.magnitude_db(&history);
+    if state.peak_hold {
+        apply_peak_hold(&mut state.peaks, &spectrum, PEAK_DECAY_DB);
+    } else {
+        state.peaks.clear();
+    }
+
+    let chart_w = (ui.available_width() - 10.0) * 0.5;
+    let chart_h = 160.0;
+
+    ui.columns(2, |columns| {
+        // Oscilloscope triggered on a rising zero crossing so periodic sounds stand still
+        let trigger = find_trigger(&history, history.len() - SCOPE_WINDOW);
+        let scope_points: PlotPoints = history[trigger..trigger + SCOPE_WINDOW]
+            .iter()
+            .enumerate()
+            .map(|(i, &sample)| [i as f64 * 1000.0 / sample_rate as f64, sample as f64])
+            .collect();
+
+        columns[0].label("Oscilloscope (ms)");
+        Plot::new("Output Oscilloscope")
+            .height(chart_h)
+            .width(chart_w.max(200.0))
+            .include_y(-1.0)
+            .include_y(1.0)
+            .allow_zoom([false, false])
+            .allow_scroll([false, false])
+            .allow_drag([false, false])
+            .show(&mut columns[0], |plot_ui| {
+                plot_ui.line(Line::new(scope_points).name("Output"));
+            });
+
+        // Spectrum on a log-frequency axis: x holds log10(Hz)
+        let to_points = |values: &[f32]| -> PlotPoints {
+            values
+                .iter()
+                .enumerate()
+                .skip(1)
+                .filter_map(|(bin, &db)| {
+                    let freq = state.analyzer.bin_frequency(bin, sample_rate);
+                    (freq >= MIN_DISPLAY_FREQ).then(|| [(freq as f64).log10(), db as f64])
+                })
+                .collect()
+        };
+        let spectrum_points = to_points(&spectrum);
+        let peak_points = (!state.peaks.is_empty()).then(|| to_points(&state.peaks));
+
+        columns[1].label("Spectrum (dBFS)");
+        Plot::new("Output Spectrum")
+            .height(chart_h)
+            .width(chart_w.max(200.0))
+            .include_x((MIN_DISPLAY_FREQ as f64).log10())
+            .include_x((sample_rate as f64 / 2.0).log10())
+            .include_y(SPECTRUM_FLOOR_DB as f64 + 20.0)
+            .include_y(0.0)
+            .allow_zoom([false, false])
+            .allow_scroll([false, false])
+            .allow_drag([false, false])
+            .x_axis_formatter(|mark, _range| format_frequency(10f64.powf(mark.value)))
+            .label_formatter(|_name, point| {
+                format!("{} Hz\n{:.1} dB", format_frequency(10f64.powf(point.x)), point.y)
+            })
+            .show(&mut columns[1], |plot_ui| {
+                if let Some(peak_points) = peak_points {
+                    plot_ui.line(
+                        Line::new(peak_points)
+                            .color(Color32::from_gray(140))
+                            .name("Peak"),
+                    );
+                }
+                plot_ui.line(Line::new(spectrum_points).name("Spectrum"));
+            });
+    });
+
+    ui.ctx().memory_mut(|mem| mem.data.insert_temp(state_id, state));
+
+    // The analyzer follows live output, so keep repainting while it is visible
+    ui.ctx().request_repaint();
+}
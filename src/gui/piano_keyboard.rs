@@ -17,7 +17,6 @@ use nih_plug_egui::egui::{Color32, CornerRadius, StrokeKind, Stroke, Vec2, Rect,
 use crate::constants::NUM_KEYS;
 use crate::engine::SynthComputeEngine;
 use crate::engine::shared_params::BufferState;
-use crate::voice::Voice;
 
 fn is_black_key(key_index: usize) -> bool {
     let octave_pos = key_index % 12;
@@ -76,37 +75,41 @@ pub fn draw_piano_keyboard(
 
     // Check if any voice is currently active for visual feedback
     let active_voices = {
-        let shared = &synth_compute_engine.shared_params;
-        let voices = shared.voices.lock().unwrap();
-        (0..NUM_KEYS).filter(|&i| voices[i].is_some()).collect::<Vec<_>>()
+        let activity = &synth_compute_engine.shared_params.voice_activity;
+        (0..NUM_KEYS).filter(|&i| activity.is_active(i)).collect::<Vec<_>>()
     };
+    if !active_voices.is_empty() {
+        // Voices end on the audio thread, so keep polling until they have faded out
+        egui_ctx.request_repaint();
+    }
 
     // Get buffer states for visual feedback
-    let buffer_states = {
-        let shared = &synth_compute_engine.shared_params;
-        let states = shared.buffer_states.lock().unwrap();
-        states.clone()
-    };
+    let buffer_states = synth_compute_engine.shared_params.job_queue.buffer_states();
 
     // Determine overall computation status
     let (computing_count, dirty_count) = buffer_states.iter().fold((0, 0), |(computing, dirty), state| {
         match state {
             BufferState::Computing => (computing + 1, dirty),
             BufferState::Dirty => (computing, dirty + 1),
-            BufferState::Clean => (computing, dirty),
+            BufferState::Clean | BufferState::Evicted | BufferState::Failed => (computing, dirty),
         }
     });
+    let failed_count = buffer_states.iter().filter(|&&state| state == BufferState::Failed).count();
 
     let status_text = if computing_count > 0 {
         format!("Recomputing the final sound ({} keys remaining)", computing_count + dirty_count)
     } else if dirty_count > 0 {
         format!("Recomputing the final sound ({} keys pending)", dirty_count)
+    } else if failed_count > 0 {
+        format!("Rendering failed for {} keys", failed_count)
     } else {
         "Synthesis finished".to_string()
     };
 
     let status_color = if computing_count > 0 || dirty_count > 0 {
         Color32::from_rgb(200, 100, 50) // Orange for computing/pending
+    } else if failed_count > 0 {
+        Color32::from_rgb(200, 50, 50) // Red for failed
     } else {
         Color32::from_rgb(50, 150, 50) // Green for finished
     };
@@ -147,6 +150,8 @@ pub fn draw_piano_keyboard(
                 BufferState::Clean => Color32::WHITE, // Normal - buffer ready
                 BufferState::Dirty => Color32::from_rgb(230, 230, 230), // Light shadow - needs recomputation
                 BufferState::Computing => Color32::from_rgb(255, 255, 200), // Light yellow - currently computing
+                BufferState::Evicted => Color32::from_rgb(238, 238, 248), // Faint blue - freed, rendered again when played
+                BufferState::Failed => Color32::from_rgb(255, 210, 210), // Light red - rendering panicked
             }
         };
 
@@ -200,6 +205,8 @@ pub fn draw_piano_keyboard(
                 BufferState::Clean => Color32::from_rgb(30, 30, 30), // Normal - buffer ready
                 BufferState::Dirty => Color32::from_rgb(60, 60, 60), // Lighter shadow - needs recomputation
                 BufferState::Computing => Color32::from_rgb(80, 80, 40), // Darker yellow - currently computing
+                BufferState::Evicted => Color32::from_rgb(45, 45, 60), // Bluish - freed, rendered again when played
+                BufferState::Failed => Color32::from_rgb(90, 30, 30), // Reddish - rendering panicked
             }
         };
 
@@ -273,20 +280,7 @@ pub fn draw_piano_keyboard(
     if let Some(key_idx) = pressed_this_frame.or(keyboard_pressed_key) {
         if Some(key_idx) != last_pressed_key {
             log::debug!("Key {} clicked", key_idx);
-            {
-                let shared = &synth_compute_engine.shared_params;
-                let buf = synth_compute_engine.get_buffer_for_key(key_idx);
-                let mut voices = shared.voices.lock().unwrap();
-                voices[key_idx] = Some(Voice {
-                    buffer: buf,
-                    idx: 0,
-                    fade_in_active: true,
-                    fade_in_pos: 0,
-                    fade_out_active: false,
-                    fade_out_pos: 0,
-                });
-            }
-            synth_compute_engine.update_plotted_mix();
+            synth_compute_engine.note_on(key_idx);
             last_pressed_key = Some(key_idx);
             last_pressed_key_persist = Some(key_idx);
         }
@@ -299,16 +293,8 @@ pub fn draw_piano_keyboard(
         
         if let Some(prev_key) = release_key {
             log::debug!("Key {} released", prev_key);
-            {
-                let shared = &synth_compute_engine.shared_params;
-                let mut voices = shared.voices.lock().unwrap();
-                if let Some(v) = voices[prev_key].as_mut() {
-                    v.fade_out_active = true;
-                    v.fade_out_pos = 0;
-                }
-            }
+            synth_compute_engine.note_off(prev_key);
 
-            synth_compute_engine.update_plotted_mix();
             last_pressed_key = None;
         }
     }
@@ -0,0 +1,245 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use nih_plug_egui::egui::{self, ecolor::Hsva, Color32, ColorImage, TextureHandle, TextureOptions};
+use egui_plot::{Plot, PlotImage, PlotPoint, VLine};
+use crate::constants::TWO_PI;
+use crate::engine::{ChartType, LockRecover, SynthComputeEngine};
+
+// Lowest level shown by the dB amplitude scale; quieter values render as black
+const DB_FLOOR: f32 = -60.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum HarmonicView {
+    #[default]
+    Lines,
+    Heatmap,
+}
+
+impl HarmonicView {
+    pub const VARIANTS: [HarmonicView; 2] = [HarmonicView::Lines, HarmonicView::Heatmap];
+}
+
+// Texture uploaded for the heatmap together with a fingerprint of the data it was built from,
+// so the image is only rebuilt when the curves, enabled flags or scale actually change
+#[derive(Clone)]
+struct SpectrogramTexture {
+    fingerprint: u64,
+    texture: TextureHandle,
+}
+
+/// Map a normalized value in 0..=1 onto a dark-to-bright heat palette
+fn heat_color(value: f32) -> Color32 {
+    let v = value.clamp(0.0, 1.0);
+    let r = (v * 3.0).min(1.0);
+    let g = (v * 3.0 - 1.0).clamp(0.0, 1.0);
+    let b = (v * 3.0 - 2.0).clamp(0.0, 1.0);
+    Color32::from_rgb((r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8)
+}
+
+/// Convert a linear amplitude into the 0..=1 range used by the palette
+fn amplitude_level(amp: f32, db_scale: bool) -> f32 {
+    if db_scale {
+        if amp <= 0.0 {
+            return 0.0;
+        }
+        let db = 20.0 * amp.log10();
+        ((db - DB_FLOOR) / -DB_FLOOR).clamp(0.0, 1.0)
+    } else {
+        amp.clamp(0.0, 1.0)
+    }
+}
+
+fn cell_color(chart_type: &ChartType, value: f32, enabled: bool, db_scale: bool) -> Color32 {
+    if !enabled {
+        return Color32::from_gray(20);
+    }
+    match chart_type {
+        ChartType::Amp => heat_color(amplitude_level(value, db_scale)),
+        // Phase is cyclic, so it is shown on the hue wheel instead of a linear palette
+        ChartType::Phase => Color32::from(Hsva::new((value / TWO_PI).rem_euclid(1.0), 0.85, 0.9, 1.0)),
+    }
+}
+
+fn heatmap_image(data: &[Vec<f32>], chart_type: &ChartType, enabled_flags: &[bool], db_scale: bool) -> ColorImage {
+    let num_harmonics = data.len();
+    let num_buckets = data.first().map(|row| row.len()).unwrap_or(0);
+    // Row 0 of the image is the top of the plot, which is the highest harmonic
+    let mut image = ColorImage::new([num_buckets, num_harmonics], Color32::BLACK);
+    for (n, row) in data.iter().enumerate() {
+        let y = num_harmonics - 1 - n;
+        for (bucket, &value) in row.iter().enumerate() {
+            image.pixels[y * num_buckets + bucket] = cell_color(chart_type, value, enabled_flags[n], db_scale);
+        }
+    }
+    image
+}
+
+/// Heatmap of curves that aren't in the engine, e.g. an import waiting to be applied
+pub fn curves_image(data: &[Vec<f32>], chart_type: &ChartType) -> ColorImage {
+    heatmap_image(data, chart_type, &vec![true; data.len()], false)
+}
+
+fn format_cell_value(chart_type: &ChartType, value: f32, db_scale: bool) -> String {
+    match chart_type {
+        ChartType::Amp if db_scale => {
+            if value > 0.0 {
+                format!("{:.1} dB", 20.0 * value.log10())
+            } else {
+                "-inf dB".to_string()
+            }
+        }
+        ChartType::Amp => format!("{:.3}", value),
+        ChartType::Phase => format!("{:.3} rad", value),
+    }
+}
+
+pub fn draw_spectrogram(
+    ui: &mut egui::Ui,
+    title: &str,
+    chart_type: ChartType,
+    chart_w: f32,
+    chart_h: f32,
+    db_scale: bool,
+    synth_compute_engine: &Arc<SynthComputeEngine>,
+) {
+    ui.label(title);
+
+    let (data, enabled_flags) = {
+        let shared = &synth_compute_engine.shared_params;
+        match chart_type {
+            ChartType::Amp => (
+                shared.amplitude_data.lock_recover().clone(),
+                shared.harmonic_ampl_enabled.lock_recover().clone(),
+            ),
+            ChartType::Phase => (
+                shared.phase_data.lock_recover().clone(),
+                shared.harmonic_phase_enabled.lock_recover().clone(),
+            ),
+        }
+    };
+
+    let num_harmonics = data.len();
+    let num_buckets = data.first().map(|row| row.len()).unwrap_or(0);
+    if num_harmonics == 0 || num_buckets == 0 {
+        return;
+    }
+
+    let mut hasher = DefaultHasher::new();
+    db_scale.hash(&mut hasher);
+    enabled_flags.hash(&mut hasher);
+    for row in &data {
+        for value in row {
+            value.to_bits().hash(&mut hasher);
+        }
+    }
+    let fingerprint = hasher.finish();
+
+    let texture_id = egui::Id::new(("spectrogram_texture", format!("{:?}", chart_type)));
+    let cached = ui
+        .ctx()
+        .memory(|mem| mem.data.get_temp::<SpectrogramTexture>(texture_id));
+
+    let texture = match cached {
+        Some(cached) if cached.fingerprint == fingerprint => cached.texture,
+        cached => {
+            let image = heatmap_image(&data, &chart_type, &enabled_flags, db_scale);
+
+            let texture = match cached {
+                Some(mut cached) => {
+                    cached.texture.set(image, TextureOptions::NEAREST);
+                    cached.texture
+                }
+                None => ui.ctx().load_texture(
+                    format!("spectrogram_{:?}", chart_type),
+                    image,
+                    TextureOptions::NEAREST,
+                ),
+            };
+            ui.ctx().memory_mut(|mem| {
+                mem.data.insert_temp(
+                    texture_id,
+                    SpectrogramTexture {
+                        fingerprint,
+                        texture: texture.clone(),
+                    },
+                )
+            });
+            texture
+        }
+    };
+
+    let playback_position = synth_compute_engine.playback_position();
+    if playback_position.is_some() {
+        // Keep the position indicator moving while a voice is sounding
+        ui.ctx().request_repaint();
+    }
+
+    let plot_id = match chart_type {
+        ChartType::Amp => "Amplitude Heatmap",
+        ChartType::Phase => "Phase Heatmap",
+    };
+
+    let hovered = Plot::new(plot_id)
+        .height(chart_h.max(150.0))
+        .width(chart_w.max(300.0))
+        .allow_zoom([false, false])
+        .allow_scroll([false, false])
+        .allow_drag([false, false])
+        .include_x(0.0)
+        .include_x(num_buckets as f64)
+        .include_y(0.5)
+        .include_y(num_harmonics as f64 + 0.5)
+        .show(ui, |plot_ui| {
+            plot_ui.image(PlotImage::new(
+                &texture,
+                PlotPoint::new(num_buckets as f64 / 2.0, (num_harmonics as f64 + 1.0) / 2.0),
+                [num_buckets as f32, num_harmonics as f32],
+            ));
+
+            if let Some(position) = playback_position {
+                plot_ui.vline(
+                    VLine::new(position.rem_euclid(num_buckets as f64))
+                        .color(Color32::WHITE)
+                        .width(1.5)
+                        .name("Playback position"),
+                );
+            }
+
+            plot_ui.pointer_coordinate().and_then(|pointer| {
+                let bucket = pointer.x.floor();
+                let harmonic = pointer.y.round();
+                if bucket < 0.0 || bucket >= num_buckets as f64 || harmonic < 1.0 || harmonic > num_harmonics as f64 {
+                    return None;
+                }
+                Some((harmonic as usize - 1, bucket as usize))
+            })
+        })
+        .inner;
+
+    let readout = match hovered {
+        Some((n, bucket)) => format!(
+            "H{}, bucket {}: {}",
+            n + 1,
+            bucket,
+            format_cell_value(&chart_type, data[n][bucket], db_scale && chart_type == ChartType::Amp)
+        ),
+        None => String::from(" "),
+    };
+    ui.label(readout);
+}
+
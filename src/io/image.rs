@@ -0,0 +1,216 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Spectra painted as PNG images.
+//!
+//! Columns are buckets and rows are harmonics, with the first harmonic at the bottom like in
+//! the editor's heatmap. Brightness is the amplitude and, optionally, hue is the phase.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use crate::constants::{NUM_HARMONICS, TWO_PI};
+use crate::patch::{resample_curve, HarmonicCurves};
+
+// Pixels less saturated than this are grey and have no hue to take a phase from
+const MIN_SATURATION: f32 = 0.05;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ImageOptions {
+    // Take phases from the hue, red being 0 and going round through green and blue
+    pub hue_to_phase: bool,
+}
+
+/// Decoded image, rows from the top, RGBA in 0..=1
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<[f32; 4]>,
+}
+
+impl Image {
+    pub fn pixel(&self, x: usize, y: usize) -> [f32; 4] {
+        self.pixels[y * self.width + x]
+    }
+}
+
+fn invalid(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// Decode a PNG of any colour type and bit depth
+pub fn decode_png(bytes: &[u8]) -> io::Result<Image> {
+    let mut decoder = png::Decoder::new(bytes);
+    // Palettes expanded and 16 bits reduced to 8
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(invalid)?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(invalid)?;
+    let channels = match info.color_type {
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        png::ColorType::Rgb => 3,
+        png::ColorType::Rgba => 4,
+        png::ColorType::Indexed => return Err(invalid("Indexed PNG was not expanded")),
+    };
+    let (width, height) = (info.width as usize, info.height as usize);
+    let pixels = buffer[..info.buffer_size()]
+        .chunks_exact(info.line_size)
+        .flat_map(|line| line[..width * channels].chunks_exact(channels))
+        .map(|pixel| {
+            let value = |i: usize| pixel[i] as f32 / 255.0;
+            match channels {
+                1 => [value(0), value(0), value(0), 1.0],
+                2 => [value(0), value(0), value(0), value(1)],
+                3 => [value(0), value(1), value(2), 1.0],
+                _ => [value(0), value(1), value(2), value(3)],
+            }
+        })
+        .collect();
+    Ok(Image { width, height, pixels })
+}
+
+/// Amplitude from the brightness (HSV value, darkened by transparency) and phase from the hue
+fn pixel_curves(pixel: [f32; 4]) -> (f32, f32) {
+    let [r, g, b, alpha] = pixel;
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let chroma = max - min;
+    if max == 0.0 || chroma / max < MIN_SATURATION {
+        return (max * alpha, 0.0);
+    }
+    let sector = if max == r {
+        (g - b) / chroma
+    } else if max == g {
+        (b - r) / chroma + 2.0
+    } else {
+        (r - g) / chroma + 4.0
+    };
+    (max * alpha, (sector / 6.0).rem_euclid(1.0) * TWO_PI)
+}
+
+/// Curves of the image resampled to the harmonics and `num_buckets`. Without
+/// `hue_to_phase` the phases are all zero.
+pub fn image_curves(image: &Image, num_buckets: usize, options: &ImageOptions) -> HarmonicCurves {
+    let mut amplitudes = vec![Vec::new(); image.height];
+    let mut phases = vec![Vec::new(); image.height];
+    // The bottom row is the first harmonic
+    for (row, y) in (0..image.height).rev().enumerate() {
+        let (row_amplitudes, row_phases): (Vec<f32>, Vec<f32>) =
+            (0..image.width).map(|x| pixel_curves(image.pixel(x, y))).unzip();
+        amplitudes[row] = resample_curve(&row_amplitudes, num_buckets, false);
+        phases[row] = resample_curve(&row_phases, num_buckets, true);
+    }
+
+    // Then each bucket's column to the number of harmonics
+    let mut curves = HarmonicCurves {
+        amplitudes: vec![vec![0.0; num_buckets]; NUM_HARMONICS],
+        phases: vec![vec![0.0; num_buckets]; NUM_HARMONICS],
+    };
+    for bucket in 0..num_buckets {
+        let column: Vec<f32> = amplitudes.iter().map(|row| row[bucket]).collect();
+        for (n, value) in resample_curve(&column, NUM_HARMONICS, false).into_iter().enumerate() {
+            curves.amplitudes[n][bucket] = value.clamp(0.0, 1.0);
+        }
+        if options.hue_to_phase {
+            let column: Vec<f32> = phases.iter().map(|row| row[bucket]).collect();
+            for (n, value) in resample_curve(&column, NUM_HARMONICS, true).into_iter().enumerate() {
+                curves.phases[n][bucket] = value;
+            }
+        }
+    }
+    curves
+}
+
+pub fn import_image(path: &Path, num_buckets: usize, options: &ImageOptions) -> io::Result<HarmonicCurves> {
+    let image = decode_png(&fs::read(path)?)?;
+    if image.width == 0 || image.height == 0 {
+        return Err(invalid("Image is empty"));
+    }
+    Ok(image_curves(&image, num_buckets, options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_png(width: u32, height: u32, color_type: png::ColorType, data: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(color_type);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header().unwrap().write_image_data(data).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_decode_png() {
+        let bytes = encode_png(2, 1, png::ColorType::GrayscaleAlpha, &[255, 255, 51, 0]);
+        let image = decode_png(&bytes).unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(image.pixels, [[1.0, 1.0, 1.0, 1.0], [0.2, 0.2, 0.2, 0.0]]);
+        assert_eq!(decode_png(b"not a png").unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_pixel_curves() {
+        assert_eq!(pixel_curves([0.5, 0.5, 0.5, 1.0]), (0.5, 0.0));
+        assert_eq!(pixel_curves([1.0, 0.0, 0.0, 0.5]), (0.5, 0.0));
+        let (amplitude, phase) = pixel_curves([0.0, 0.8, 0.0, 1.0]);
+        assert_eq!(amplitude, 0.8);
+        assert!((phase - TWO_PI / 3.0).abs() < 1e-5);
+        let (_, phase) = pixel_curves([1.0, 0.0, 1.0, 1.0]);
+        assert!((phase - TWO_PI * 5.0 / 6.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_image_curves() {
+        // Bright bottom row fading to the right, blue top row
+        let image = Image {
+            width: 3,
+            height: 2,
+            pixels: vec![
+                [0.0, 0.0, 1.0, 1.0],
+                [0.0, 0.0, 1.0, 1.0],
+                [0.0, 0.0, 1.0, 1.0],
+                [1.0, 1.0, 1.0, 1.0],
+                [0.5, 0.5, 0.5, 1.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        };
+        let curves = image_curves(&image, 5, &ImageOptions::default());
+        assert_eq!(curves.num_buckets(), 5);
+        assert_eq!(curves.amplitudes.len(), NUM_HARMONICS);
+        assert_eq!(curves.amplitudes[0], [1.0, 0.75, 0.5, 0.25, 0.0]);
+        assert_eq!(curves.amplitudes[NUM_HARMONICS - 1], [1.0; 5]);
+        assert!(curves.phases.iter().flatten().all(|&phase| phase == 0.0));
+
+        let curves = image_curves(&image, 5, &ImageOptions { hue_to_phase: true });
+        assert_eq!(curves.phases[0], [0.0; 5]);
+        assert!((curves.phases[NUM_HARMONICS - 1][2] - TWO_PI * 2.0 / 3.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_import_image() {
+        let path = std::env::temp_dir().join(format!("lesynth_image_test_{}.png", std::process::id()));
+        fs::write(&path, encode_png(2, 2, png::ColorType::Rgb, &[255, 0, 0, 255, 0, 0, 0, 0, 0, 128, 128, 128])).unwrap();
+        let curves = import_image(&path, 70, &ImageOptions::default()).unwrap();
+        assert_eq!(curves.num_buckets(), 70);
+        assert_eq!(curves.amplitudes[0][0], 0.0);
+        assert!((curves.amplitudes[0][69] - 128.0 / 255.0).abs() < 1e-6);
+        assert_eq!(curves.amplitudes[NUM_HARMONICS - 1][35], 1.0);
+        fs::remove_file(&path).unwrap();
+    }
+}
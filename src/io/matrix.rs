@@ -0,0 +1,261 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Amplitude or phase data as a matrix with a row per harmonic and a column per bucket.
+//!
+//! CSV files hold one row per line, separated by commas or whitespace, so `numpy.savetxt` and
+//! `numpy.loadtxt` read and write them directly. Lines starting with `#` are comments. JSON
+//! files hold an array of rows.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use crate::constants::{NUM_HARMONICS, TWO_PI};
+use crate::engine::{ChartType, SynthComputeEngine};
+use crate::patch::HarmonicCurves;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatrixFormat {
+    Csv,
+    Json,
+}
+
+impl MatrixFormat {
+    /// Format matching the extension of `path`
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "csv" | "txt" => Some(MatrixFormat::Csv),
+            "json" => Some(MatrixFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Reason a matrix can't be imported
+#[derive(Debug, Clone, PartialEq)]
+pub enum MatrixError {
+    Parse { line: usize, message: String },
+    HarmonicCount { expected: usize, found: usize },
+    BucketCount { harmonic: usize, expected: usize, found: usize },
+    OutOfRange { harmonic: usize, bucket: usize, value: f32, range: RangeInclusive<f32> },
+}
+
+impl fmt::Display for MatrixError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatrixError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            MatrixError::HarmonicCount { expected, found } => {
+                write!(f, "expected {} rows (one per harmonic), found {}", expected, found)
+            }
+            MatrixError::BucketCount { harmonic, expected, found } => {
+                write!(f, "harmonic {} has {} buckets, expected {}", harmonic + 1, found, expected)
+            }
+            MatrixError::OutOfRange { harmonic, bucket, value, range } => write!(
+                f,
+                "harmonic {}, bucket {}: {} is outside {}..={}",
+                harmonic + 1,
+                bucket + 1,
+                value,
+                range.start(),
+                range.end()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MatrixError {}
+
+/// Values allowed in amplitude or phase data
+pub fn value_range(chart_type: &ChartType) -> RangeInclusive<f32> {
+    match chart_type {
+        ChartType::Amp => 0.0..=1.0,
+        ChartType::Phase => 0.0..=TWO_PI,
+    }
+}
+
+pub fn encode(rows: &[Vec<f32>], format: MatrixFormat) -> String {
+    let lines: Vec<String> = match format {
+        MatrixFormat::Csv => rows
+            .iter()
+            .map(|row| row.iter().map(f32::to_string).collect::<Vec<_>>().join(","))
+            .collect(),
+        MatrixFormat::Json => rows
+            .iter()
+            .map(|row| format!("  {}", serde_json::to_string(row).unwrap_or_default()))
+            .collect(),
+    };
+    match format {
+        MatrixFormat::Csv => lines.join("\n") + "\n",
+        // One row per line keeps the file readable and diffable
+        MatrixFormat::Json => format!("[\n{}\n]\n", lines.join(",\n")),
+    }
+}
+
+/// Parse a matrix without checking its dimensions or values
+pub fn decode(text: &str, format: MatrixFormat) -> Result<Vec<Vec<f32>>, MatrixError> {
+    match format {
+        MatrixFormat::Csv => text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+            .map(|(index, line)| {
+                line.split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|cell| !cell.is_empty())
+                    .map(|cell| {
+                        cell.parse::<f32>().map_err(|_| MatrixError::Parse {
+                            line: index + 1,
+                            message: format!("invalid number {:?}", cell),
+                        })
+                    })
+                    .collect()
+            })
+            .collect(),
+        MatrixFormat::Json => serde_json::from_str(text)
+            .map_err(|err| MatrixError::Parse { line: err.line(), message: err.to_string() }),
+    }
+}
+
+/// Check that there is a row per harmonic, `num_buckets` values in each and all of them in range
+pub fn validate(rows: &[Vec<f32>], chart_type: &ChartType, num_buckets: usize) -> Result<(), MatrixError> {
+    if rows.len() != NUM_HARMONICS {
+        return Err(MatrixError::HarmonicCount { expected: NUM_HARMONICS, found: rows.len() });
+    }
+    let range = value_range(chart_type);
+    for (harmonic, row) in rows.iter().enumerate() {
+        if row.len() != num_buckets {
+            return Err(MatrixError::BucketCount { harmonic, expected: num_buckets, found: row.len() });
+        }
+        if let Some((bucket, &value)) = row.iter().enumerate().find(|(_, value)| !range.contains(value)) {
+            return Err(MatrixError::OutOfRange { harmonic, bucket, value, range });
+        }
+    }
+    Ok(())
+}
+
+fn format_of(path: &Path) -> io::Result<MatrixFormat> {
+    MatrixFormat::from_path(path)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Expected a .csv or .json file"))
+}
+
+/// Read and validate a matrix, in the format given by the file extension
+pub fn read_matrix(path: &Path, chart_type: &ChartType, num_buckets: usize) -> io::Result<Vec<Vec<f32>>> {
+    let format = format_of(path)?;
+    let rows = decode(&fs::read_to_string(path)?, format).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    validate(&rows, chart_type, num_buckets).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    Ok(rows)
+}
+
+pub fn write_matrix(path: &Path, rows: &[Vec<f32>]) -> io::Result<()> {
+    fs::write(path, encode(rows, format_of(path)?))
+}
+
+/// Write the engine's amplitude or phase data
+pub fn export_matrix(engine: &SynthComputeEngine, chart_type: &ChartType, path: &Path) -> io::Result<()> {
+    let patch = engine.patch();
+    match chart_type {
+        ChartType::Amp => write_matrix(path, &patch.amplitudes),
+        ChartType::Phase => write_matrix(path, &patch.phases),
+    }
+}
+
+/// Replace the engine's amplitude or phase data with a matrix file. Returns the engine's curves
+/// after the import.
+pub fn import_matrix(engine: &SynthComputeEngine, chart_type: &ChartType, path: &Path) -> io::Result<HarmonicCurves> {
+    let patch = engine.patch();
+    let rows = read_matrix(path, chart_type, patch.num_buckets())?;
+    let mut curves = HarmonicCurves { amplitudes: patch.amplitudes, phases: patch.phases };
+    match chart_type {
+        ChartType::Amp => curves.amplitudes = rows,
+        ChartType::Phase => curves.phases = rows,
+    }
+    engine.load_curves(&curves, |_, other| other == *chart_type);
+    Ok(curves)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patch::RenderSettings;
+
+    fn matrix(num_buckets: usize) -> Vec<Vec<f32>> {
+        (0..NUM_HARMONICS)
+            .map(|n| (0..num_buckets).map(|b| ((n * num_buckets + b) % 7) as f32 / 7.0).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let rows = matrix(5);
+        for format in [MatrixFormat::Csv, MatrixFormat::Json] {
+            assert_eq!(decode(&encode(&rows, format), format).unwrap(), rows);
+        }
+        assert!(encode(&rows, MatrixFormat::Csv).starts_with("0,0.14285715,0.2857143,"));
+
+        // As written by numpy.savetxt with its default delimiter and a header
+        let text = "# amplitudes\n1.000000000000000000e+00 5.0e-01\n\n0 0.25\n";
+        assert_eq!(decode(text, MatrixFormat::Csv).unwrap(), [vec![1.0, 0.5], vec![0.0, 0.25]]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = decode("0.1,0.2\n0.3,x\n", MatrixFormat::Csv).unwrap_err();
+        assert_eq!(err, MatrixError::Parse { line: 2, message: "invalid number \"x\"".to_string() });
+        assert!(matches!(decode("[[0.1],\n[", MatrixFormat::Json), Err(MatrixError::Parse { line: 2, .. })));
+
+        let mut rows = matrix(5);
+        assert_eq!(validate(&rows, &ChartType::Amp, 5), Ok(()));
+        assert_eq!(
+            validate(&rows[1..], &ChartType::Amp, 5),
+            Err(MatrixError::HarmonicCount { expected: NUM_HARMONICS, found: NUM_HARMONICS - 1 })
+        );
+        assert_eq!(
+            validate(&rows, &ChartType::Amp, 6),
+            Err(MatrixError::BucketCount { harmonic: 0, expected: 6, found: 5 })
+        );
+        rows[3][2] = 3.0;
+        let err = validate(&rows, &ChartType::Amp, 5).unwrap_err();
+        assert_eq!(err.to_string(), "harmonic 4, bucket 3: 3 is outside 0..=1");
+        assert_eq!(validate(&rows, &ChartType::Phase, 5), Ok(()));
+        rows[3][2] = f32::NAN;
+        assert!(validate(&rows, &ChartType::Phase, 5).is_err());
+    }
+
+    #[test]
+    fn test_import_and_export() {
+        let engine = SynthComputeEngine::new(RenderSettings::default());
+        let num_buckets = engine.patch().num_buckets();
+        engine.fill_constant_curve(1, 1.5, ChartType::Phase);
+        let rows = matrix(num_buckets);
+        for extension in ["csv", "json"] {
+            let path = std::env::temp_dir().join(format!("lesynth_matrix_test_{}.{}", std::process::id(), extension));
+            write_matrix(&path, &rows).unwrap();
+            let curves = import_matrix(&engine, &ChartType::Amp, &path).unwrap();
+            assert_eq!(curves.amplitudes, rows);
+            assert_eq!(engine.patch().amplitudes, rows);
+            // The other matrix is untouched
+            assert_eq!(engine.patch().phases[1][0], 1.5);
+
+            export_matrix(&engine, &ChartType::Phase, &path).unwrap();
+            assert_eq!(read_matrix(&path, &ChartType::Phase, num_buckets).unwrap(), engine.patch().phases);
+            let err = read_matrix(&path, &ChartType::Phase, num_buckets + 1).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            fs::remove_file(&path).unwrap();
+        }
+        let err = export_matrix(&engine, &ChartType::Amp, Path::new("matrix.xlsx")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,130 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Reading the notes of a Standard MIDI File.
+
+use std::io;
+use midly::{MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+use crate::constants::NUM_KEYS;
+use crate::offline::NoteEvent;
+
+// Microseconds per quarter note until the first tempo event
+const DEFAULT_TEMPO: f64 = 500_000.0;
+
+enum Change {
+    Note { key: usize, on: bool },
+    Tempo(f64),
+}
+
+/// Note events of all tracks and channels, sorted by time, at `sample_rate`.
+/// Note numbers are used as key indices, as the plugin does with incoming MIDI.
+pub fn read_notes(bytes: &[u8], sample_rate: f64) -> io::Result<Vec<NoteEvent>> {
+    let smf = Smf::parse(bytes).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+
+    // Tempo changes of any track apply to all of them, so merge the tracks first
+    let mut changes = Vec::new();
+    for track in &smf.tracks {
+        let mut tick = 0u64;
+        for event in track {
+            tick += event.delta.as_int() as u64;
+            let change = match event.kind {
+                TrackEventKind::Midi { message: MidiMessage::NoteOn { key, vel }, .. } => {
+                    Change::Note { key: key.as_int() as usize, on: vel.as_int() > 0 }
+                }
+                TrackEventKind::Midi { message: MidiMessage::NoteOff { key, .. }, .. } => {
+                    Change::Note { key: key.as_int() as usize, on: false }
+                }
+                TrackEventKind::Meta(MetaMessage::Tempo(tempo)) => Change::Tempo(tempo.as_int() as f64),
+                _ => continue,
+            };
+            changes.push((tick, change));
+        }
+    }
+    changes.sort_by_key(|&(tick, _)| tick);
+
+    let mut seconds_per_tick = match smf.header.timing {
+        Timing::Metrical(ticks_per_beat) => DEFAULT_TEMPO / 1e6 / ticks_per_beat.as_int().max(1) as f64,
+        Timing::Timecode(fps, subframes) => 1.0 / (fps.as_f32() as f64 * subframes.max(1) as f64),
+    };
+    let (mut last_tick, mut seconds) = (0u64, 0.0);
+    let mut events = Vec::new();
+    for (tick, change) in changes {
+        seconds += (tick - last_tick) as f64 * seconds_per_tick;
+        last_tick = tick;
+        match change {
+            Change::Note { key, on } if key < NUM_KEYS => {
+                let sample = (seconds * sample_rate).round() as usize;
+                events.push(NoteEvent { sample, key, on });
+            }
+            Change::Note { .. } => {}
+            Change::Tempo(tempo) => {
+                // SMPTE timing has no tempo
+                if let Timing::Metrical(ticks_per_beat) = smf.header.timing {
+                    seconds_per_tick = tempo / 1e6 / ticks_per_beat.as_int().max(1) as f64;
+                }
+            }
+        }
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use midly::num::{u15, u24, u28, u4, u7};
+    use midly::{Format, Header, TrackEvent};
+
+    fn event(delta: u32, kind: TrackEventKind<'static>) -> TrackEvent<'static> {
+        TrackEvent { delta: u28::new(delta), kind }
+    }
+
+    fn note(delta: u32, key: u8, vel: u8) -> TrackEvent<'static> {
+        let message = MidiMessage::NoteOn { key: u7::new(key), vel: u7::new(vel) };
+        event(delta, TrackEventKind::Midi { channel: u4::new(0), message })
+    }
+
+    fn write(smf: &Smf) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        smf.write_std(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_tempo_changes_apply_across_tracks() {
+        let mut smf = Smf::new(Header::new(Format::Parallel, Timing::Metrical(u15::new(480))));
+        // Tempo track: 120 bpm, then 60 bpm from the second beat
+        smf.tracks.push(vec![
+            event(480, TrackEventKind::Meta(MetaMessage::Tempo(u24::new(1_000_000)))),
+            event(0, TrackEventKind::Meta(MetaMessage::EndOfTrack)),
+        ]);
+        // Zero velocity ends a note; keys the plugin doesn't have are dropped
+        smf.tracks.push(vec![note(0, 40, 100), note(480, 40, 0), note(480, 41, 90), note(0, 100, 90)]);
+
+        let events = read_notes(&write(&smf), 1000.0).unwrap();
+        assert_eq!(
+            events,
+            vec![
+                NoteEvent { sample: 0, key: 40, on: true },
+                NoteEvent { sample: 500, key: 40, on: false },
+                NoteEvent { sample: 1500, key: 41, on: true },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_file() {
+        let err = read_notes(b"not a midi file", 44100.0).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,164 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Exporting the patch as a multisampled SFZ instrument.
+//!
+//! Every exported key is rendered to a WAV file that loops over its whole bucket timeline,
+//! like a held note in the plugin. Keys map to MIDI note numbers the way the plugin
+//! maps incoming notes, so the instrument plays the same pitches as LeSynth in a host.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::constants::{key_name, NUM_KEYS};
+use crate::engine::SynthComputeEngine;
+use crate::io::wav::{self, Chunk, SampleFormat, WavSpec};
+
+// Highest MIDI note a region can reach
+const MAX_NOTE: usize = 127;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SfzOptions {
+    // Name of the .sfz file and of the folder holding its samples
+    pub name: String,
+    pub keys: Vec<usize>,
+    pub format: SampleFormat,
+}
+
+/// Sampled key and the notes it is played for
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Region {
+    key: usize,
+    lokey: usize,
+    hikey: usize,
+}
+
+/// Every `step`th key from A0, so a sampler has to transpose by at most half the step
+pub fn every_nth_key(step: usize) -> Vec<usize> {
+    (0..NUM_KEYS).step_by(step.max(1)).collect()
+}
+
+/// Split the note range between the keys, each taking the notes up to halfway to its neighbours
+fn regions(keys: &[usize]) -> Vec<Region> {
+    let mut keys: Vec<usize> = keys.iter().copied().filter(|&key| key < NUM_KEYS).collect();
+    keys.sort_unstable();
+    keys.dedup();
+    let mut regions: Vec<Region> = Vec::with_capacity(keys.len());
+    for (i, &key) in keys.iter().enumerate() {
+        let lokey = regions.last().map_or(0, |previous| previous.hikey + 1);
+        let hikey = keys.get(i + 1).map_or(MAX_NOTE, |&next| (key + next) / 2);
+        regions.push(Region { key, lokey, hikey });
+    }
+    regions
+}
+
+fn sample_file(key: usize) -> String {
+    format!("{}.wav", key_name(key).replace('#', "s"))
+}
+
+fn sfz_text(name: &str, regions: &[Region], sample_lens: &[usize], fade_seconds: f64) -> String {
+    let mut text = String::new();
+    let _ = writeln!(text, "// {} exported from LeSynth", name);
+    let _ = writeln!(text, "<control>\ndefault_path={}/\n", name);
+    let _ = writeln!(text, "<global>\nloop_mode=loop_continuous");
+    let _ = writeln!(text, "ampeg_attack={:.6}\nampeg_release={:.6}\n", fade_seconds, fade_seconds);
+    for (region, &len) in regions.iter().zip(sample_lens) {
+        let _ = writeln!(
+            text,
+            "<region> sample={} lokey={} hikey={} pitch_keycenter={} loop_start=0 loop_end={}",
+            sample_file(region.key),
+            region.lokey,
+            region.hikey,
+            region.key,
+            len.saturating_sub(1)
+        );
+    }
+    text
+}
+
+/// Render the keys to `dir/<name>/` and write `dir/<name>.sfz`, returning the path of the latter
+pub fn export_sfz(engine: &SynthComputeEngine, dir: &Path, options: &SfzOptions) -> io::Result<PathBuf> {
+    let name = options.name.trim();
+    if name.is_empty() || name.contains(['/', '\\']) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Instrument name must be a plain file name"));
+    }
+    let regions = regions(&options.keys);
+    if regions.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "No keys to export"));
+    }
+
+    let sample_rate = engine.sample_rate().round() as u32;
+    let spec = WavSpec { channels: 1, sample_rate, format: options.format };
+    let samples_dir = dir.join(name);
+    fs::create_dir_all(&samples_dir)?;
+    let mut sample_lens = Vec::with_capacity(regions.len());
+    for region in &regions {
+        let samples = engine.assemble_buffer_for_key(region.key);
+        let loop_end = samples.len().saturating_sub(1) as u32;
+        let sampler = Chunk::sampler(sample_rate, region.key as u8, 0, loop_end);
+        wav::write_with_chunks(&samples_dir.join(sample_file(region.key)), &spec, &samples, &[sampler])?;
+        sample_lens.push(samples.len());
+    }
+
+    let fade_seconds = engine.shared_params.fade_duration as f64 / sample_rate.max(1) as f64;
+    let path = dir.join(format!("{}.sfz", name));
+    fs::write(&path, sfz_text(name, &regions, &sample_lens, fade_seconds))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::ChartType;
+    use crate::patch::RenderSettings;
+
+    #[test]
+    fn test_regions_split_halfway() {
+        let regions = regions(&[24, 12, 12, 36, 200]);
+        assert_eq!(
+            regions,
+            vec![
+                Region { key: 12, lokey: 0, hikey: 18 },
+                Region { key: 24, lokey: 19, hikey: 30 },
+                Region { key: 36, lokey: 31, hikey: 127 },
+            ]
+        );
+        assert_eq!(every_nth_key(12).len(), 8);
+        assert_eq!(every_nth_key(0).len(), NUM_KEYS);
+    }
+
+    #[test]
+    fn test_export_writes_looped_samples() {
+        let dir = std::env::temp_dir().join(format!("lesynth_sfz_test_{}", std::process::id()));
+        let engine = SynthComputeEngine::new(RenderSettings::default());
+        engine.fill_constant_curve(0, 0.5, ChartType::Amp);
+        let options = SfzOptions { name: "test".to_string(), keys: vec![27, 39], format: SampleFormat::Int16 };
+        let path = export_sfz(&engine, &dir, &options).unwrap();
+
+        let text = fs::read_to_string(&path).unwrap();
+        let len = engine.assemble_buffer_for_key(39).len();
+        assert!(text.contains("default_path=test/"));
+        assert!(text.contains(&format!(
+            "sample=C4.wav lokey=34 hikey=127 pitch_keycenter=39 loop_start=0 loop_end={}",
+            len - 1
+        )));
+        let wav = fs::read(dir.join("test").join("C4.wav")).unwrap();
+        assert_eq!(wav.len(), 44 + 2 * len + 8 + 60);
+
+        let err = export_sfz(&engine, &dir, &SfzOptions { name: "a/b".to_string(), ..options }).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,377 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Reading RIFF WAVE files and writing them with 16 or 24-bit integer or 32-bit float samples.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_IEEE_FLOAT: u16 = 3;
+// The actual format tag is the start of the sub-format GUID
+const FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+/// Sample encoding of a WAV file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    Int16,
+    Int24,
+    Float32,
+}
+
+impl SampleFormat {
+    pub fn bits(self) -> u16 {
+        match self {
+            SampleFormat::Int16 => 16,
+            SampleFormat::Int24 => 24,
+            SampleFormat::Float32 => 32,
+        }
+    }
+
+    /// Parse `16`, `24` or `32f`
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "16" => Some(SampleFormat::Int16),
+            "24" => Some(SampleFormat::Int24),
+            "32f" | "32" => Some(SampleFormat::Float32),
+            _ => None,
+        }
+    }
+
+    fn write_sample(self, bytes: &mut Vec<u8>, sample: f32) {
+        match self {
+            SampleFormat::Int16 => {
+                let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16;
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            SampleFormat::Int24 => {
+                let value = (sample.clamp(-1.0, 1.0) * 8_388_607.0).round() as i32;
+                bytes.extend_from_slice(&value.to_le_bytes()[..3]);
+            }
+            SampleFormat::Float32 => bytes.extend_from_slice(&sample.to_le_bytes()),
+        }
+    }
+}
+
+/// Layout of the samples in a WAV file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WavSpec {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub format: SampleFormat,
+}
+
+impl WavSpec {
+    fn block_align(&self) -> u16 {
+        self.channels * self.format.bits() / 8
+    }
+}
+
+fn push_chunk(bytes: &mut Vec<u8>, id: &[u8; 4], body: &[u8]) {
+    bytes.extend_from_slice(id);
+    bytes.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(body);
+    // Chunks are word aligned
+    if body.len() % 2 == 1 {
+        bytes.push(0);
+    }
+}
+
+/// Extra chunk stored after the samples, e.g. sampler metadata
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    pub id: [u8; 4],
+    pub body: Vec<u8>,
+}
+
+impl Chunk {
+    /// Sampler chunk with the root note and one forward loop over `loop_start..=loop_end`
+    pub fn sampler(sample_rate: u32, root_note: u8, loop_start: u32, loop_end: u32) -> Self {
+        let sample_period_ns = (1e9 / sample_rate.max(1) as f64).round() as u32;
+        let fields = [
+            0, // manufacturer
+            0, // product
+            sample_period_ns,
+            root_note as u32,
+            0, // pitch fraction
+            0, // SMPTE format
+            0, // SMPTE offset
+            1, // loops
+            0, // sampler data
+            // The loop: cue point ID, forward type, start, end, fraction, endless
+            0,
+            0,
+            loop_start,
+            loop_end,
+            0,
+            0,
+        ];
+        Self {
+            id: *b"smpl",
+            body: fields.iter().flat_map(|field: &u32| field.to_le_bytes()).collect(),
+        }
+    }
+}
+
+/// WAV file holding interleaved `samples`
+pub fn encode(spec: &WavSpec, samples: &[f32]) -> Vec<u8> {
+    encode_with_chunks(spec, samples, &[])
+}
+
+/// WAV file holding interleaved `samples` followed by `chunks`
+pub fn encode_with_chunks(spec: &WavSpec, samples: &[f32], chunks: &[Chunk]) -> Vec<u8> {
+    let format_tag = match spec.format {
+        SampleFormat::Float32 => FORMAT_IEEE_FLOAT,
+        SampleFormat::Int16 | SampleFormat::Int24 => FORMAT_PCM,
+    };
+    let mut fmt = Vec::with_capacity(16);
+    fmt.extend_from_slice(&format_tag.to_le_bytes());
+    fmt.extend_from_slice(&spec.channels.to_le_bytes());
+    fmt.extend_from_slice(&spec.sample_rate.to_le_bytes());
+    fmt.extend_from_slice(&(spec.sample_rate * spec.block_align() as u32).to_le_bytes());
+    fmt.extend_from_slice(&spec.block_align().to_le_bytes());
+    fmt.extend_from_slice(&spec.format.bits().to_le_bytes());
+
+    let mut data = Vec::with_capacity(samples.len() * spec.format.bits() as usize / 8);
+    for &sample in samples {
+        spec.format.write_sample(&mut data, sample);
+    }
+
+    let mut bytes = Vec::with_capacity(data.len() + 64);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&[0; 4]);
+    bytes.extend_from_slice(b"WAVE");
+    push_chunk(&mut bytes, b"fmt ", &fmt);
+    if format_tag != FORMAT_PCM {
+        // Required for anything but integer PCM: number of sample frames
+        let frames = (samples.len() / spec.channels.max(1) as usize) as u32;
+        push_chunk(&mut bytes, b"fact", &frames.to_le_bytes());
+    }
+    push_chunk(&mut bytes, b"data", &data);
+    for chunk in chunks {
+        push_chunk(&mut bytes, &chunk.id, &chunk.body);
+    }
+    let riff_len = (bytes.len() - 8) as u32;
+    bytes[4..8].copy_from_slice(&riff_len.to_le_bytes());
+    bytes
+}
+
+pub fn write(path: &Path, spec: &WavSpec, samples: &[f32]) -> io::Result<()> {
+    fs::write(path, encode(spec, samples))
+}
+
+pub fn write_with_chunks(path: &Path, spec: &WavSpec, samples: &[f32], chunks: &[Chunk]) -> io::Result<()> {
+    fs::write(path, encode_with_chunks(spec, samples, chunks))
+}
+
+/// Samples and chunks read from a WAV file
+#[derive(Debug, Clone, PartialEq)]
+pub struct WavFile {
+    pub channels: u16,
+    pub sample_rate: u32,
+    // Interleaved, scaled to `-1.0..=1.0` for integer formats
+    pub samples: Vec<f32>,
+    // Chunks other than the format and the samples
+    pub chunks: Vec<Chunk>,
+}
+
+impl WavFile {
+    /// Average of the channels
+    pub fn mono(&self) -> Vec<f32> {
+        let channels = self.channels.max(1) as usize;
+        self.samples
+            .chunks_exact(channels)
+            .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+            .collect()
+    }
+
+    pub fn chunk(&self, id: &[u8; 4]) -> Option<&Chunk> {
+        self.chunks.iter().find(|chunk| &chunk.id == id)
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn read_samples(data: &[u8], format_tag: u16, bits: u16) -> io::Result<Vec<f32>> {
+    let samples = match (format_tag, bits) {
+        (FORMAT_PCM, 8) => data.iter().map(|&b| (b as f32 - 128.0) / 128.0).collect(),
+        (FORMAT_PCM, 16) => data
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0)
+            .collect(),
+        (FORMAT_PCM, 24) => data
+            .chunks_exact(3)
+            .map(|b| (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f32 / 8_388_608.0)
+            .collect(),
+        (FORMAT_PCM, 32) => data
+            .chunks_exact(4)
+            .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2_147_483_648.0)
+            .collect(),
+        (FORMAT_IEEE_FLOAT, 32) => data
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect(),
+        (FORMAT_IEEE_FLOAT, 64) => data
+            .chunks_exact(8)
+            .map(|b| f64::from_le_bytes(b.try_into().unwrap()) as f32)
+            .collect(),
+        _ => return Err(invalid(&format!("Unsupported sample format {} with {} bits", format_tag, bits))),
+    };
+    Ok(samples)
+}
+
+/// Parse a WAV file of 8 to 32-bit integer or 32 or 64-bit float samples
+pub fn decode(bytes: &[u8]) -> io::Result<WavFile> {
+    if bytes.len() < 12 || &bytes[..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(invalid("Not a WAV file"));
+    }
+    let mut format = None;
+    let mut data = None;
+    let mut chunks = Vec::new();
+    let mut pos = 12;
+    while pos + 8 <= bytes.len() {
+        let id: [u8; 4] = bytes[pos..pos + 4].try_into().unwrap();
+        let len = u32::from_le_bytes(bytes[pos + 4..pos + 8].try_into().unwrap()) as usize;
+        // Writers that never patched the length leave it too long, so take what is there
+        let body = &bytes[pos + 8..(pos + 8).saturating_add(len).min(bytes.len())];
+        match &id {
+            b"fmt " if body.len() >= 16 => {
+                let field = |at: usize| u16::from_le_bytes([body[at], body[at + 1]]);
+                let mut format_tag = field(0);
+                if format_tag == FORMAT_EXTENSIBLE && body.len() >= 26 {
+                    format_tag = field(24);
+                }
+                let sample_rate = u32::from_le_bytes(body[4..8].try_into().unwrap());
+                format = Some((format_tag, field(2), sample_rate, field(14)));
+            }
+            b"data" => data = Some(body),
+            _ => chunks.push(Chunk { id, body: body.to_vec() }),
+        }
+        pos = pos.saturating_add(8 + len + len % 2);
+    }
+    let (format_tag, channels, sample_rate, bits) = format.ok_or_else(|| invalid("WAV file has no format chunk"))?;
+    let data = data.ok_or_else(|| invalid("WAV file has no samples"))?;
+    if channels == 0 {
+        return Err(invalid("WAV file has no channels"));
+    }
+    Ok(WavFile {
+        channels,
+        sample_rate,
+        samples: read_samples(data, format_tag, bits)?,
+        chunks,
+    })
+}
+
+pub fn read(path: &Path) -> io::Result<WavFile> {
+    decode(&fs::read(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk<'a>(bytes: &'a [u8], id: &[u8; 4]) -> Option<&'a [u8]> {
+        let mut pos = 12;
+        while pos + 8 <= bytes.len() {
+            let len = u32::from_le_bytes(bytes[pos + 4..pos + 8].try_into().unwrap()) as usize;
+            if &bytes[pos..pos + 4] == id {
+                return Some(&bytes[pos + 8..pos + 8 + len]);
+            }
+            pos += 8 + len + len % 2;
+        }
+        None
+    }
+
+    #[test]
+    fn test_int16_header_and_samples() {
+        let spec = WavSpec { channels: 2, sample_rate: 48000, format: SampleFormat::Int16 };
+        let bytes = encode(&spec, &[0.0, 1.0, -1.0, 2.0]);
+        assert_eq!(&bytes[..4], b"RIFF");
+        assert_eq!(&bytes[8..12], b"WAVE");
+        assert_eq!(u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize, bytes.len() - 8);
+
+        let fmt = chunk(&bytes, b"fmt ").unwrap();
+        assert_eq!(u16::from_le_bytes([fmt[0], fmt[1]]), FORMAT_PCM);
+        assert_eq!(u32::from_le_bytes(fmt[8..12].try_into().unwrap()), 48000 * 4);
+        assert!(chunk(&bytes, b"fact").is_none());
+        // Out-of-range samples are clipped
+        let data = chunk(&bytes, b"data").unwrap();
+        assert_eq!(data, [0, 0, 0xff, 0x7f, 0x01, 0x80, 0xff, 0x7f]);
+    }
+
+    #[test]
+    fn test_int24_and_float_samples() {
+        let spec = WavSpec { channels: 1, sample_rate: 44100, format: SampleFormat::Int24 };
+        let data = chunk(&encode(&spec, &[0.5, -1.0]), b"data").unwrap().to_vec();
+        assert_eq!(data, [0x00, 0x00, 0x40, 0x01, 0x00, 0x80]);
+
+        let spec = WavSpec { format: SampleFormat::Float32, ..spec };
+        let bytes = encode(&spec, &[0.25, -0.75, 1.5]);
+        assert_eq!(u16::from_le_bytes(chunk(&bytes, b"fmt ").unwrap()[..2].try_into().unwrap()), FORMAT_IEEE_FLOAT);
+        assert_eq!(chunk(&bytes, b"fact").unwrap(), 3u32.to_le_bytes());
+        let data = chunk(&bytes, b"data").unwrap();
+        assert_eq!(f32::from_le_bytes(data[8..12].try_into().unwrap()), 1.5);
+    }
+
+    #[test]
+    fn test_sampler_chunk() {
+        let spec = WavSpec { channels: 1, sample_rate: 50000, format: SampleFormat::Int16 };
+        let sampler = Chunk::sampler(50000, 60, 0, 99);
+        let bytes = encode_with_chunks(&spec, &[0.0; 100], &[sampler]);
+        let smpl = chunk(&bytes, b"smpl").unwrap();
+        let field = |index: usize| u32::from_le_bytes(smpl[index * 4..index * 4 + 4].try_into().unwrap());
+        assert_eq!(smpl.len(), 60);
+        assert_eq!(field(2), 20_000);
+        assert_eq!(field(3), 60);
+        assert_eq!(field(7), 1);
+        assert_eq!((field(11), field(12)), (0, 99));
+        assert_eq!(u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize, bytes.len() - 8);
+    }
+
+    #[test]
+    fn test_decode_what_is_encoded() {
+        let samples = [0.0, 0.5, -0.5, 0.25];
+        for format in [SampleFormat::Int16, SampleFormat::Int24, SampleFormat::Float32] {
+            let spec = WavSpec { channels: 2, sample_rate: 48000, format };
+            let sampler = Chunk::sampler(48000, 60, 0, 1);
+            let file = decode(&encode_with_chunks(&spec, &samples, std::slice::from_ref(&sampler))).unwrap();
+            assert_eq!((file.channels, file.sample_rate), (2, 48000));
+            for (decoded, expected) in file.samples.iter().zip(samples) {
+                assert!((decoded - expected).abs() < 1e-4);
+            }
+            assert_eq!(file.chunk(b"smpl"), Some(&sampler));
+            let mono = file.mono();
+            assert!((mono[0] - 0.25).abs() < 1e-4 && (mono[1] + 0.125).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert_eq!(decode(b"RIFF\0\0\0\0AVI ").unwrap_err().kind(), io::ErrorKind::InvalidData);
+        let mut bytes = encode(&WavSpec { channels: 1, sample_rate: 44100, format: SampleFormat::Int16 }, &[0.0]);
+        // 12-bit samples aren't supported
+        bytes[34] = 12;
+        assert!(decode(&bytes).unwrap_err().to_string().contains("Unsupported"));
+    }
+
+    #[test]
+    fn test_parse_sample_format() {
+        assert_eq!(SampleFormat::parse("24"), Some(SampleFormat::Int24));
+        assert_eq!(SampleFormat::parse("32f"), Some(SampleFormat::Float32));
+        assert_eq!(SampleFormat::parse("8"), None);
+    }
+}
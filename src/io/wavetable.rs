@@ -0,0 +1,270 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Wavetable WAV files to and from the bucket timeline.
+//!
+//! Frames are stored back to back in a mono WAV with a `clm ` chunk giving the cycle length,
+//! the layout read by most wavetable synths.
+
+use std::f32::consts::TAU;
+use std::io;
+use std::path::Path;
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use crate::constants::NUM_HARMONICS;
+use crate::engine::SynthComputeEngine;
+use crate::io::wav::{self, Chunk, SampleFormat, WavFile, WavSpec};
+use crate::patch::HarmonicCurves;
+
+pub const FRAME_SIZE_DEFAULT: usize = 2048;
+// Most wavetable synths load at most this many frames
+pub const MAX_FRAMES: usize = 256;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WavetableOptions {
+    // Samples per single-cycle frame
+    pub frame_size: usize,
+    // Resample the buckets to this many frames, `None` keeps one frame per bucket
+    pub frames: Option<usize>,
+    pub format: SampleFormat,
+}
+
+impl Default for WavetableOptions {
+    fn default() -> Self {
+        Self {
+            frame_size: FRAME_SIZE_DEFAULT,
+            frames: None,
+            format: SampleFormat::Float32,
+        }
+    }
+}
+
+/// Cycle length metadata in the form written by Serum and read by most other synths
+fn cycle_chunk(frame_size: usize) -> Chunk {
+    Chunk {
+        id: *b"clm ",
+        body: format!("<!>{} 00000000 wavetable (LeSynth)", frame_size).into_bytes(),
+    }
+}
+
+/// Spread `count` frames evenly over the bucket frames, crossfading between neighbours
+fn resample_frames(frames: &[f32], frame_size: usize, count: usize) -> Vec<f32> {
+    let num_frames = frames.len() / frame_size;
+    if num_frames == 0 || count == num_frames {
+        return frames.to_vec();
+    }
+    let frame = |index: usize| &frames[index * frame_size..(index + 1) * frame_size];
+    let mut resampled = Vec::with_capacity(count * frame_size);
+    for i in 0..count {
+        let position = if count > 1 { i as f32 * (num_frames - 1) as f32 / (count - 1) as f32 } else { 0.0 };
+        let index = (position as usize).min(num_frames - 1);
+        let next = (index + 1).min(num_frames - 1);
+        let frac = position - index as f32;
+        resampled.extend(frame(index).iter().zip(frame(next)).map(|(&a, &b)| a + (b - a) * frac));
+    }
+    resampled
+}
+
+/// Frames of the current patch, ready to be written
+pub fn render_wavetable(engine: &SynthComputeEngine, options: &WavetableOptions) -> Vec<f32> {
+    let frames = engine.render_bucket_frames(options.frame_size);
+    match options.frames {
+        Some(count) => resample_frames(&frames, options.frame_size, count),
+        None => frames,
+    }
+}
+
+/// Write the wavetable to `path`, returning the number of frames
+pub fn export_wavetable(engine: &SynthComputeEngine, path: &Path, options: &WavetableOptions) -> io::Result<usize> {
+    if options.frame_size < 2 || options.frames == Some(0) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Frame size and frame count must be positive"));
+    }
+    let samples = render_wavetable(engine, options);
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: engine.sample_rate().round() as u32,
+        format: options.format,
+    };
+    wav::write_with_chunks(path, &spec, &samples, &[cycle_chunk(options.frame_size)])?;
+    Ok(samples.len() / options.frame_size)
+}
+
+/// Cycle length given by a `clm ` chunk
+fn cycle_length(file: &WavFile) -> Option<usize> {
+    let text = String::from_utf8_lossy(&file.chunk(b"clm ")?.body).into_owned();
+    let digits: String = text.strip_prefix("<!>")?.chars().take_while(char::is_ascii_digit).collect();
+    digits.parse().ok().filter(|&size| size > 1)
+}
+
+/// Frame length of a file: from its `clm ` chunk, else 2048 if that divides it into several
+/// frames, else the whole file is a single cycle
+fn detect_frame_size(file: &WavFile, len: usize) -> usize {
+    cycle_length(file).unwrap_or(if len > FRAME_SIZE_DEFAULT && len.is_multiple_of(FRAME_SIZE_DEFAULT) {
+        FRAME_SIZE_DEFAULT
+    } else {
+        len
+    })
+}
+
+/// Magnitude and phase of the first harmonics of every frame, one bucket per frame
+pub fn analyze_frames(samples: &[f32], frame_size: usize) -> HarmonicCurves {
+    let num_frames = samples.len() / frame_size.max(1);
+    let mut curves = HarmonicCurves {
+        amplitudes: vec![vec![0.0; num_frames]; NUM_HARMONICS],
+        phases: vec![vec![0.0; num_frames]; NUM_HARMONICS],
+    };
+    if num_frames == 0 {
+        return curves;
+    }
+    let fft = FftPlanner::new().plan_fft_forward(frame_size);
+    let mut spectrum = vec![Complex::default(); frame_size];
+    // Harmonics at or above Nyquist of the frame are not in it
+    let harmonics = NUM_HARMONICS.min(frame_size.saturating_sub(1) / 2);
+    for (bucket, frame) in samples.chunks_exact(frame_size).enumerate() {
+        for (bin, &sample) in spectrum.iter_mut().zip(frame) {
+            *bin = Complex::new(sample, 0.0);
+        }
+        fft.process(&mut spectrum);
+        for n in 0..harmonics {
+            // A cosine of amplitude A and phase φ at bin n is A·N/2·e^(iφ)
+            let bin = spectrum[n + 1];
+            let amplitude = 2.0 * bin.norm() / frame_size as f32;
+            if amplitude > 1e-6 {
+                curves.amplitudes[n][bucket] = amplitude.min(1.0);
+                curves.phases[n][bucket] = bin.arg().rem_euclid(TAU);
+            }
+        }
+    }
+    curves
+}
+
+/// Harmonic curves of a wavetable or single-cycle WAV file, one bucket per frame.
+/// `frame_size` overrides the detected cycle length.
+pub fn import_wavetable(path: &Path, frame_size: Option<usize>) -> io::Result<HarmonicCurves> {
+    let file = wav::read(path)?;
+    let samples = file.mono();
+    let frame_size = frame_size.unwrap_or_else(|| detect_frame_size(&file, samples.len()));
+    if frame_size < 2 || samples.len() < frame_size {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "File is shorter than one frame"));
+    }
+    Ok(analyze_frames(&samples, frame_size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::ChartType;
+    use crate::patch::RenderSettings;
+
+    #[test]
+    fn test_resample_frames() {
+        let frames = [0.0, 0.0, 1.0, 1.0, 2.0, 2.0];
+        assert_eq!(resample_frames(&frames, 2, 5), [0.0, 0.0, 0.5, 0.5, 1.0, 1.0, 1.5, 1.5, 2.0, 2.0]);
+        assert_eq!(resample_frames(&frames, 2, 2), [0.0, 0.0, 2.0, 2.0]);
+        assert_eq!(resample_frames(&frames, 2, 1), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_frames_are_single_cycles() {
+        let engine = SynthComputeEngine::new(RenderSettings::default());
+        engine.fill_constant_curve(0, 0.5, ChartType::Amp);
+        let options = WavetableOptions { frame_size: 256, ..Default::default() };
+        let samples = render_wavetable(&engine, &options);
+        assert_eq!(samples.len(), 256 * engine.patch().num_buckets());
+
+        // A pure fundamental: every frame is one cosine cycle starting at its peak
+        for frame in samples.chunks(256) {
+            assert!((frame[0] - 0.5).abs() < 1e-4);
+            assert!((frame[128] + 0.5).abs() < 1e-4);
+        }
+        let resampled = render_wavetable(&engine, &WavetableOptions { frames: Some(8), ..options });
+        assert_eq!(resampled.len(), 256 * 8);
+    }
+
+    #[test]
+    fn test_analyze_finds_harmonics() {
+        let frame_size = 256;
+        let frame = |amp: f32, phase: f32| -> Vec<f32> {
+            (0..frame_size)
+                .map(|t| {
+                    let x = TAU * t as f32 / frame_size as f32;
+                    amp * (x + phase).cos() + 0.25 * (3.0 * x).cos()
+                })
+                .collect()
+        };
+        let samples: Vec<f32> = [frame(0.5, 1.0), frame(0.7, 4.0)].concat();
+        let curves = analyze_frames(&samples, frame_size);
+        assert_eq!(curves.num_buckets(), 2);
+        assert!((curves.amplitudes[0][0] - 0.5).abs() < 1e-4 && (curves.phases[0][0] - 1.0).abs() < 1e-4);
+        assert!((curves.amplitudes[0][1] - 0.7).abs() < 1e-4 && (curves.phases[0][1] - 4.0).abs() < 1e-4);
+        assert!((curves.amplitudes[2][1] - 0.25).abs() < 1e-4);
+        assert!(curves.amplitudes[1][0] < 1e-4 && curves.phases[1][0] == 0.0);
+    }
+
+    #[test]
+    fn test_export_and_import_round_trip() {
+        let path = std::env::temp_dir().join(format!("lesynth_wavetable_import_test_{}.wav", std::process::id()));
+        let engine = SynthComputeEngine::new(RenderSettings::default());
+        engine.fill_constant_curve(0, 0.5, ChartType::Amp);
+        engine.fill_constant_curve(4, 0.25, ChartType::Amp);
+        engine.fill_constant_curve(4, 2.0, ChartType::Phase);
+        export_wavetable(&engine, &path, &WavetableOptions { frames: Some(3), ..Default::default() }).unwrap();
+
+        // The clm chunk splits the file into its three frames
+        let curves = import_wavetable(&path, None).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(curves.num_buckets(), 3);
+        assert!((curves.amplitudes[0][1] - 0.5).abs() < 1e-3);
+        assert!((curves.amplitudes[4][2] - 0.25).abs() < 1e-3);
+        assert!((curves.phases[4][0] - 2.0).abs() < 1e-3);
+        assert!(curves.amplitudes[1][0] < 1e-3);
+
+        engine.load_curves(&curves, |n, _| n < 8);
+        let patch = engine.patch();
+        assert_eq!(patch.num_buckets(), engine.shared_params.num_buckets());
+        assert!(patch.amplitudes[4].iter().all(|&amp| (amp - 0.25).abs() < 1e-3));
+    }
+
+    #[test]
+    fn test_single_cycle_file() {
+        let path = std::env::temp_dir().join(format!("lesynth_single_cycle_test_{}.wav", std::process::id()));
+        let cycle: Vec<f32> = (0..600).map(|t| 0.8 * (TAU * 2.0 * t as f32 / 600.0).sin()).collect();
+        let spec = WavSpec { channels: 1, sample_rate: 44100, format: SampleFormat::Int16 };
+        wav::write(&path, &spec, &cycle).unwrap();
+        let curves = import_wavetable(&path, None).unwrap();
+        assert_eq!(curves.num_buckets(), 1);
+        assert!((curves.amplitudes[1][0] - 0.8).abs() < 1e-3);
+        // A sine is a cosine delayed by a quarter turn
+        assert!((curves.phases[1][0] - 1.5 * std::f32::consts::PI).abs() < 1e-3);
+
+        assert_eq!(import_wavetable(&path, Some(1000)).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_export_writes_cycle_length() {
+        let path = std::env::temp_dir().join(format!("lesynth_wavetable_test_{}.wav", std::process::id()));
+        let engine = SynthComputeEngine::new(RenderSettings::default());
+        let frames = export_wavetable(&engine, &path, &WavetableOptions { frames: Some(4), ..Default::default() });
+        assert_eq!(frames.unwrap(), 4);
+
+        let bytes = std::fs::read(&path).unwrap();
+        let clm = b"<!>2048 00000000 wavetable (LeSynth)";
+        assert!(bytes.windows(clm.len()).any(|window| window == clm));
+        std::fs::remove_file(&path).unwrap();
+
+        let zero = WavetableOptions { frames: Some(0), ..Default::default() };
+        assert_eq!(export_wavetable(&engine, &path, &zero).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+}
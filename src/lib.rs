@@ -14,11 +14,28 @@
 
 mod constants;
 mod engine;
+#[cfg(feature = "plugin")]
 mod gui;
+pub mod io;
+mod offline;
 mod params;
+mod patch;
+#[cfg(feature = "plugin")]
 mod plugin;
+mod resynthesis;
 mod voice;
 
+// Synthesis engine usable without a plugin host
+pub use constants::{key_from_name, key_name, NUM_HARMONICS, NUM_KEYS, SAMPLE_RATE};
+pub use engine::{ChartType, FailureLog, SynthComputeEngine};
+pub use engine::streaming::MemoryUsage;
+pub use offline::{render_notes, NoteEvent, OfflineOptions};
+pub use params::{CurveType, GranularityLevel, PlaybackMode, RenderMethod};
+pub use patch::{HarmonicCurves, Patch, PatchError, RenderSettings, SineCurve};
+pub use resynthesis::{detect_fundamental, resynthesize, resynthesize_file, BucketMapping, Resynthesis, ResynthesisOptions};
+pub use voice::{KeySound, NoteId, Voice, VoiceBank, VoiceControls};
+
+#[cfg(feature = "plugin")]
 pub use plugin::LeSynth;
 
 #[cfg(all(debug_assertions, feature = "debug-logging"))]
@@ -66,4 +83,7 @@ pub fn init_logging() {
     // No-op when not in debug build with debug-logging feature
 }
 
+#[cfg(feature = "plugin")]
+nih_plug::nih_export_clap!(LeSynth);
+#[cfg(feature = "plugin")]
 nih_plug::nih_export_vst3!(LeSynth);
@@ -0,0 +1,191 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Rendering notes to audio without a plugin host.
+//!
+//! Blocks are processed like `LeSynth::process` does: queued sounds are applied and all notes
+//! of a block start at its beginning, then the voice bank is mixed sample by sample. Unlike
+//! in a host, keys are rendered whole rather than streamed, whatever the memory budget, and
+//! playback waits until every key it needs is rendered, so the output does not depend on how
+//! fast the machine is.
+
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::constants::{key_name, NUM_KEYS};
+use crate::engine::shared_params::BufferState;
+use crate::engine::{LockRecover, SynthComputeEngine};
+use crate::voice::VoiceBank;
+
+/// Note starting or ending at a sample position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoteEvent {
+    pub sample: usize,
+    pub key: usize,
+    pub on: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OfflineOptions {
+    // Samples per processing block, which quantizes note timing like a host buffer does
+    pub block_size: usize,
+    // Samples rendered after the last event, for release fades
+    pub tail: usize,
+    // How long to wait for the keys to be rendered
+    pub timeout: Duration,
+}
+
+impl Default for OfflineOptions {
+    fn default() -> Self {
+        Self {
+            block_size: 512,
+            tail: 44100,
+            timeout: Duration::from_secs(120),
+        }
+    }
+}
+
+/// Wait until `keys` are rendered and queued for the voice bank, applying its commands
+/// meanwhile so the queue can't fill up
+fn wait_for_keys(engine: &SynthComputeEngine, voice_bank: &mut VoiceBank, keys: &[usize], timeout: Duration) -> io::Result<()> {
+    let shared_params = &engine.shared_params;
+    let deadline = Instant::now() + timeout;
+    engine.start_workers();
+    loop {
+        voice_bank.apply_commands();
+        shared_params.flush_key_sounds();
+        let pending: Vec<usize> = {
+            let unpublished = shared_params.unpublished_sounds.lock_recover();
+            keys.iter()
+                .copied()
+                .filter(|&key| shared_params.job_queue.buffer_state(key) != BufferState::Clean || unpublished[key])
+                .collect()
+        };
+        if pending.is_empty() {
+            voice_bank.apply_commands();
+            return Ok(());
+        }
+        if let Some(&key) = pending.iter().find(|&&key| shared_params.job_queue.buffer_state(key) == BufferState::Failed) {
+            let reason = engine.render_failures().last().unwrap_or_default();
+            return Err(io::Error::other(format!("Rendering key {} failed: {}", key_name(key), reason)));
+        }
+        if Instant::now() >= deadline {
+            let names: Vec<String> = pending.iter().map(|&key| key_name(key)).collect();
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("Keys not rendered in time: {}", names.join(", ")),
+            ));
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+/// Play `events`, sorted by sample, through the engine's voice bank and return the mono mix
+pub fn render_notes(
+    engine: &SynthComputeEngine,
+    voice_bank: &mut VoiceBank,
+    events: &[NoteEvent],
+    options: &OfflineOptions,
+) -> io::Result<Vec<f32>> {
+    let mut keys: Vec<usize> = events.iter().filter(|e| e.on && e.key < NUM_KEYS).map(|e| e.key).collect();
+    keys.sort_unstable();
+    keys.dedup();
+    // Streamed chunks that aren't rendered in time would play as silence
+    engine.render_whole_keys();
+    wait_for_keys(engine, voice_bank, &keys, options.timeout)?;
+
+    let block_size = options.block_size.max(1);
+    let end = events.last().map_or(0, |event| event.sample + 1) + options.tail;
+    let mut output = Vec::with_capacity(end);
+    let mut events = events.iter().peekable();
+    while output.len() < end {
+        let block_end = (output.len() + block_size).min(end);
+        voice_bank.apply_commands();
+        while let Some(event) = events.next_if(|event| event.sample < block_end) {
+            if !event.on {
+                voice_bank.note_off(event.key);
+            } else if event.key < NUM_KEYS {
+                engine.note_played(event.key);
+                voice_bank.note_on(event.key);
+            }
+        }
+        while output.len() < block_end {
+            output.push(voice_bank.next_sample());
+        }
+        voice_bank.publish_activity();
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::ChartType;
+    use crate::patch::RenderSettings;
+    use crate::voice::KeySound;
+
+    fn engine_with_sound() -> (SynthComputeEngine, VoiceBank) {
+        let engine = SynthComputeEngine::new(RenderSettings::default());
+        engine.fill_constant_curve(0, 0.5, ChartType::Amp);
+        let voice_bank = engine.take_voice_bank().unwrap();
+        (engine, voice_bank)
+    }
+
+    #[test]
+    fn test_notes_start_at_block_boundaries() {
+        let (engine, mut voice_bank) = engine_with_sound();
+        let events = [
+            NoteEvent { sample: 300, key: 40, on: true },
+            NoteEvent { sample: 5000, key: 40, on: false },
+        ];
+        let options = OfflineOptions { block_size: 256, tail: 1000, ..Default::default() };
+        let output = render_notes(&engine, &mut voice_bank, &events, &options).unwrap();
+        assert_eq!(output.len(), 6001);
+
+        // Events apply at the start of their block, like in a host
+        assert!(output[..256].iter().all(|&sample| sample == 0.0));
+        assert!(output[256..300].iter().any(|&sample| sample != 0.0));
+        // and has faded out well before the end of the tail
+        assert!(output[5500..].iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn test_rendering_is_repeatable() {
+        let events = [NoteEvent { sample: 0, key: 30, on: true }, NoteEvent { sample: 3000, key: 52, on: true }];
+        let options = OfflineOptions { block_size: 64, tail: 2000, ..Default::default() };
+        let render = || {
+            let (engine, mut voice_bank) = engine_with_sound();
+            render_notes(&engine, &mut voice_bank, &events, &options).unwrap()
+        };
+        assert_eq!(render(), render());
+    }
+
+    #[test]
+    fn test_memory_budget_does_not_change_the_output() {
+        let events = [NoteEvent { sample: 0, key: 40, on: true }];
+        let options = OfflineOptions { block_size: 64, tail: 20_000, ..Default::default() };
+        let render = |memory_budget_mb| {
+            let engine = SynthComputeEngine::new(RenderSettings { memory_budget_mb, ..Default::default() });
+            engine.fill_constant_curve(0, 0.5, ChartType::Amp);
+            // A megabyte is too little for all keys, so the engine would stream them
+            assert_eq!(engine.shared_params.streaming(), memory_budget_mb == 1);
+            let mut voice_bank = engine.take_voice_bank().unwrap();
+            let output = render_notes(&engine, &mut voice_bank, &events, &options).unwrap();
+            // but plays fully rendered keys instead
+            assert!(matches!(engine.shared_params.key_sounds.lock_recover()[40], Some(KeySound::Buffer(_))));
+            output
+        };
+        assert_eq!(render(1), render(RenderSettings::default().memory_budget_mb));
+    }
+}
@@ -12,29 +12,35 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+#[cfg(feature = "plugin")]
 use nih_plug::prelude::*;
 
-#[derive(Debug, Clone, Copy, PartialEq, Enum)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "plugin", derive(Enum))]
 pub enum CurveType {
     Constant,
     Sine,
+    // Imported data kept with the plugin state, e.g. from a wavetable
+    Custom,
 }
 
 impl CurveType {
     // so we can write `for variant in CurveType::VARIANTS`
-    pub const VARIANTS: [CurveType; 2] = [
+    pub const VARIANTS: [CurveType; 3] = [
         CurveType::Constant,
         CurveType::Sine,
+        CurveType::Custom,
     ];
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Enum)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "plugin", derive(Enum))]
 pub enum GranularityLevel {
-    #[name = "0.1"]
+    #[cfg_attr(feature = "plugin", name = "0.1")]
     Low,
-    #[name = "0.5"] 
+    #[cfg_attr(feature = "plugin", name = "0.5")] 
     Medium,
-    #[name = "1.0"]
+    #[cfg_attr(feature = "plugin", name = "1.0")]
     High,
 }
 
@@ -70,15 +76,17 @@ mod tests {
 
     #[test]
     fn test_curve_type_variants() {
-        assert_eq!(CurveType::VARIANTS.len(), 2);
+        assert_eq!(CurveType::VARIANTS.len(), 3);
         assert_eq!(CurveType::VARIANTS[0], CurveType::Constant);
         assert_eq!(CurveType::VARIANTS[1], CurveType::Sine);
+        assert_eq!(CurveType::VARIANTS[2], CurveType::Custom);
     }
 
     #[test]
     fn test_curve_type_debug() {
         assert_eq!(format!("{:?}", CurveType::Constant), "Constant");
         assert_eq!(format!("{:?}", CurveType::Sine), "Sine");
+        assert_eq!(format!("{:?}", CurveType::Custom), "Custom");
     }
 
     #[test]
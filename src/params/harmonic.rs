@@ -13,6 +13,8 @@
 // limitations under the License.
 
 use nih_plug::prelude::*;
+use crate::engine::ChartType;
+use crate::patch::SineCurve;
 use super::{CurveType, GranularityLevel};
 
 /// A single harmonic's complete parameter set.
@@ -42,4 +44,29 @@ pub struct HarmonicParam {
     pub granularity_amp: EnumParam<GranularityLevel>,
     #[id = "granularity_phase"]
     pub granularity_phase: EnumParam<GranularityLevel>,
-}
\ No newline at end of file
+}
+
+impl HarmonicParam {
+    pub fn curve_type(&self, chart_type: ChartType) -> CurveType {
+        match chart_type {
+            ChartType::Amp => self.curve_type_amp.value(),
+            ChartType::Phase => self.curve_type_phase.value(),
+        }
+    }
+
+    /// Sine curve set up for the amplitude or phase of this harmonic
+    pub fn sine_curve(&self, chart_type: ChartType) -> SineCurve {
+        match chart_type {
+            ChartType::Amp => SineCurve {
+                amplitude: self.sine_curve_amp_amp.value(),
+                frequency: self.sine_curve_freq_amp.value(),
+                offset: self.curve_offset_amp.value(),
+            },
+            ChartType::Phase => SineCurve {
+                amplitude: self.sine_curve_amp_phase.value(),
+                frequency: self.sine_curve_freq_phase.value(),
+                offset: self.curve_offset_phase.value(),
+            },
+        }
+    }
+}
@@ -13,9 +13,17 @@
 // limitations under the License.
 
 pub mod curve_type;
+#[cfg(feature = "plugin")]
 pub mod harmonic;
+pub mod playback_mode;
+pub mod render_method;
+#[cfg(feature = "plugin")]
 pub mod synth_params;
 
 pub use curve_type::{CurveType, GranularityLevel};
+#[cfg(feature = "plugin")]
 pub use harmonic::HarmonicParam;
-pub use synth_params::LeSynthParams;
+pub use playback_mode::PlaybackMode;
+pub use render_method::RenderMethod;
+#[cfg(feature = "plugin")]
+pub use synth_params::{LeSynthParams, POLY_MOD_VOICE_GAIN, POLY_MOD_VOICE_PAN, POLY_MOD_VOICE_TUNING};
@@ -0,0 +1,54 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(feature = "plugin")]
+use nih_plug::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// What is rendered for the audio thread to play
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "plugin", derive(Enum))]
+pub enum PlaybackMode {
+    // A fully rendered buffer per key
+    #[cfg_attr(feature = "plugin", name = "Key Buffers")]
+    #[default]
+    KeyBuffers,
+    // Band-limited single-cycle frames per mip level, read at each key's pitch
+    #[cfg_attr(feature = "plugin", name = "Wavetable")]
+    Wavetable,
+}
+
+impl PlaybackMode {
+    pub const VARIANTS: [PlaybackMode; 2] = [
+        PlaybackMode::KeyBuffers,
+        PlaybackMode::Wavetable,
+    ];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_playback_mode_variants() {
+        assert_eq!(PlaybackMode::VARIANTS.len(), 2);
+        assert_eq!(PlaybackMode::VARIANTS[0], PlaybackMode::KeyBuffers);
+        assert_eq!(PlaybackMode::VARIANTS[1], PlaybackMode::Wavetable);
+    }
+
+    #[test]
+    fn test_playback_mode_default() {
+        assert_eq!(PlaybackMode::default(), PlaybackMode::KeyBuffers);
+    }
+}
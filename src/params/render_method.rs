@@ -0,0 +1,54 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(feature = "plugin")]
+use nih_plug::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// How key buffers are rendered from the harmonic data
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "plugin", derive(Enum))]
+pub enum RenderMethod {
+    // Sum every harmonic's sinusoid sample by sample
+    #[cfg_attr(feature = "plugin", name = "Direct Sum")]
+    #[default]
+    DirectSum,
+    // Build each bucket's cycle with an inverse FFT and resample it to the key's period
+    #[cfg_attr(feature = "plugin", name = "Inverse FFT")]
+    InverseFft,
+}
+
+impl RenderMethod {
+    pub const VARIANTS: [RenderMethod; 2] = [
+        RenderMethod::DirectSum,
+        RenderMethod::InverseFft,
+    ];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_method_variants() {
+        assert_eq!(RenderMethod::VARIANTS.len(), 2);
+        assert_eq!(RenderMethod::VARIANTS[0], RenderMethod::DirectSum);
+        assert_eq!(RenderMethod::VARIANTS[1], RenderMethod::InverseFft);
+    }
+
+    #[test]
+    fn test_render_method_default() {
+        assert_eq!(RenderMethod::default(), RenderMethod::DirectSum);
+    }
+}
@@ -12,12 +12,19 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use nih_plug::prelude::*;
 use nih_plug_egui::EguiState;
+use crate::engine::{RwLockRecover, SynthComputeEngine};
 
 use crate::constants::*;
-use super::{CurveType, GranularityLevel, HarmonicParam};
+use crate::patch::{HarmonicCurves, RenderSettings};
+use super::{CurveType, GranularityLevel, HarmonicParam, PlaybackMode, RenderMethod};
+
+// Ids the host uses to modulate the voice parameters of single notes
+pub const POLY_MOD_VOICE_GAIN: u32 = 0;
+pub const POLY_MOD_VOICE_PAN: u32 = 1;
+pub const POLY_MOD_VOICE_TUNING: u32 = 2;
 
 #[derive(Params)]
 pub struct LeSynthParams {
@@ -30,10 +37,64 @@ pub struct LeSynthParams {
     #[id = "num_buckets"]
     pub num_buckets: IntParam,
 
+    // Upper limit for background rendering threads, leaves the rest of the CPU to the host
+    #[id = "render_threads"]
+    pub render_threads: IntParam,
+
+    #[id = "render_method"]
+    pub render_method: EnumParam<RenderMethod>,
+
+    #[id = "playback_mode"]
+    pub playback_mode: EnumParam<PlaybackMode>,
+
+    // Key buffers that don't fit are rendered in chunks while played
+    #[id = "memory_budget"]
+    pub memory_budget: IntParam,
+
+    // Directory for rendered keys kept between sessions, `None` disables the cache
+    #[persist = "render-cache-dir"]
+    pub render_cache_dir: Arc<RwLock<Option<String>>>,
+
+    // Curves of the harmonics whose curve type is custom
+    #[persist = "custom-curves"]
+    pub custom_curves: Arc<RwLock<Option<HarmonicCurves>>>,
+
+    // Playback of each voice, which CLAP hosts can modulate per note
+    #[id = "voice_gain"]
+    pub voice_gain: FloatParam,
+
+    #[id = "voice_pan"]
+    pub voice_pan: FloatParam,
+
+    #[id = "voice_tuning"]
+    pub voice_tuning: FloatParam,
+
     #[nested(array, group = "harmonics")]
     pub harmonics: [HarmonicParam; NUM_HARMONICS],
 }
 
+impl LeSynthParams {
+    /// Render settings for the engine from the current parameter values
+    pub fn render_settings(&self) -> RenderSettings {
+        RenderSettings {
+            render_threads: self.render_threads.value().max(1) as usize,
+            render_method: self.render_method.value(),
+            playback_mode: self.playback_mode.value(),
+            memory_budget_mb: self.memory_budget.value().max(1) as usize,
+            render_cache_dir: self.render_cache_dir.read_recover().clone(),
+        }
+    }
+
+    /// Put the custom curves back into the engine for the harmonics set to use them
+    pub fn apply_custom_curves(&self, engine: &SynthComputeEngine) {
+        if let Some(curves) = self.custom_curves.read_recover().as_ref() {
+            engine.load_curves(curves, |n, chart_type| {
+                self.harmonics.get(n).is_some_and(|harmonic| harmonic.curve_type(chart_type) == CurveType::Custom)
+            });
+        }
+    }
+}
+
 impl Default for LeSynthParams {
     fn default() -> Self {
         // your old "make_param" defaults for amplitude:
@@ -125,6 +186,55 @@ impl Default for LeSynthParams {
                     max: NUM_OF_BUCKETS_MAX,
                 },
             ),
+            render_threads: IntParam::new(
+                "Render Threads",
+                RENDER_THREADS_DEFAULT,
+                IntRange::Linear {
+                    min: RENDER_THREADS_MIN,
+                    max: RENDER_THREADS_MAX,
+                },
+            ),
+            render_method: EnumParam::new("Render Method", RenderMethod::default()),
+            playback_mode: EnumParam::new("Playback Mode", PlaybackMode::default()),
+            memory_budget: IntParam::new(
+                "Memory Budget",
+                MEMORY_BUDGET_MB_DEFAULT,
+                IntRange::Linear {
+                    min: MEMORY_BUDGET_MB_MIN,
+                    max: MEMORY_BUDGET_MB_MAX,
+                },
+            )
+            .with_unit(" MB"),
+            render_cache_dir: Arc::new(RwLock::new(None)),
+            custom_curves: Arc::new(RwLock::new(None)),
+            voice_gain: FloatParam::new(
+                "Voice Gain",
+                util::db_to_gain(0.0),
+                FloatRange::Skewed {
+                    min: util::db_to_gain(VOICE_GAIN_MIN_DB),
+                    max: util::db_to_gain(VOICE_GAIN_MAX_DB),
+                    factor: FloatRange::gain_skew_factor(VOICE_GAIN_MIN_DB, VOICE_GAIN_MAX_DB),
+                },
+            )
+            .with_poly_modulation_id(POLY_MOD_VOICE_GAIN)
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_gain_to_db(1))
+            .with_string_to_value(formatters::s2v_f32_gain_to_db()),
+            voice_pan: FloatParam::new("Voice Pan", 0.0, FloatRange::Linear { min: -1.0, max: 1.0 })
+                .with_poly_modulation_id(POLY_MOD_VOICE_PAN)
+                .with_value_to_string(formatters::v2s_f32_panning())
+                .with_string_to_value(formatters::s2v_f32_panning()),
+            voice_tuning: FloatParam::new(
+                "Voice Tuning",
+                0.0,
+                FloatRange::Linear {
+                    min: -VOICE_TUNING_RANGE,
+                    max: VOICE_TUNING_RANGE,
+                },
+            )
+            .with_poly_modulation_id(POLY_MOD_VOICE_TUNING)
+            .with_step_size(0.01)
+            .with_unit(" st"),
             harmonics,
         }
     }
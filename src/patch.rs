@@ -0,0 +1,309 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Plain description of a sound, independent of any plugin host.
+//!
+//! The plugin builds these from its parameters; other front ends create or load them directly
+//! and hand them to the engine.
+
+use std::f32::consts::{PI, TAU};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::constants::{
+    MEMORY_BUDGET_MB_DEFAULT, NUM_HARMONICS, NUM_OF_BUCKETS_DEFAULT, NUM_OF_BUCKETS_MAX, NUM_OF_BUCKETS_MIN,
+    RENDER_THREADS_DEFAULT,
+};
+use crate::engine::ChartType;
+use crate::params::{PlaybackMode, RenderMethod};
+
+/// How keys are rendered and played
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderSettings {
+    // Upper limit for background rendering threads, one core is always left free
+    pub render_threads: usize,
+    pub render_method: RenderMethod,
+    pub playback_mode: PlaybackMode,
+    // Key buffers that don't fit are rendered in chunks while played
+    pub memory_budget_mb: usize,
+    // Directory for rendered keys kept between sessions, `None` disables the cache
+    pub render_cache_dir: Option<String>,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            render_threads: RENDER_THREADS_DEFAULT as usize,
+            render_method: RenderMethod::default(),
+            playback_mode: PlaybackMode::default(),
+            memory_budget_mb: MEMORY_BUDGET_MB_DEFAULT as usize,
+            render_cache_dir: None,
+        }
+    }
+}
+
+/// Sine curve over the buckets of a harmonic, clamped to `0.0..=1.0`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SineCurve {
+    pub amplitude: f32,
+    // Radians per bucket
+    pub frequency: f32,
+    pub offset: f32,
+}
+
+impl SineCurve {
+    pub fn value(&self, bucket: usize) -> f32 {
+        (self.amplitude * (self.frequency * bucket as f32).sin() + self.offset).clamp(0.0, 1.0)
+    }
+}
+
+/// Amplitude and phase curves of every harmonic that don't come from curve parameters,
+/// e.g. analysed from a wavetable
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HarmonicCurves {
+    // [harmonic][bucket]
+    pub amplitudes: Vec<Vec<f32>>,
+    // [harmonic][bucket], radians in `0.0..TAU`
+    pub phases: Vec<Vec<f32>>,
+}
+
+impl HarmonicCurves {
+    pub fn num_buckets(&self) -> usize {
+        self.amplitudes.first().map_or(0, Vec::len)
+    }
+
+    /// Curves stretched or squeezed to `num_buckets` by linear interpolation, phases along the
+    /// shorter way around the circle
+    pub fn resampled(&self, num_buckets: usize) -> Self {
+        Self {
+            amplitudes: self.amplitudes.iter().map(|row| resample_curve(row, num_buckets, false)).collect(),
+            phases: self.phases.iter().map(|row| resample_curve(row, num_buckets, true)).collect(),
+        }
+    }
+}
+
+/// Linear interpolation of `values` at `len` evenly spaced points from the first to the last
+pub fn resample_curve(values: &[f32], len: usize, is_phase: bool) -> Vec<f32> {
+    if values.len() == len || values.is_empty() {
+        return values.iter().copied().chain(std::iter::repeat(0.0)).take(len).collect();
+    }
+    (0..len)
+        .map(|i| {
+            let position = if len > 1 { i as f32 * (values.len() - 1) as f32 / (len - 1) as f32 } else { 0.0 };
+            let index = (position as usize).min(values.len() - 1);
+            let (a, b) = (values[index], values[(index + 1).min(values.len() - 1)]);
+            let frac = position - index as f32;
+            if is_phase {
+                let delta = (b - a + PI).rem_euclid(TAU) - PI;
+                (a + delta * frac).rem_euclid(TAU)
+            } else {
+                a + (b - a) * frac
+            }
+        })
+        .collect()
+}
+
+/// Harmonic data of a sound and the settings to render it with, stored as JSON in preset files
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Patch {
+    // [harmonic][bucket]
+    pub amplitudes: Vec<Vec<f32>>,
+    pub phases: Vec<Vec<f32>>,
+    pub amplitude_enabled: Vec<bool>,
+    pub phase_enabled: Vec<bool>,
+    pub render: RenderSettings,
+}
+
+impl Default for Patch {
+    fn default() -> Self {
+        Self::new(NUM_OF_BUCKETS_DEFAULT)
+    }
+}
+
+impl Patch {
+    /// Silent patch with every harmonic enabled
+    pub fn new(num_buckets: usize) -> Self {
+        Self {
+            amplitudes: vec![vec![0.0; num_buckets]; NUM_HARMONICS],
+            phases: vec![vec![0.0; num_buckets]; NUM_HARMONICS],
+            amplitude_enabled: vec![true; NUM_HARMONICS],
+            phase_enabled: vec![true; NUM_HARMONICS],
+            render: RenderSettings::default(),
+        }
+    }
+
+    pub fn num_buckets(&self) -> usize {
+        self.amplitudes.first().map_or(0, Vec::len)
+    }
+
+    /// Amplitude or phase curve of a harmonic
+    pub fn curve_mut(&mut self, harmonic: usize, chart_type: ChartType) -> &mut [f32] {
+        match chart_type {
+            ChartType::Amp => &mut self.amplitudes[harmonic],
+            ChartType::Phase => &mut self.phases[harmonic],
+        }
+    }
+
+    /// Check that the data has one row per harmonic and the same number of buckets in each
+    pub fn validate(&self) -> Result<(), PatchError> {
+        let counts = [
+            self.amplitudes.len(),
+            self.phases.len(),
+            self.amplitude_enabled.len(),
+            self.phase_enabled.len(),
+        ];
+        if let Some(&found) = counts.iter().find(|&&count| count != NUM_HARMONICS) {
+            return Err(PatchError::HarmonicCount { expected: NUM_HARMONICS, found });
+        }
+        let expected = self.num_buckets();
+        if expected == 0 {
+            return Err(PatchError::NoBuckets);
+        }
+        // The same range as the bucket count parameter
+        let (min, max) = (NUM_OF_BUCKETS_MIN as usize, NUM_OF_BUCKETS_MAX as usize);
+        if !(min..=max).contains(&expected) {
+            return Err(PatchError::BucketRange { found: expected, min, max });
+        }
+        for (harmonic, (amplitudes, phases)) in self.amplitudes.iter().zip(&self.phases).enumerate() {
+            if let Some(found) = [amplitudes.len(), phases.len()].into_iter().find(|&len| len != expected) {
+                return Err(PatchError::BucketCount { harmonic, expected, found });
+            }
+        }
+        Ok(())
+    }
+
+    /// Read and validate a preset file
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let patch: Patch = serde_json::from_slice(&fs::read(path)?)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        patch.validate().map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        Ok(patch)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_vec_pretty(self).map_err(io::Error::other)?;
+        fs::write(path, json)
+    }
+}
+
+/// Reason a patch can't be loaded
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatchError {
+    HarmonicCount { expected: usize, found: usize },
+    BucketCount { harmonic: usize, expected: usize, found: usize },
+    NoBuckets,
+    BucketRange { found: usize, min: usize, max: usize },
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchError::HarmonicCount { expected, found } => {
+                write!(f, "expected {} harmonics, found {}", expected, found)
+            }
+            PatchError::BucketCount { harmonic, expected, found } => {
+                write!(f, "harmonic {} has {} buckets, expected {}", harmonic + 1, found, expected)
+            }
+            PatchError::NoBuckets => write!(f, "patch has no buckets"),
+            PatchError::BucketRange { found, min, max } => {
+                write!(f, "patch has {} buckets, expected {} to {}", found, min, max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sine_curve_is_clamped() {
+        let curve = SineCurve { amplitude: 0.8, frequency: std::f32::consts::FRAC_PI_2, offset: 0.5 };
+        assert_eq!(curve.value(0), 0.5);
+        assert_eq!(curve.value(1), 1.0);
+        assert!((curve.value(2) - 0.5).abs() < 1e-6);
+        assert_eq!(curve.value(3), 0.0);
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut patch = Patch::new(40);
+        assert_eq!(patch.validate(), Ok(()));
+        assert_eq!(patch.num_buckets(), 40);
+
+        patch.phases[3].pop();
+        assert_eq!(patch.validate(), Err(PatchError::BucketCount { harmonic: 3, expected: 40, found: 39 }));
+
+        patch.amplitude_enabled.pop();
+        assert_eq!(
+            patch.validate(),
+            Err(PatchError::HarmonicCount { expected: NUM_HARMONICS, found: NUM_HARMONICS - 1 })
+        );
+        assert_eq!(Patch::new(0).validate(), Err(PatchError::NoBuckets));
+
+        // Bucket counts the parameter can't hold
+        let (min, max) = (NUM_OF_BUCKETS_MIN as usize, NUM_OF_BUCKETS_MAX as usize);
+        assert_eq!(Patch::new(min).validate(), Ok(()));
+        assert_eq!(Patch::new(max).validate(), Ok(()));
+        assert_eq!(Patch::new(min - 1).validate(), Err(PatchError::BucketRange { found: min - 1, min, max }));
+        assert_eq!(Patch::new(max + 1).validate(), Err(PatchError::BucketRange { found: max + 1, min, max }));
+    }
+
+    #[test]
+    fn test_resample_curves() {
+        assert_eq!(resample_curve(&[0.0, 1.0], 5, false), [0.0, 0.25, 0.5, 0.75, 1.0]);
+        assert_eq!(resample_curve(&[0.0, 0.5, 1.0], 2, false), [0.0, 1.0]);
+        assert_eq!(resample_curve(&[0.4], 3, false), [0.4, 0.4, 0.4]);
+        assert_eq!(resample_curve(&[], 2, false), [0.0, 0.0]);
+
+        // Phases wrap instead of sweeping through the whole circle
+        let phases = resample_curve(&[TAU - 0.2, 0.2], 3, true);
+        assert!((phases[0] - (TAU - 0.2)).abs() < 1e-5);
+        assert!(phases[1] < 1e-5 || phases[1] > TAU - 1e-5);
+        assert!((phases[2] - 0.2).abs() < 1e-5);
+
+        let curves = HarmonicCurves { amplitudes: vec![vec![0.0, 1.0]; 2], phases: vec![vec![1.0, 2.0]; 2] };
+        let resampled = curves.resampled(3);
+        assert_eq!(resampled.num_buckets(), 3);
+        assert!((resampled.phases[1][1] - 1.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = std::env::temp_dir().join(format!("lesynth_patch_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("patch.json");
+
+        let mut patch = Patch::new(30);
+        patch.amplitudes[0] = (0..30).map(|bucket| 1.0 - bucket as f32 / 30.0).collect();
+        patch.phase_enabled[2] = false;
+        patch.render.render_method = RenderMethod::InverseFft;
+        patch.save(&path).unwrap();
+        assert_eq!(Patch::load(&path).unwrap(), patch);
+
+        // Invalid data is rejected
+        patch.phases[1].clear();
+        patch.save(&path).unwrap();
+        assert_eq!(Patch::load(&path).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        fs::write(&path, "{").unwrap();
+        assert_eq!(Patch::load(&path).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
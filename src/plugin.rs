@@ -13,7 +13,7 @@
 // limitations under the License.
 
 use std::num::NonZeroU32;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use nih_plug::prelude::*;
 use nih_plug_egui::{
     create_egui_editor,
@@ -21,14 +21,28 @@ use nih_plug_egui::{
 };
 
 use crate::constants::*;
-use crate::engine::{ChartType, SynthComputeEngine};
-use crate::gui::{draw_assembled_chart, draw_curve_controls, draw_harmonic_plot, draw_piano_keyboard};
-use crate::params::LeSynthParams;
-use crate::voice::Voice;
+use crate::engine::streaming::BYTES_PER_MB;
+use crate::engine::{ChartType, LockRecover, RwLockRecover, SynthComputeEngine};
+use crate::gui::{
+    draw_assembled_chart, draw_curve_controls, draw_harmonic_plot, draw_image_import, draw_output_scope,
+    draw_piano_keyboard, draw_spectrogram, HarmonicView,
+};
+use crate::io::matrix;
+use crate::io::sfz::{every_nth_key, export_sfz, SfzOptions};
+use crate::io::wav::SampleFormat;
+use crate::io::wavetable::{self, import_wavetable, WavetableOptions};
+use crate::params::{
+    CurveType, LeSynthParams, PlaybackMode, RenderMethod, POLY_MOD_VOICE_GAIN, POLY_MOD_VOICE_PAN, POLY_MOD_VOICE_TUNING,
+};
+use crate::patch::{HarmonicCurves, Patch};
+use crate::resynthesis::{resynthesize_file, BucketMapping, ResynthesisOptions};
+use crate::voice::{NoteId, Voice, VoiceBank, VoiceControls};
 
 pub struct LeSynth {
     synth_params: Arc<LeSynthParams>,
     pub synth_compute_engine: Arc<SynthComputeEngine>,
+    // Owned by the audio thread; fed by the editor and the render thread through queues
+    voice_bank: VoiceBank,
 }
 
 impl Default for LeSynth {
@@ -36,9 +50,14 @@ impl Default for LeSynth {
         crate::init_logging();
         
         let synth_params = Arc::new(LeSynthParams::default());
+        let synth_compute_engine = Arc::new(SynthComputeEngine::new(synth_params.render_settings()));
+        let voice_bank = synth_compute_engine
+            .take_voice_bank()
+            .expect("voice bank of a new engine is available");
         Self {
-            synth_params: synth_params.clone(),
-            synth_compute_engine: Arc::new(SynthComputeEngine::new(synth_params)),
+            synth_params,
+            synth_compute_engine,
+            voice_bank,
         }
     }
 }
@@ -66,127 +85,105 @@ impl Plugin for LeSynth {
         self.synth_params.clone()
     }
 
+    fn initialize(
+        &mut self,
+        _audio_io_layout: &AudioIOLayout,
+        buffer_config: &BufferConfig,
+        _context: &mut impl InitContext<Self>,
+    ) -> bool {
+        self.synth_compute_engine.set_sample_rate(buffer_config.sample_rate);
+        // Pick up render settings, the bucket count and imported curves restored from the saved state
+        self.synth_compute_engine.set_render_settings(self.synth_params.render_settings());
+        self.synth_compute_engine.set_num_buckets(self.synth_params.num_buckets.value() as usize);
+        self.synth_params.apply_custom_curves(&self.synth_compute_engine);
+        // Resume rendering key buffers after a previous deactivation
+        self.synth_compute_engine.start_workers();
+        self.synth_compute_engine.supervise_workers();
+        true
+    }
+
+    fn deactivate(&mut self) {
+        // No audio is produced while deactivated, so stop using CPU for rendering
+        self.synth_compute_engine.stop_workers();
+    }
+
     fn process(
         &mut self,
         buffer: &mut Buffer,
         _aux: &mut AuxiliaryBuffers,
         context: &mut impl ProcessContext<Self>,
     ) -> ProcessStatus {
+        // Nothing in here may lock, allocate or render: buffers and notes from the editor and
+        // the render thread arrive through the voice bank's queues
         let shared = &self.synth_compute_engine.shared_params;
-        let fade_duration = shared.fade_duration;
+        self.voice_bank.apply_commands();
 
-        // --- Handle incoming MIDI events (build/stop voices) ---
+        // --- Handle incoming MIDI events (start/stop voices) ---
         while let Some(event) = context.next_event() {
             match event {
-                NoteEvent::NoteOn { note, .. } => {
+                NoteEvent::NoteOn { note, channel, voice_id, .. } => {
                     let key_idx = note as usize;
                     if key_idx < NUM_KEYS {
-                        // Get pre-computed buffer or compute synchronously as fallback
-                        let buf = self.synth_compute_engine.get_buffer_for_key(key_idx);
-                        let mut voices = shared.voices.lock().unwrap();
-                        voices[key_idx] = Some(Voice {
-                            buffer: buf,
-                            idx: 0,
-                            fade_in_active: true,
-                            fade_in_pos: 0,
-                            fade_out_active: false,
-                            fade_out_pos: 0,
-                        });
+                        self.synth_compute_engine.note_played(key_idx);
+                        // Hosts without voice ids still get a stable id per key and channel
+                        let id = voice_id.unwrap_or_else(|| fallback_voice_id(note, channel));
+                        self.voice_bank.note_on_voice(key_idx, Some(NoteId { id, channel }));
                     }
                 }
                 NoteEvent::NoteOff { note, .. } => {
-                    let key_idx = note as usize;
-                    if key_idx < NUM_KEYS {
-                        let mut voices = shared.voices.lock().unwrap();
-                        if let Some(v) = voices[key_idx].as_mut() {
-                            v.fade_out_active = true;
-                            v.fade_out_pos = 0;
-                        }
+                    self.voice_bank.note_off(note as usize);
+                }
+                // Note expressions
+                NoteEvent::PolyTuning { voice_id, note, tuning, .. } => {
+                    if let Some(voice) = self.expression_voice(voice_id, note) {
+                        voice.expression.tuning = tuning;
+                    }
+                }
+                NoteEvent::PolyVolume { voice_id, note, gain, .. } => {
+                    if let Some(voice) = self.expression_voice(voice_id, note) {
+                        voice.expression.gain = gain;
+                    }
+                }
+                NoteEvent::PolyPan { voice_id, note, pan, .. } => {
+                    if let Some(voice) = self.expression_voice(voice_id, note) {
+                        voice.expression.pan = pan;
                     }
                 }
+                NoteEvent::PolyModulation { voice_id, poly_modulation_id, normalized_offset, .. } => {
+                    let voice = self.voice_bank.key_of_voice(voice_id).and_then(|key| self.voice_bank.voice_mut(key));
+                    if let Some(offset) = voice.and_then(|v| v.modulation.get_mut(poly_modulation_id as usize)) {
+                        *offset = Some(normalized_offset);
+                    }
+                }
+                // Monophonic changes arrive as parameter values, picked up below
                 _ => {}
             }
         }
+        self.update_voice_controls();
 
         // --- Mixdown all active voices into the output buffer with headroom ---
-        {
-            let mut voices = shared.voices.lock().unwrap();
-
-            for mut frame in buffer.iter_samples() {
-                // Count active voices this frame (cheap; keeps headroom stable)
-                let active_count = voices.iter().filter(|o| o.is_some()).count();
-                
-                // Per-voice scaling with safe loudness compensation
-                // Scale each voice down, then boost final mix carefully to avoid clipping
-                let (voice_gain, master_gain) = if active_count > 0 {
-                    let n = active_count as f32;
-                    // Each voice gets 1/N scaling to prevent clipping
-                    let voice_scaling = 0.8 / n;  // More conservative base scaling
-                    // Safer loudness compensation that won't exceed ±1.0
-                    let loudness_compensation = match active_count {
-                        1 => 1.0,   // Single voice: 0.8 * 1.0 = 0.8
-                        2 => 1.5,   // 2 voices: 0.4 * 1.5 = 0.6  
-                        3 => 2.0,   // 3 voices: 0.267 * 2.0 = 0.53
-                        4 => 2.4,   // 4 voices: 0.2 * 2.4 = 0.48
-                        5 => 2.8,   // 5 voices: 0.16 * 2.8 = 0.45
-                        _ => 3.0,   // 6+ voices: 0.133 * 3.0 = 0.4 max
-                    };
-                    (voice_scaling, loudness_compensation)
-                } else {
-                    (1.0, 1.0)
-                };
-
-                let mut mixed = 0.0f32;
-
-                for opt in voices.iter_mut() {
-                    if let Some(v) = opt.as_mut() {
-                        let len = v.buffer.len();
-                        if len == 0 {
-                            continue;
-                        }
+        for mut frame in buffer.iter_samples() {
+            let mixed = self.voice_bank.next_frame();
 
-                        let mut s = v.buffer[v.idx % len];
+            // Publish the final mix for the editor's oscilloscope and spectrum analyzer
+            shared.output_tap.push(0.5 * (mixed[0] + mixed[1]));
 
-                        // Apply per-voice scaling FIRST to prevent intermediate clipping
-                        s *= voice_gain;
-
-                        // Fade in
-                        if v.fade_in_active && v.fade_in_pos < fade_duration {
-                            let g = v.fade_in_pos as f32 / fade_duration as f32;
-                            s *= g;
-                            v.fade_in_pos += 1;
-                        } else {
-                            v.fade_in_active = false;
-                        }
-
-                        // Fade out
-                        if v.fade_out_active {
-                            if v.fade_out_pos < fade_duration {
-                                let g = 1.0 - (v.fade_out_pos as f32 / fade_duration as f32);
-                                s *= g;
-                                v.fade_out_pos += 1;
-                            } else {
-                                // Voice finished after fade; remove it
-                                *opt = None;
-                                continue;
-                            }
-                        }
-
-                        mixed += s;
-                        v.idx = v.idx.wrapping_add(1);
-                    }
-                }
-
-                // Apply loudness compensation
-                mixed *= master_gain;
+            for (sample, mixed) in frame.iter_mut().zip(mixed) {
+                *sample = mixed;
+            }
+        }
 
-                // Final clamp - should rarely trigger now
-                mixed = mixed.clamp(-1.0, 1.0);
+        self.voice_bank.publish_activity();
 
-                for (_, sample) in frame.iter_mut().enumerate() {
-                    *sample = mixed;
-                }
-            }
+        // Voices the host may still modulate have to be reported when they end
+        let timing = buffer.samples().saturating_sub(1) as u32;
+        for (key, note_id) in self.voice_bank.drain_terminated() {
+            context.send_event(NoteEvent::VoiceTerminated {
+                timing,
+                voice_id: Some(note_id.id),
+                channel: note_id.channel,
+                note: key as u8,
+            });
         }
 
         ProcessStatus::Normal
@@ -220,24 +217,11 @@ impl Plugin for LeSynth {
                 });
                 egui::CentralPanel::default().show(egui_ctx, |ui| {
                         let params_changed_action = || {
-                            synth_compute_engine.set_normalization_needed(true);
-
-                            // Rebuild buffers for currently active voices so changes are audible immediately
-                            {
-                                let shared = &synth_compute_engine.shared_params;
-                                let mut voices = shared.voices.lock().unwrap();
-                                for (key_idx, slot) in voices.iter_mut().enumerate() {
-                                    if let Some(v) = slot.as_mut() {
-                                        let buf = synth_compute_engine
-                                            .get_buffer_for_key(key_idx);
-                                        v.buffer = buf;
-                                        // keep current idx and fade states
-                                    }
-                                }
-                            }
+                            // Active voices switch to the new buffers as soon as the render thread
+                            // publishes them; sounding keys are rendered first
 
-                            // Update assembled chart with key 24 for immediate preview
-                            synth_compute_engine.update_assembled_chart_with_key24();
+                            // Update assembled chart for immediate preview
+                            synth_compute_engine.update_assembled_chart_preview();
                         };
 
                         // Keep original structure but make it responsive
@@ -255,6 +239,7 @@ impl Plugin for LeSynth {
                                         ChartType::Amp,
                                         harmonic,
                                         synth_compute_engine.clone(),
+                                        &synth_params.custom_curves,
                                         setter,
                                         &params_changed_action,
                                         MIN_OFFSET_AMP,
@@ -270,6 +255,7 @@ impl Plugin for LeSynth {
                                         ChartType::Phase,
                                         harmonic,
                                         synth_compute_engine.clone(),
+                                        &synth_params.custom_curves,
                                         setter,
                                         &params_changed_action,
                                         MIN_OFFSET_PHASE,
@@ -298,30 +284,561 @@ impl Plugin for LeSynth {
 
                         ui.add_space(10.0);
 
+                        let harmonic_view_id = egui::Id::new("harmonic_view");
+                        let heatmap_db_scale_id = egui::Id::new("heatmap_db_scale");
+                        let mut harmonic_view = egui_ctx.memory(|mem| {
+                            mem.data.get_temp::<HarmonicView>(harmonic_view_id).unwrap_or_default()
+                        });
+                        let mut heatmap_db_scale = egui_ctx.memory(|mem| {
+                            mem.data.get_temp::<bool>(heatmap_db_scale_id).unwrap_or(false)
+                        });
+
+                        ui.horizontal(|ui| {
+                            ui.label("Harmonic view:");
+                            for variant in HarmonicView::VARIANTS {
+                                ui.selectable_value(&mut harmonic_view, variant, format!("{:?}", variant));
+                            }
+                            if harmonic_view == HarmonicView::Heatmap {
+                                ui.checkbox(&mut heatmap_db_scale, "dB amplitude scale");
+                            }
+                        });
+
+                        egui_ctx.memory_mut(|mem| {
+                            mem.data.insert_temp(harmonic_view_id, harmonic_view);
+                            mem.data.insert_temp(heatmap_db_scale_id, heatmap_db_scale);
+                        });
+
                         let available = ui.available_size();
                         let gutter = 10.0;
                         let chart_w = (available.x - gutter) * 0.5;
                         let chart_h = (available.y * 0.4).max(200.0);
 
-                        ui.columns(2, |columns| {
-                            draw_harmonic_plot(
-                                &mut columns[0],
-                                "Amplitude",
-                                ChartType::Amp,
-                                chart_w,
-                                chart_h,
-                                &synth_compute_engine,
+                        ui.columns(2, |columns| match harmonic_view {
+                            HarmonicView::Lines => {
+                                draw_harmonic_plot(
+                                    &mut columns[0],
+                                    "Amplitude",
+                                    ChartType::Amp,
+                                    chart_w,
+                                    chart_h,
+                                    &synth_compute_engine,
+                                );
+                                draw_harmonic_plot(
+                                    &mut columns[1],
+                                    "Phase",
+                                    ChartType::Phase,
+                                    chart_w,
+                                    chart_h,
+                                    &synth_compute_engine,
+                                );
+                            }
+                            HarmonicView::Heatmap => {
+                                draw_spectrogram(
+                                    &mut columns[0],
+                                    "Amplitude",
+                                    ChartType::Amp,
+                                    chart_w,
+                                    chart_h,
+                                    heatmap_db_scale,
+                                    &synth_compute_engine,
+                                );
+                                draw_spectrogram(
+                                    &mut columns[1],
+                                    "Phase",
+                                    ChartType::Phase,
+                                    chart_w,
+                                    chart_h,
+                                    false,
+                                    &synth_compute_engine,
+                                );
+                            }
+                        });
+
+                        ui.add_space(10.0);
+
+                        ui.horizontal(|ui| {
+                            let param = &synth_params.render_threads;
+                            let slider = egui::Slider::from_get_set(
+                                RENDER_THREADS_MIN as f64..=RENDER_THREADS_MAX as f64,
+                                |new_val| {
+                                    if let Some(v) = new_val {
+                                        setter.begin_set_parameter(param);
+                                        setter.set_parameter(param, v as i32);
+                                        setter.end_set_parameter(param);
+                                        v
+                                    } else {
+                                        param.value() as f64
+                                    }
+                                },
+                            )
+                            .integer()
+                            .text("Render threads");
+                            ui.add(slider);
+                            ui.label(format!("({} running)", synth_compute_engine.worker_count()));
+
+                            ui.add_space(15.0);
+                            let param = &synth_params.render_method;
+                            egui::ComboBox::from_label("Render method")
+                                .selected_text(format!("{:?}", param.value()))
+                                .show_ui(ui, |ui| {
+                                    for &variant in RenderMethod::VARIANTS.iter() {
+                                        if ui
+                                            .selectable_label(param.value() == variant, format!("{:?}", variant))
+                                            .clicked()
+                                        {
+                                            setter.begin_set_parameter(param);
+                                            setter.set_parameter(param, variant);
+                                            setter.end_set_parameter(param);
+                                        }
+                                    }
+                                });
+
+                            ui.add_space(15.0);
+                            let param = &synth_params.playback_mode;
+                            egui::ComboBox::from_label("Playback mode")
+                                .selected_text(format!("{:?}", param.value()))
+                                .show_ui(ui, |ui| {
+                                    for &variant in PlaybackMode::VARIANTS.iter() {
+                                        if ui
+                                            .selectable_label(param.value() == variant, format!("{:?}", variant))
+                                            .clicked()
+                                        {
+                                            setter.begin_set_parameter(param);
+                                            setter.set_parameter(param, variant);
+                                            setter.end_set_parameter(param);
+                                        }
+                                    }
+                                });
+                        });
+
+                        ui.horizontal(|ui| {
+                            // Per-voice parameters, which CLAP hosts can also modulate per note
+                            let param = &synth_params.voice_gain;
+                            let slider = egui::Slider::from_get_set(
+                                VOICE_GAIN_MIN_DB as f64..=VOICE_GAIN_MAX_DB as f64,
+                                |new_val| {
+                                    if let Some(v) = new_val {
+                                        setter.begin_set_parameter(param);
+                                        setter.set_parameter(param, util::db_to_gain(v as f32));
+                                        setter.end_set_parameter(param);
+                                        v
+                                    } else {
+                                        util::gain_to_db(param.value()) as f64
+                                    }
+                                },
+                            )
+                            .suffix(" dB")
+                            .text("Voice gain");
+                            ui.add(slider);
+
+                            ui.add_space(15.0);
+                            let param = &synth_params.voice_pan;
+                            let slider = egui::Slider::from_get_set(-1.0..=1.0, |new_val| {
+                                if let Some(v) = new_val {
+                                    setter.begin_set_parameter(param);
+                                    setter.set_parameter(param, v as f32);
+                                    setter.end_set_parameter(param);
+                                    v
+                                } else {
+                                    param.value() as f64
+                                }
+                            })
+                            .text("Pan");
+                            ui.add(slider);
+
+                            ui.add_space(15.0);
+                            let param = &synth_params.voice_tuning;
+                            let slider = egui::Slider::from_get_set(
+                                -VOICE_TUNING_RANGE as f64..=VOICE_TUNING_RANGE as f64,
+                                |new_val| {
+                                    if let Some(v) = new_val {
+                                        setter.begin_set_parameter(param);
+                                        setter.set_parameter(param, v as f32);
+                                        setter.end_set_parameter(param);
+                                        v
+                                    } else {
+                                        param.value() as f64
+                                    }
+                                },
+                            )
+                            .suffix(" st")
+                            .text("Tuning");
+                            ui.add(slider);
+                        });
+
+                        ui.horizontal(|ui| {
+                            let param = &synth_params.memory_budget;
+                            let slider = egui::Slider::from_get_set(
+                                MEMORY_BUDGET_MB_MIN as f64..=MEMORY_BUDGET_MB_MAX as f64,
+                                |new_val| {
+                                    if let Some(v) = new_val {
+                                        setter.begin_set_parameter(param);
+                                        setter.set_parameter(param, v as i32);
+                                        setter.end_set_parameter(param);
+                                        v
+                                    } else {
+                                        param.value() as f64
+                                    }
+                                },
+                            )
+                            .integer()
+                            .logarithmic(true)
+                            .suffix(" MB")
+                            .text("Memory budget");
+                            ui.add(slider);
+
+                            let usage = synth_compute_engine.memory_usage();
+                            let megabytes = |bytes: usize| bytes as f64 / BYTES_PER_MB as f64;
+                            ui.label(format!(
+                                "Using {:.1} MB: key buffers {:.1}, patch sums {:.1}, wavetables {:.1}{}",
+                                megabytes(usage.total()),
+                                megabytes(usage.key_buffers),
+                                megabytes(usage.render_sums),
+                                megabytes(usage.wavetables),
+                                if usage.streaming { " (streaming)" } else { "" }
+                            ));
+                        });
+
+                        ui.horizontal(|ui| {
+                            // Edited text is kept until focus leaves the field, then applied
+                            let input_id = egui::Id::new("render_cache_dir_input");
+                            let mut input = ui.memory(|mem| mem.data.get_temp::<String>(input_id)).unwrap_or_else(|| {
+                                synth_params.render_cache_dir.read_recover().clone().unwrap_or_default()
+                            });
+                            ui.label("Render cache");
+                            let response = ui.add(
+                                egui::TextEdit::singleline(&mut input)
+                                    .hint_text("Directory, empty to disable")
+                                    .desired_width(300.0),
                             );
-                            draw_harmonic_plot(
-                                &mut columns[1],
-                                "Phase",
-                                ChartType::Phase,
-                                chart_w,
-                                chart_h,
-                                &synth_compute_engine,
+                            if response.lost_focus() {
+                                let dir = Some(input.trim().to_string()).filter(|dir| !dir.is_empty());
+                                *synth_params.render_cache_dir.write_recover() = dir;
+                            }
+                            ui.memory_mut(|mem| mem.data.insert_temp(input_id, input));
+
+                            // Listing the directory every frame would be wasteful
+                            let usage_id = egui::Id::new("render_cache_usage");
+                            let now = ui.input(|i| i.time);
+                            let usage = match ui.memory(|mem| mem.data.get_temp::<(f64, Option<(usize, u64)>)>(usage_id)) {
+                                Some((checked, usage)) if now - checked < 1.0 => usage,
+                                _ => {
+                                    let usage = synth_compute_engine.render_cache_usage();
+                                    ui.memory_mut(|mem| mem.data.insert_temp(usage_id, (now, usage)));
+                                    usage
+                                }
+                            };
+                            match usage {
+                                Some((keys, bytes)) => {
+                                    ui.label(format!("{} keys, {:.1} MB", keys, bytes as f64 / BYTES_PER_MB as f64));
+                                    if ui.button("Clear").clicked() {
+                                        synth_compute_engine.clear_render_cache();
+                                        ui.memory_mut(|mem| mem.data.remove::<(f64, Option<(usize, u64)>)>(usage_id));
+                                    }
+                                }
+                                None => {
+                                    ui.label("(disabled)");
+                                }
+                            }
+                        });
+
+                        ui.horizontal(|ui| {
+                            let path_id = egui::Id::new("patch_file_input");
+                            let status_id = egui::Id::new("patch_file_status");
+                            let mut path = ui.memory(|mem| mem.data.get_temp::<String>(path_id)).unwrap_or_default();
+                            ui.label("Patch file");
+                            ui.add(
+                                egui::TextEdit::singleline(&mut path)
+                                    .hint_text("Preset .json, also read by lesynth-render")
+                                    .desired_width(300.0),
                             );
+                            let file = std::path::Path::new(path.trim());
+                            let mut status = None;
+                            if ui.button("Save").clicked() {
+                                status = Some(match synth_compute_engine.patch().save(file) {
+                                    Ok(()) => Ok("Saved".to_string()),
+                                    Err(err) => Err(err.to_string()),
+                                });
+                            }
+                            if ui.button("Load").clicked() {
+                                // Render settings stay with the host parameters
+                                status = Some(
+                                    Patch::load(file)
+                                        .and_then(|patch| {
+                                            let patch = Patch { render: synth_params.render_settings(), ..patch };
+                                            synth_compute_engine
+                                                .load_patch(&patch)
+                                                .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+                                            Ok(patch)
+                                        })
+                                        .map(|patch| {
+                                            // Saved with the plugin state, which restores the bucket count first
+                                            setter.begin_set_parameter(&synth_params.num_buckets);
+                                            setter.set_parameter(&synth_params.num_buckets, patch.num_buckets() as i32);
+                                            setter.end_set_parameter(&synth_params.num_buckets);
+                                            let curves = HarmonicCurves { amplitudes: patch.amplitudes, phases: patch.phases };
+                                            use_custom_curves(
+                                                setter,
+                                                &synth_params,
+                                                &synth_compute_engine,
+                                                curves,
+                                                &[ChartType::Amp, ChartType::Phase],
+                                            );
+                                            "Loaded".to_string()
+                                        })
+                                        .map_err(|err| err.to_string()),
+                                );
+                            }
+                            ui.memory_mut(|mem| {
+                                mem.data.insert_temp(path_id, path);
+                                if let Some(status) = status {
+                                    mem.data.insert_temp(status_id, status);
+                                }
+                            });
+                            show_file_status(ui, status_id);
                         });
 
+                        ui.horizontal(|ui| {
+                            let dir_id = egui::Id::new("sfz_export_dir");
+                            let name_id = egui::Id::new("sfz_export_name");
+                            let step_id = egui::Id::new("sfz_export_step");
+                            let status_id = egui::Id::new("sfz_export_status");
+                            let (mut dir, mut name, mut step) = ui.memory(|mem| {
+                                (
+                                    mem.data.get_temp::<String>(dir_id).unwrap_or_default(),
+                                    mem.data.get_temp::<String>(name_id).unwrap_or_else(|| "LeSynth".to_string()),
+                                    mem.data.get_temp::<usize>(step_id).unwrap_or(3),
+                                )
+                            });
+
+                            ui.label("SFZ export");
+                            ui.add(egui::TextEdit::singleline(&mut dir).hint_text("Directory").desired_width(200.0));
+                            ui.add(egui::TextEdit::singleline(&mut name).hint_text("Name").desired_width(100.0));
+                            ui.add(egui::DragValue::new(&mut step).range(1..=12).prefix("every ").suffix(" keys"));
+                            if export_button(ui, status_id) {
+                                let engine = synth_compute_engine.clone();
+                                let options = SfzOptions {
+                                    name: name.clone(),
+                                    keys: every_nth_key(step),
+                                    format: SampleFormat::Int24,
+                                };
+                                let dir = std::path::PathBuf::from(dir.trim());
+                                spawn_export(ui, status_id, move || {
+                                    export_sfz(&engine, &dir, &options).map(|path| format!("Exported to {}", path.display()))
+                                });
+                            }
+                            ui.memory_mut(|mem| {
+                                mem.data.insert_temp(dir_id, dir);
+                                mem.data.insert_temp(name_id, name);
+                                mem.data.insert_temp(step_id, step);
+                            });
+                            show_export_status(ui, status_id);
+                        });
+
+                        ui.horizontal(|ui| {
+                            let path_id = egui::Id::new("wavetable_export_path");
+                            let frames_id = egui::Id::new("wavetable_export_frames");
+                            let status_id = egui::Id::new("wavetable_export_status");
+                            let (mut path, mut frames) = ui.memory(|mem| {
+                                (
+                                    mem.data.get_temp::<String>(path_id).unwrap_or_default(),
+                                    mem.data.get_temp::<usize>(frames_id).unwrap_or(0),
+                                )
+                            });
+
+                            ui.label("Wavetable export");
+                            ui.add(egui::TextEdit::singleline(&mut path).hint_text("File .wav").desired_width(300.0));
+                            ui.add(
+                                egui::DragValue::new(&mut frames)
+                                    .range(0..=wavetable::MAX_FRAMES)
+                                    .custom_formatter(|n, _| {
+                                        if n == 0.0 { "one frame per bucket".to_string() } else { format!("{} frames", n) }
+                                    }),
+                            );
+                            if export_button(ui, status_id) {
+                                let engine = synth_compute_engine.clone();
+                                let options = WavetableOptions {
+                                    frames: Some(frames).filter(|&frames| frames > 0),
+                                    ..Default::default()
+                                };
+                                let path = std::path::PathBuf::from(path.trim());
+                                spawn_export(ui, status_id, move || {
+                                    wavetable::export_wavetable(&engine, &path, &options)
+                                        .map(|frames| format!("Exported {} frames", frames))
+                                });
+                            }
+                            ui.memory_mut(|mem| {
+                                mem.data.insert_temp(path_id, path);
+                                mem.data.insert_temp(frames_id, frames);
+                            });
+                            show_export_status(ui, status_id);
+                        });
+                        ui.horizontal(|ui| {
+                            let path_id = egui::Id::new("wavetable_import_path");
+                            let job_id = egui::Id::new("wavetable_import_job");
+                            let status_id = egui::Id::new("wavetable_import_status");
+                            let mut path = ui.memory(|mem| mem.data.get_temp::<String>(path_id)).unwrap_or_default();
+
+                            ui.label("Wavetable import");
+                            ui.add(
+                                egui::TextEdit::singleline(&mut path)
+                                    .hint_text("Wavetable or single-cycle .wav")
+                                    .desired_width(300.0),
+                            );
+                            if import_button(ui, job_id, "Import") {
+                                let path = std::path::PathBuf::from(path.trim());
+                                spawn_import(ui, job_id, status_id, move || {
+                                    let curves = import_wavetable(&path, None)?;
+                                    let message = format!("Imported {} frames", curves.num_buckets());
+                                    Ok((curves, message))
+                                });
+                            }
+                            finish_import(ui, job_id, status_id, "Importing", |curves| {
+                                use_custom_curves(setter, &synth_params, &synth_compute_engine, curves, &[ChartType::Amp, ChartType::Phase]);
+                            });
+                            ui.memory_mut(|mem| mem.data.insert_temp(path_id, path));
+                            show_file_status(ui, status_id);
+                        });
+                        ui.horizontal(|ui| {
+                            let path_id = egui::Id::new("resynthesis_path");
+                            let options_id = egui::Id::new("resynthesis_options");
+                            let job_id = egui::Id::new("resynthesis_job");
+                            let status_id = egui::Id::new("resynthesis_status");
+                            let mut path = ui.memory(|mem| mem.data.get_temp::<String>(path_id)).unwrap_or_default();
+                            let mut options = ui
+                                .memory(|mem| mem.data.get_temp::<ResynthesisOptions>(options_id))
+                                .unwrap_or_default();
+
+                            ui.label("Resynthesis");
+                            ui.add(
+                                egui::TextEdit::singleline(&mut path)
+                                    .hint_text("Mono .wav of a pitched note")
+                                    .desired_width(200.0),
+                            );
+                            ui.label("From");
+                            ui.add(egui::DragValue::new(&mut options.start).range(0.0..=600.0).speed(0.01).suffix(" s"));
+                            // Zero analyses up to the end of the recording
+                            let mut end = options.end.unwrap_or(0.0);
+                            ui.label("to");
+                            ui.add(egui::DragValue::new(&mut end).range(0.0..=600.0).speed(0.01).suffix(" s"));
+                            options.end = (end > 0.0).then_some(end);
+                            egui::ComboBox::from_id_salt("resynthesis_mapping")
+                                .selected_text(format!("{:?}", options.mapping))
+                                .show_ui(ui, |ui| {
+                                    for &variant in BucketMapping::VARIANTS.iter() {
+                                        ui.selectable_value(&mut options.mapping, variant, format!("{:?}", variant));
+                                    }
+                                });
+                            if import_button(ui, job_id, "Analyze") {
+                                let num_buckets = synth_compute_engine.shared_params.num_buckets();
+                                let path = std::path::PathBuf::from(path.trim());
+                                let options = options.clone();
+                                spawn_import(ui, job_id, status_id, move || {
+                                    let resynthesis = resynthesize_file(&path, num_buckets, &options)?;
+                                    Ok((resynthesis.curves, format!("Fundamental {:.2} Hz", resynthesis.fundamental)))
+                                });
+                            }
+                            finish_import(ui, job_id, status_id, "Analyzing", |curves| {
+                                use_custom_curves(setter, &synth_params, &synth_compute_engine, curves, &[ChartType::Amp, ChartType::Phase]);
+                            });
+                            ui.memory_mut(|mem| {
+                                mem.data.insert_temp(path_id, path);
+                                mem.data.insert_temp(options_id, options);
+                            });
+                            show_file_status(ui, status_id);
+                        });
+                        ui.horizontal(|ui| {
+                            let path_id = egui::Id::new("matrix_path");
+                            let chart_type_id = egui::Id::new("matrix_chart_type");
+                            let status_id = egui::Id::new("matrix_status");
+                            let mut path = ui.memory(|mem| mem.data.get_temp::<String>(path_id)).unwrap_or_default();
+                            let mut chart_type =
+                                ui.memory(|mem| mem.data.get_temp::<ChartType>(chart_type_id)).unwrap_or(ChartType::Amp);
+
+                            ui.label("Harmonic data");
+                            ui.add(
+                                egui::TextEdit::singleline(&mut path)
+                                    .hint_text("Harmonics x buckets .csv or .json")
+                                    .desired_width(300.0),
+                            );
+                            egui::ComboBox::from_id_salt("matrix_chart_type")
+                                .selected_text(format!("{:?}", chart_type))
+                                .show_ui(ui, |ui| {
+                                    for variant in [ChartType::Amp, ChartType::Phase] {
+                                        let label = format!("{:?}", variant);
+                                        ui.selectable_value(&mut chart_type, variant, label);
+                                    }
+                                });
+                            let file = std::path::Path::new(path.trim());
+                            if ui.button("Import").clicked() {
+                                let status = match matrix::import_matrix(&synth_compute_engine, &chart_type, file) {
+                                    Ok(curves) => {
+                                        // Only the imported matrix switches to custom curves
+                                        use_custom_curves(
+                                            setter,
+                                            &synth_params,
+                                            &synth_compute_engine,
+                                            curves,
+                                            std::slice::from_ref(&chart_type),
+                                        );
+                                        Ok(format!("Imported {:?} data", chart_type))
+                                    }
+                                    Err(err) => Err(err.to_string()),
+                                };
+                                ui.memory_mut(|mem| mem.data.insert_temp(status_id, status));
+                            }
+                            if ui.button("Export").clicked() {
+                                let status = matrix::export_matrix(&synth_compute_engine, &chart_type, file)
+                                    .map(|()| format!("Exported {:?} data", chart_type))
+                                    .map_err(|err| err.to_string());
+                                ui.memory_mut(|mem| mem.data.insert_temp(status_id, status));
+                            }
+                            ui.memory_mut(|mem| {
+                                mem.data.insert_temp(path_id, path);
+                                mem.data.insert_temp(chart_type_id, chart_type);
+                            });
+                            show_file_status(ui, status_id);
+                        });
+                        let num_buckets = synth_compute_engine.shared_params.num_buckets();
+                        if let Some((mut curves, options)) = draw_image_import(ui, num_buckets) {
+                            let chart_types = if options.hue_to_phase {
+                                vec![ChartType::Amp, ChartType::Phase]
+                            } else {
+                                curves.phases = synth_compute_engine.patch().phases;
+                                vec![ChartType::Amp]
+                            };
+                            use_custom_curves(setter, &synth_params, &synth_compute_engine, curves, &chart_types);
+                        }
+                        // Apply render settings changed from the GUI or the host
+                        synth_compute_engine.set_render_settings(synth_params.render_settings());
+
+                        // Bring back computation threads lost to a panic and report what went wrong
+                        synth_compute_engine.supervise_workers();
+                        let failures = synth_compute_engine.render_failures();
+                        if let Some(last) = failures.last() {
+                            ui.horizontal(|ui| {
+                                ui.colored_label(
+                                    egui::Color32::from_rgb(220, 60, 60),
+                                    format!("Rendering failed {} times, last: {}", failures.count(), last),
+                                );
+                                if ui.button("Retry").clicked() {
+                                    synth_compute_engine.shared_params.job_queue.retry_failed_keys();
+                                    failures.clear();
+                                }
+                                if ui.button("Dismiss").clicked() {
+                                    failures.clear();
+                                }
+                            });
+                        }
+
+                        ui.add_space(10.0);
+
+                        egui::CollapsingHeader::new("Output Analyzer")
+                            .default_open(false)
+                            .show(ui, |ui| {
+                                draw_output_scope(ui, &synth_compute_engine);
+                            });
+
                         ui.add_space(10.0);
 
                         draw_assembled_chart(ui, &synth_compute_engine);
@@ -331,12 +848,176 @@ impl Plugin for LeSynth {
     }
 }
 
+// Result of an export running on its own thread, `None` while it runs
+type ExportStatus = Arc<Mutex<Option<Result<String, String>>>>;
+
+/// Export button, disabled while the export it started last is still running
+fn export_button(ui: &mut egui::Ui, status_id: egui::Id) -> bool {
+    let running = ui
+        .memory(|mem| mem.data.get_temp::<ExportStatus>(status_id))
+        .is_some_and(|status| status.lock_recover().is_none());
+    ui.add_enabled(!running, egui::Button::new("Export")).clicked()
+}
+
+/// Run an export off the GUI thread so the editor stays responsive
+fn spawn_export(ui: &egui::Ui, status_id: egui::Id, export: impl FnOnce() -> std::io::Result<String> + Send + 'static) {
+    let status: ExportStatus = Arc::new(Mutex::new(None));
+    let thread_status = status.clone();
+    std::thread::spawn(move || {
+        *thread_status.lock_recover() = Some(export().map_err(|err| err.to_string()));
+    });
+    ui.memory_mut(|mem| mem.data.insert_temp(status_id, status));
+}
+
+fn show_export_status(ui: &mut egui::Ui, status_id: egui::Id) {
+    let status = ui.memory(|mem| mem.data.get_temp::<ExportStatus>(status_id));
+    match status.map(|status| status.lock_recover().clone()) {
+        Some(None) => {
+            ui.spinner();
+        }
+        Some(Some(Ok(message))) => {
+            ui.label(message);
+        }
+        Some(Some(Err(message))) => {
+            ui.colored_label(egui::Color32::from_rgb(220, 60, 60), message);
+        }
+        None => {}
+    }
+}
+
+// Curves read on their own thread and a status message, `None` while the import runs
+type ImportJob = Arc<Mutex<Option<Result<(HarmonicCurves, String), String>>>>;
+
+/// Import button, disabled while the import it started last is still running
+fn import_button(ui: &mut egui::Ui, job_id: egui::Id, label: &str) -> bool {
+    let running = ui.memory(|mem| mem.data.get_temp::<ImportJob>(job_id)).is_some();
+    ui.add_enabled(!running, egui::Button::new(label)).clicked()
+}
+
+/// Read curves off the GUI thread so the editor stays responsive; `finish_import` applies them
+fn spawn_import(
+    ui: &egui::Ui,
+    job_id: egui::Id,
+    status_id: egui::Id,
+    import: impl FnOnce() -> std::io::Result<(HarmonicCurves, String)> + Send + 'static,
+) {
+    let job: ImportJob = Arc::new(Mutex::new(None));
+    let thread_job = job.clone();
+    std::thread::spawn(move || {
+        *thread_job.lock_recover() = Some(import().map_err(|err| err.to_string()));
+    });
+    ui.memory_mut(|mem| {
+        mem.data.insert_temp(job_id, job);
+        mem.data.remove::<Result<String, String>>(status_id);
+    });
+}
+
+/// Hand the curves of a finished import to `apply` on the GUI thread and keep its message under
+/// `status_id` for `show_file_status`. Shows a spinner with `running` while the import runs.
+fn finish_import(ui: &mut egui::Ui, job_id: egui::Id, status_id: egui::Id, running: &str, apply: impl FnOnce(HarmonicCurves)) {
+    let Some(job) = ui.memory(|mem| mem.data.get_temp::<ImportJob>(job_id)) else {
+        return;
+    };
+    let Some(result) = job.lock_recover().take() else {
+        ui.spinner();
+        ui.label(running);
+        return;
+    };
+    let status = result.map(|(curves, message)| {
+        apply(curves);
+        message
+    });
+    ui.memory_mut(|mem| {
+        mem.data.remove::<ImportJob>(job_id);
+        mem.data.insert_temp(status_id, status);
+    });
+}
+
+/// Result of the last file operation of a row, kept in the egui memory under `status_id`
+fn show_file_status(ui: &mut egui::Ui, status_id: egui::Id) {
+    match ui.memory(|mem| mem.data.get_temp::<Result<String, String>>(status_id)) {
+        Some(Ok(message)) => {
+            ui.label(message);
+        }
+        Some(Err(message)) => {
+            ui.colored_label(egui::Color32::from_rgb(220, 60, 60), message);
+        }
+        None => {}
+    }
+}
+
+/// Switch the curves of `chart_types` to `curves`, which are saved with the plugin state
+fn use_custom_curves(
+    setter: &ParamSetter,
+    synth_params: &LeSynthParams,
+    synth_compute_engine: &SynthComputeEngine,
+    curves: HarmonicCurves,
+    chart_types: &[ChartType],
+) {
+    for harmonic in synth_params.harmonics.iter() {
+        for chart_type in chart_types {
+            let param = match chart_type {
+                ChartType::Amp => &harmonic.curve_type_amp,
+                ChartType::Phase => &harmonic.curve_type_phase,
+            };
+            setter.begin_set_parameter(param);
+            setter.set_parameter(param, CurveType::Custom);
+            setter.end_set_parameter(param);
+        }
+    }
+    synth_compute_engine.load_curves(&curves, |_, chart_type| chart_types.contains(&chart_type));
+    *synth_params.custom_curves.write_recover() = Some(curves);
+}
+
+/// Voice id for notes from hosts that don't send one, unique per key and channel
+fn fallback_voice_id(note: u8, channel: u8) -> i32 {
+    note as i32 | ((channel as i32) << 16)
+}
+
+impl LeSynth {
+    /// Voice a note expression applies to, by the host's voice id or else by its key
+    fn expression_voice(&mut self, voice_id: Option<i32>, note: u8) -> Option<&mut Voice> {
+        let key = match voice_id {
+            Some(id) => self.voice_bank.key_of_voice(id)?,
+            None => note as usize,
+        };
+        self.voice_bank.voice_mut(key)
+    }
+
+    /// Combine the voice parameters, each voice's polyphonic modulation and its note
+    /// expressions into the controls the voices play with
+    fn update_voice_controls(&mut self) {
+        let params = &self.synth_params;
+        for key in 0..NUM_KEYS {
+            let Some(voice) = self.voice_bank.voice_mut(key) else {
+                continue;
+            };
+            let value = |param: &FloatParam, id: u32| match voice.modulation[id as usize] {
+                Some(offset) => param.preview_modulated(offset),
+                None => param.value(),
+            };
+            let controls = VoiceControls {
+                tuning: value(&params.voice_tuning, POLY_MOD_VOICE_TUNING) + voice.expression.tuning,
+                gain: value(&params.voice_gain, POLY_MOD_VOICE_GAIN) * voice.expression.gain,
+                pan: value(&params.voice_pan, POLY_MOD_VOICE_PAN) + voice.expression.pan,
+            };
+            voice.set_controls(controls);
+        }
+    }
+}
+
 impl ClapPlugin for LeSynth {
-    const CLAP_ID: &'static str = "org.example.lesynth";
-    const CLAP_DESCRIPTION: Option<&'static str> = Some("A LeSynth plugin");
-    const CLAP_MANUAL_URL: Option<&'static str> = None;
-    const CLAP_SUPPORT_URL: Option<&'static str> = None;
-    const CLAP_FEATURES: &'static [ClapFeature] = &[];
+    const CLAP_ID: &'static str = "com.hlavnjak.lesynth-fourier";
+    const CLAP_DESCRIPTION: Option<&'static str> = Some("Additive synthesizer with harmonic curves over time");
+    const CLAP_MANUAL_URL: Option<&'static str> = Some("https://github.com/hlavnjak/lesynth-fourier");
+    const CLAP_SUPPORT_URL: Option<&'static str> = Some("https://github.com/hlavnjak/lesynth-fourier/issues");
+    const CLAP_FEATURES: &'static [ClapFeature] =
+        &[ClapFeature::Instrument, ClapFeature::Synthesizer, ClapFeature::Stereo];
+    // One voice per key
+    const CLAP_POLY_MODULATION_CONFIG: Option<PolyModulationConfig> = Some(PolyModulationConfig {
+        max_voice_capacity: NUM_KEYS as u32,
+        supports_overlapping_voices: false,
+    });
 }
 
 impl Vst3Plugin for LeSynth {
@@ -0,0 +1,339 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Harmonic curves analysed from a recorded note.
+//!
+//! The fundamental is found with the YIN difference function, then every bucket gets a
+//! short-time spectrum around its point in time. Peaks are tracked near each multiple of the
+//! fundamental, and their phases are taken relative to the fundamental's, so a bucket holds the
+//! shape of one cycle just like the synthesized ones.
+
+use std::f32::consts::{PI, TAU};
+use std::io;
+use std::path::Path;
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use crate::constants::NUM_HARMONICS;
+use crate::io::wav;
+use crate::patch::HarmonicCurves;
+
+// Pitch range searched for the fundamental, the piano's
+const MIN_FUNDAMENTAL: f32 = 27.5;
+const MAX_FUNDAMENTAL: f32 = 4186.0;
+// YIN threshold on the normalized difference
+const YIN_THRESHOLD: f32 = 0.15;
+// Cycles of the fundamental in an analysis window
+const WINDOW_CYCLES: f32 = 6.0;
+// How far a tracked peak may drift from the previous one, in fractions of the harmonic spacing
+const PEAK_SEARCH: f32 = 0.3;
+// Peaks this far below the strongest one in their frame are noise
+const NOISE_FLOOR: f32 = 1e-4;
+
+/// Where in the recording each bucket is analysed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BucketMapping {
+    // Buckets spread evenly over the time range, however long it is
+    #[default]
+    Stretch,
+    // One cycle of the fundamental per bucket from the start of the range, so the patch
+    // plays back in real time at the recorded pitch
+    PerCycle,
+}
+
+impl BucketMapping {
+    pub const VARIANTS: [BucketMapping; 2] = [BucketMapping::Stretch, BucketMapping::PerCycle];
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResynthesisOptions {
+    // Analysed part of the recording in seconds, `None` for its end
+    pub start: f32,
+    pub end: Option<f32>,
+    pub mapping: BucketMapping,
+    // Skip detection and use this fundamental in Hz
+    pub fundamental: Option<f32>,
+    // Scale the amplitudes so the loudest bucket sums to 1
+    pub normalize: bool,
+}
+
+impl Default for ResynthesisOptions {
+    fn default() -> Self {
+        Self {
+            start: 0.0,
+            end: None,
+            mapping: BucketMapping::default(),
+            fundamental: None,
+            normalize: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resynthesis {
+    pub curves: HarmonicCurves,
+    // Fundamental in Hz, detected or given
+    pub fundamental: f32,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Fundamental of a pitched signal in Hz, or `None` if it has no clear pitch
+pub fn detect_fundamental(samples: &[f32], sample_rate: f32) -> Option<f32> {
+    let min_lag = ((sample_rate / MAX_FUNDAMENTAL) as usize).max(2);
+    let max_lag = ((sample_rate / MIN_FUNDAMENTAL).ceil() as usize).min(samples.len() / 2);
+    if max_lag <= min_lag + 1 {
+        return None;
+    }
+    let window = (samples.len() - max_lag).min(2 * max_lag);
+
+    // Cumulative mean normalized difference
+    let mut normalized = vec![1.0f32; max_lag + 1];
+    let mut running_sum = 0.0;
+    for lag in 1..=max_lag {
+        let difference: f32 = (0..window).map(|j| (samples[j] - samples[j + lag]).powi(2)).sum();
+        running_sum += difference;
+        normalized[lag] = if running_sum > 0.0 { difference * lag as f32 / running_sum } else { 1.0 };
+    }
+
+    // First dip below the threshold, else the deepest one
+    let mut lag = (min_lag..max_lag).find(|&lag| normalized[lag] < YIN_THRESHOLD).unwrap_or_else(|| {
+        (min_lag..max_lag).min_by(|&a, &b| normalized[a].total_cmp(&normalized[b])).unwrap_or(min_lag)
+    });
+    while lag + 1 < max_lag && normalized[lag + 1] < normalized[lag] {
+        lag += 1;
+    }
+    if normalized[lag] > 0.5 {
+        return None;
+    }
+    // Parabola through the dip and its neighbours
+    let (a, b, c) = (normalized[lag - 1], normalized[lag], normalized[lag + 1]);
+    let curvature = a - 2.0 * b + c;
+    let offset = if curvature > 0.0 { 0.5 * (a - c) / curvature } else { 0.0 };
+    Some(sample_rate / (lag as f32 + offset.clamp(-1.0, 1.0)))
+}
+
+/// Amplitude and phase curves of `samples` at `num_buckets` points in time
+pub fn resynthesize(
+    samples: &[f32],
+    sample_rate: f32,
+    num_buckets: usize,
+    options: &ResynthesisOptions,
+) -> io::Result<Resynthesis> {
+    let start = ((options.start.max(0.0) * sample_rate) as usize).min(samples.len());
+    let end = options.end.map_or(samples.len(), |end| ((end * sample_rate) as usize).min(samples.len()));
+    if end <= start || num_buckets == 0 {
+        return Err(invalid("Time range is empty"));
+    }
+    let range = &samples[start..end];
+
+    let fundamental = match options.fundamental {
+        Some(fundamental) if fundamental > 0.0 => fundamental,
+        Some(_) => return Err(invalid("Fundamental must be positive")),
+        None => {
+            // The middle of the range, past the attack
+            let len = ((3.0 * sample_rate / MIN_FUNDAMENTAL) as usize).min(range.len());
+            let from = (range.len() - len) / 2;
+            detect_fundamental(&range[from..from + len], sample_rate).ok_or_else(|| invalid("No pitch found"))?
+        }
+    };
+    let period = sample_rate / fundamental;
+
+    let window_len = ((WINDOW_CYCLES * period) as usize).max(16);
+    let fft_size = (2 * window_len).next_power_of_two();
+    let window: Vec<f32> = (0..window_len)
+        .map(|j| 0.5 - 0.5 * (TAU * (j as f32 + 0.5) / window_len as f32).cos())
+        .collect();
+    let window_sum: f32 = window.iter().sum();
+    let fft = FftPlanner::new().plan_fft_forward(fft_size);
+    let bins_per_hz = fft_size as f32 / sample_rate;
+    let spacing = fundamental * bins_per_hz;
+    let harmonics = NUM_HARMONICS.min((0.5 * sample_rate / fundamental) as usize);
+
+    let mut curves = HarmonicCurves {
+        amplitudes: vec![vec![0.0; num_buckets]; NUM_HARMONICS],
+        phases: vec![vec![0.0; num_buckets]; NUM_HARMONICS],
+    };
+    // Peak positions in bins, followed from bucket to bucket
+    let mut tracked: Vec<f32> = (1..=harmonics).map(|h| h as f32 * spacing).collect();
+    let mut spectrum = vec![Complex::default(); fft_size];
+    for bucket in 0..num_buckets {
+        let center = match options.mapping {
+            BucketMapping::Stretch => (bucket as f32 + 0.5) * range.len() as f32 / num_buckets as f32,
+            BucketMapping::PerCycle => (bucket as f32 + 0.5) * period,
+        } as usize;
+        if center >= range.len() {
+            // Past the end of the range, leave the bucket silent
+            continue;
+        }
+
+        // Centre the window on time zero so the phases are those at the bucket's point in time.
+        // Windows near the ends of the range still see the audio around it.
+        spectrum.fill(Complex::default());
+        for (j, &weight) in window.iter().enumerate() {
+            let offset = j as isize - (window_len / 2) as isize;
+            let at = (start + center) as isize + offset;
+            let sample = usize::try_from(at).ok().and_then(|at| samples.get(at)).copied().unwrap_or(0.0);
+            spectrum[offset.rem_euclid(fft_size as isize) as usize] = Complex::new(sample * weight, 0.0);
+        }
+        fft.process(&mut spectrum);
+
+        let loudest = spectrum[..fft_size / 2].iter().map(|bin| bin.norm()).fold(0.0, f32::max);
+        if loudest == 0.0 {
+            continue;
+        }
+        let mut fundamental_phase = 0.0;
+        for (n, track) in tracked.iter_mut().enumerate() {
+            let expected = (n + 1) as f32 * spacing;
+            let low = ((*track - PEAK_SEARCH * spacing).floor().max(1.0)) as usize;
+            let high = ((*track + PEAK_SEARCH * spacing).ceil() as usize).min(fft_size / 2 - 1);
+            let Some(peak) = (low..=high).max_by(|&a, &b| spectrum[a].norm().total_cmp(&spectrum[b].norm())) else {
+                continue;
+            };
+            let magnitude = spectrum[peak].norm();
+            // A maximum at the edge of the search is the skirt of a neighbouring partial
+            if magnitude < NOISE_FLOOR * loudest || peak == low || peak == high {
+                // Lost the partial; look for it where it belongs next time
+                *track = expected;
+                continue;
+            }
+            let (a, b, c) = (spectrum[peak - 1].norm(), magnitude, spectrum[peak + 1].norm());
+            let curvature = a - 2.0 * b + c;
+            let offset = if curvature < 0.0 { (0.5 * (a - c) / curvature).clamp(-0.5, 0.5) } else { 0.0 };
+            // Keep the track near its harmonic so a strong neighbour can't capture it
+            *track = (peak as f32 + offset).clamp(expected - spacing / 2.0, expected + spacing / 2.0);
+
+            let amplitude = 2.0 * (b - 0.25 * (a - c) * offset) / window_sum;
+            let phase = spectrum[peak].arg();
+            if n == 0 {
+                fundamental_phase = phase;
+            }
+            curves.amplitudes[n][bucket] = amplitude;
+            curves.phases[n][bucket] = (phase - (n + 1) as f32 * fundamental_phase + PI).rem_euclid(TAU) - PI;
+        }
+    }
+
+    let loudest_bucket = (0..num_buckets)
+        .map(|bucket| curves.amplitudes.iter().map(|row| row[bucket]).sum::<f32>())
+        .fold(0.0, f32::max);
+    let scale = if options.normalize && loudest_bucket > 0.0 { 1.0 / loudest_bucket } else { 1.0 };
+    for row in curves.amplitudes.iter_mut() {
+        for amplitude in row.iter_mut() {
+            *amplitude = (*amplitude * scale).clamp(0.0, 1.0);
+        }
+    }
+    for row in curves.phases.iter_mut() {
+        for phase in row.iter_mut() {
+            *phase = phase.rem_euclid(TAU);
+        }
+    }
+    Ok(Resynthesis { curves, fundamental })
+}
+
+/// Analyse a WAV file, mixing its channels down to mono
+pub fn resynthesize_file(path: &Path, num_buckets: usize, options: &ResynthesisOptions) -> io::Result<Resynthesis> {
+    let file = wav::read(path)?;
+    resynthesize(&file.mono(), file.sample_rate as f32, num_buckets, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: f32 = 44100.0;
+
+    // Decaying note with harmonics (number, amplitude, phase)
+    fn note(fundamental: f32, seconds: f32, partials: &[(usize, f32, f32)]) -> Vec<f32> {
+        (0..(seconds * RATE) as usize)
+            .map(|i| {
+                let t = i as f32 / RATE;
+                let envelope = (-t * 2.0).exp();
+                partials
+                    .iter()
+                    .map(|&(h, amp, phase)| envelope * amp * (TAU * h as f32 * fundamental * t + phase).cos())
+                    .sum()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_detect_fundamental() {
+        for fundamental in [55.0, 220.0, 261.63, 1000.0] {
+            let samples = note(fundamental, 0.5, &[(1, 0.5, 0.0), (2, 0.3, 1.0), (3, 0.2, 2.0)]);
+            let detected = detect_fundamental(&samples, RATE).unwrap();
+            assert!((detected - fundamental).abs() < fundamental * 0.002, "{} != {}", detected, fundamental);
+        }
+        // Even with a weak fundamental the pitch isn't taken for the octave above
+        let samples = note(110.0, 0.5, &[(1, 0.1, 0.0), (2, 0.6, 0.0), (3, 0.4, 0.0)]);
+        assert!((detect_fundamental(&samples, RATE).unwrap() - 110.0).abs() < 0.5);
+
+        let mut state = 0x1234_5678u32;
+        let noise: Vec<f32> = (0..20000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as f32 / u32::MAX as f32 - 0.5
+            })
+            .collect();
+        assert_eq!(detect_fundamental(&noise, RATE), None);
+        assert_eq!(detect_fundamental(&[0.0; 10], RATE), None);
+    }
+
+    #[test]
+    fn test_harmonics_and_relative_phases() {
+        // The fundamental's own phase must not leak into the others
+        let samples = note(220.0, 1.0, &[(1, 0.5, 0.7), (2, 0.25, 0.7 * 2.0 + 1.0), (4, 0.1, 0.7 * 4.0 + 2.5)]);
+        let options = ResynthesisOptions { normalize: false, ..Default::default() };
+        let result = resynthesize(&samples, RATE, 10, &options).unwrap();
+        assert!((result.fundamental - 220.0).abs() < 0.5);
+
+        let curves = &result.curves;
+        assert_eq!(curves.num_buckets(), 10);
+        for bucket in 1..9 {
+            let envelope = (-2.0 * (bucket as f32 + 0.5) / 10.0).exp();
+            assert!((curves.amplitudes[0][bucket] - 0.5 * envelope).abs() < 0.01);
+            assert!((curves.amplitudes[1][bucket] - 0.25 * envelope).abs() < 0.01);
+            assert!((curves.amplitudes[3][bucket] - 0.1 * envelope).abs() < 0.01);
+            assert!(curves.amplitudes[2][bucket] < 0.005);
+            assert!(curves.phases[0][bucket].abs() < 1e-3 || (curves.phases[0][bucket] - TAU).abs() < 1e-3);
+            assert!((curves.phases[1][bucket] - 1.0).abs() < 0.05);
+            assert!((curves.phases[3][bucket] - 2.5).abs() < 0.05);
+        }
+        // Decaying from bucket to bucket
+        assert!(curves.amplitudes[0].windows(2).skip(1).take(7).all(|w| w[1] < w[0]));
+    }
+
+    #[test]
+    fn test_time_range_and_mapping() {
+        let samples = note(440.0, 1.0, &[(1, 0.5, 0.0), (3, 0.2, 0.0)]);
+        let options = ResynthesisOptions { start: 0.5, mapping: BucketMapping::PerCycle, ..Default::default() };
+        let result = resynthesize(&samples, RATE, 100, &options).unwrap();
+        // Normalized so the loudest bucket sums to 1, and the ratio of the harmonics kept
+        let curves = &result.curves;
+        let sums: Vec<f32> = (0..100).map(|b| curves.amplitudes.iter().map(|row| row[b]).sum()).collect();
+        assert!(sums.iter().all(|&sum| sum <= 1.0 + 1e-4));
+        assert!((sums[0] - 1.0).abs() < 0.05);
+        assert!((curves.amplitudes[2][50] / curves.amplitudes[0][50] - 0.4).abs() < 0.02);
+        // 100 cycles at 440 Hz cover less than half of the half second left
+        let decay = (-2.0 * 99.5 / 440.0f32).exp();
+        assert!((sums[99] - decay).abs() < 0.05);
+
+        let fixed = ResynthesisOptions { fundamental: Some(440.0), end: Some(0.6), ..options };
+        assert_eq!(resynthesize(&samples, RATE, 4, &fixed).unwrap().fundamental, 440.0);
+        let empty = ResynthesisOptions { start: 2.0, ..Default::default() };
+        assert_eq!(resynthesize(&samples, RATE, 4, &empty).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -12,28 +12,305 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::sync::{Arc, OnceLock};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use rtrb::{Consumer, Producer};
+use crate::constants::NUM_KEYS;
+use crate::engine::render_kernel::cubic_hermite;
+
+// Capacity of the queues between the audio thread and the rest of the plugin
+pub const AUDIO_QUEUE_CAPACITY: usize = 512;
+
+const KEY_MASK_WORDS: usize = NUM_KEYS.div_ceil(64);
+
+/// Rendered buffer of a key, shared between the render and audio threads without copying
+pub type KeyBuffer = Arc<[f32]>;
+
+/// Band-limited single-cycle frames, one per bucket, shared by all keys of a mip level
+#[derive(Debug, PartialEq)]
+pub struct Wavetable {
+    frames: Box<[f32]>,
+    frame_size: usize,
+}
+
+impl Wavetable {
+    pub fn new(frames: Vec<f32>, frame_size: usize) -> Self {
+        Self {
+            frames: frames.into_boxed_slice(),
+            frame_size: frame_size.max(1),
+        }
+    }
+
+    pub fn num_buckets(&self) -> usize {
+        self.frames.len() / self.frame_size
+    }
+
+    pub fn frame_size(&self) -> usize {
+        self.frame_size
+    }
+
+    /// Bytes of the frames
+    pub fn memory_bytes(&self) -> usize {
+        std::mem::size_of_val(&*self.frames)
+    }
+
+    /// Sample of the bucket's frame at `phase` in `0.0..1.0`, interpolated
+    pub fn read(&self, bucket: usize, phase: f32) -> f32 {
+        let size = self.frame_size;
+        let frame = &self.frames[bucket * size..(bucket + 1) * size];
+        let position = phase * size as f32;
+        let index = (position as usize).min(size - 1);
+        let frac = position - index as f32;
+        let at = |offset: usize| frame[(index + offset) % size];
+        cubic_hermite(at(size - 1), at(0), at(1), at(2), frac)
+    }
+}
+
+/// Key buffer rendered lazily in chunks of whole buckets while it is played.
+///
+/// Chunks are filled in place by the render threads; reading one is a single atomic load,
+/// and chunks that are not rendered yet play as silence.
+#[derive(Debug, PartialEq)]
+pub struct ChunkedBuffer {
+    len: usize,
+    chunk_len: usize,
+    chunks: Box<[OnceLock<KeyBuffer>]>,
+}
+
+impl ChunkedBuffer {
+    /// Empty buffer of `len` samples split into chunks of `chunk_len` samples
+    pub fn new(len: usize, chunk_len: usize) -> Self {
+        let chunk_len = chunk_len.max(1);
+        Self {
+            len,
+            chunk_len,
+            chunks: (0..len.div_ceil(chunk_len)).map(|_| OnceLock::new()).collect(),
+        }
+    }
+
+    /// Buffer with all chunks taken from already rendered `samples`
+    pub fn from_samples(samples: &[f32], chunk_len: usize) -> Self {
+        let buffer = Self::new(samples.len(), chunk_len);
+        for (chunk, samples) in samples.chunks(buffer.chunk_len).enumerate() {
+            buffer.fill(chunk, samples.into());
+        }
+        buffer
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn chunk_len(&self) -> usize {
+        self.chunk_len
+    }
+
+    pub fn num_chunks(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Chunk holding sample `idx`
+    pub fn chunk_of(&self, idx: usize) -> usize {
+        idx / self.chunk_len
+    }
+
+    pub fn is_rendered(&self, chunk: usize) -> bool {
+        self.chunks.get(chunk).is_some_and(|c| c.get().is_some())
+    }
+
+    /// Store a rendered chunk. Returns `false` if it was rendered already.
+    pub fn fill(&self, chunk: usize, samples: KeyBuffer) -> bool {
+        self.chunks.get(chunk).is_some_and(|c| c.set(samples).is_ok())
+    }
+
+    /// Sample at `idx`, silence if its chunk is not rendered yet
+    pub fn sample(&self, idx: usize) -> f32 {
+        self.chunks[idx / self.chunk_len]
+            .get()
+            .map_or(0.0, |chunk| chunk[idx % self.chunk_len])
+    }
+
+    /// Number of samples held by the rendered chunks
+    pub fn rendered_len(&self) -> usize {
+        self.chunks.iter().filter_map(OnceLock::get).map(|c| c.len()).sum()
+    }
+
+    /// Copy sharing only the rendered chunks for which `keep` returns `true`
+    pub fn retain_chunks(&self, keep: impl Fn(usize) -> bool) -> Self {
+        let buffer = Self::new(self.len, self.chunk_len);
+        for (chunk, samples) in self.chunks.iter().enumerate() {
+            if let Some(samples) = samples.get().filter(|_| keep(chunk)) {
+                buffer.fill(chunk, samples.clone());
+            }
+        }
+        buffer
+    }
+}
+
+/// What the audio thread plays for a key
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeySound {
+    // Fully rendered samples
+    Buffer(KeyBuffer),
+    // One cycle of each bucket's frame per `period` samples
+    Wavetable { table: Arc<Wavetable>, period: usize },
+    // Streamed key buffer, rendered ahead of the playhead
+    Chunked(Arc<ChunkedBuffer>),
+}
+
+impl KeySound {
+    /// Length in samples, each bucket lasting one period of the key
+    pub fn len(&self) -> usize {
+        match self {
+            KeySound::Buffer(buffer) => buffer.len(),
+            KeySound::Wavetable { table, period } => table.num_buckets() * period,
+            KeySound::Chunked(buffer) => buffer.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Sample at `idx`, which must be below `len()`
+    pub fn sample(&self, idx: usize) -> f32 {
+        match self {
+            KeySound::Buffer(buffer) => buffer[idx],
+            KeySound::Wavetable { table, period } => {
+                let phase = (idx % period) as f32 / *period as f32;
+                table.read(idx / period, phase).clamp(-1.0, 1.0)
+            }
+            KeySound::Chunked(buffer) => buffer.sample(idx),
+        }
+    }
+
+    /// All samples, e.g. for the assembled chart; never for the audio thread
+    pub fn to_samples(&self) -> Vec<f32> {
+        match self {
+            KeySound::Buffer(buffer) => buffer.to_vec(),
+            KeySound::Wavetable { .. } | KeySound::Chunked(_) => {
+                (0..self.len()).map(|idx| self.sample(idx)).collect()
+            }
+        }
+    }
+
+    /// Bytes of samples held by this sound alone; wavetables are shared by many keys and
+    /// accounted for with their mip levels
+    pub fn memory_bytes(&self) -> usize {
+        let samples = match self {
+            KeySound::Buffer(buffer) => buffer.len(),
+            KeySound::Wavetable { .. } => 0,
+            KeySound::Chunked(buffer) => buffer.rendered_len(),
+        };
+        samples * std::mem::size_of::<f32>()
+    }
+}
+
+impl From<KeyBuffer> for KeySound {
+    fn from(buffer: KeyBuffer) -> Self {
+        KeySound::Buffer(buffer)
+    }
+}
+
+impl From<Vec<f32>> for KeySound {
+    fn from(samples: Vec<f32>) -> Self {
+        KeySound::Buffer(samples.into())
+    }
+}
+
+/// Per-voice playback settings, from note expressions and polyphonic modulation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VoiceControls {
+    // Semitones, played by reading the key's buffer faster or slower
+    pub tuning: f32,
+    // Linear gain
+    pub gain: f32,
+    // -1 is hard left, 0 centre and 1 hard right
+    pub pan: f32,
+}
+
+impl Default for VoiceControls {
+    fn default() -> Self {
+        Self { tuning: 0.0, gain: 1.0, pan: 0.0 }
+    }
+}
+
+impl VoiceControls {
+    // Tuning is limited to four octaves either way
+    const MAX_TUNING: f32 = 48.0;
+
+    /// Buffer samples advanced per output sample
+    pub fn playback_rate(&self) -> f64 {
+        2f64.powf(self.tuning.clamp(-Self::MAX_TUNING, Self::MAX_TUNING) as f64 / 12.0)
+    }
+
+    /// Left and right gains; the centre keeps both channels at full level, so a mono mix
+    /// sounds as it did before panning
+    pub fn channel_gains(&self) -> [f32; 2] {
+        let pan = self.pan.clamp(-1.0, 1.0);
+        [self.gain * (1.0 - pan).min(1.0), self.gain * (1.0 + pan).min(1.0)]
+    }
+}
+
+// Number of voice parameters the host can modulate per note
+pub const VOICE_MODULATIONS: usize = 3;
+
+/// How the host refers to a note, for note expressions and polyphonic modulation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoteId {
+    pub id: i32,
+    pub channel: u8,
+}
+
 #[derive(Clone)]
 pub struct Voice {
-    pub buffer: Vec<f32>,
+    pub sound: KeySound,
     pub idx: usize,
+    // Position between `idx` and the next sample while the voice is retuned
+    pub frac: f64,
     pub fade_in_active: bool,
     pub fade_in_pos: usize,
     pub fade_out_active: bool,
     pub fade_out_pos: usize,
+    pub note_id: Option<NoteId>,
+    // Note expressions from the host, combined with the parameters into `controls`
+    pub expression: VoiceControls,
+    // Normalized polyphonic modulation offsets from the host, by modulation id
+    pub modulation: [Option<f32>; VOICE_MODULATIONS],
+    pub controls: VoiceControls,
+    // `controls.playback_rate()`, kept so the audio thread doesn't compute it per sample
+    rate: f64,
 }
 
 impl Voice {
-    pub fn new(buffer: Vec<f32>) -> Self {
+    pub fn new(sound: KeySound) -> Self {
         Self {
-            buffer,
+            sound,
             idx: 0,
+            frac: 0.0,
             fade_in_active: true,
             fade_in_pos: 0,
             fade_out_active: false,
             fade_out_pos: 0,
+            note_id: None,
+            expression: VoiceControls::default(),
+            modulation: [None; VOICE_MODULATIONS],
+            controls: VoiceControls::default(),
+            rate: 1.0,
         }
     }
 
+    pub fn set_controls(&mut self, controls: VoiceControls) {
+        if controls.tuning != self.controls.tuning {
+            self.rate = controls.playback_rate();
+        }
+        self.controls = controls;
+    }
+
     pub fn is_fading(&self) -> bool {
         self.fade_in_active || self.fade_out_active
     }
@@ -44,16 +321,325 @@ impl Voice {
     }
 }
 
+/// Messages to the audio thread from the editor and the background render thread
+pub enum AudioCommand {
+    NoteOn(usize),
+    NoteOff(usize),
+    // Replace the sound of a key; voices playing it switch over without restarting
+    SetSound(usize, KeySound),
+}
+
+/// Voice state published by the audio thread once per block for the editor
+pub struct VoiceActivity {
+    active: [AtomicU64; KEY_MASK_WORDS],
+    released: [AtomicU64; KEY_MASK_WORDS],
+    positions: Box<[AtomicUsize]>,
+    // Sequence number of the latest note on per key, 0 if never played
+    started: Box<[AtomicU64]>,
+}
+
+impl VoiceActivity {
+    pub fn new() -> Self {
+        Self {
+            active: std::array::from_fn(|_| AtomicU64::new(0)),
+            released: std::array::from_fn(|_| AtomicU64::new(0)),
+            positions: (0..NUM_KEYS).map(|_| AtomicUsize::new(0)).collect(),
+            started: (0..NUM_KEYS).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    fn is_set(mask: &[AtomicU64; KEY_MASK_WORDS], key: usize) -> bool {
+        key < NUM_KEYS && mask[key / 64].load(Ordering::Acquire) & (1 << (key % 64)) != 0
+    }
+
+    /// Whether a voice is sounding for the key, held or fading out
+    pub fn is_active(&self, key: usize) -> bool {
+        Self::is_set(&self.active, key)
+    }
+
+    /// Whether the key's voice has been released and is fading out
+    pub fn is_released(&self, key: usize) -> bool {
+        Self::is_set(&self.released, key)
+    }
+
+    /// Whether the key is sounding and not released yet
+    pub fn is_held(&self, key: usize) -> bool {
+        self.is_active(key) && !self.is_released(key)
+    }
+
+    /// Up to `count` most recently played keys, newest first
+    pub fn recently_played(&self, count: usize) -> Vec<usize> {
+        let mut keys: Vec<(u64, usize)> = (0..NUM_KEYS)
+            .map(|key| (self.started[key].load(Ordering::Relaxed), key))
+            .filter(|&(started, _)| started > 0)
+            .collect();
+        keys.sort_unstable_by(|a, b| b.cmp(a));
+        keys.into_iter().take(count).map(|(_, key)| key).collect()
+    }
+
+    /// Sample index of the key's voice within its buffer
+    pub fn position(&self, key: usize) -> usize {
+        self.positions[key].load(Ordering::Relaxed)
+    }
+}
+
+impl Default for VoiceActivity {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Voices owned by the audio thread.
+///
+/// Everything is preallocated: sounds arrive behind an `Arc` through the command queue and
+/// replaced sounds are sent back through the garbage queue, so the last reference is never
+/// dropped on the audio thread. Nothing here locks, allocates or renders.
+pub struct VoiceBank {
+    voices: Vec<Option<Voice>>,
+    sounds: Vec<Option<KeySound>>,
+    // Played by notes whose sound has not been rendered yet
+    empty: KeySound,
+    commands: Consumer<AudioCommand>,
+    garbage: Producer<KeySound>,
+    activity: Arc<VoiceActivity>,
+    fade_duration: usize,
+    notes_started: u64,
+    // Keys and note ids of host notes that ended since the last `drain_terminated`
+    terminated: Vec<(usize, NoteId)>,
+}
+
+impl VoiceBank {
+    pub fn new(
+        commands: Consumer<AudioCommand>,
+        garbage: Producer<KeySound>,
+        activity: Arc<VoiceActivity>,
+        fade_duration: usize,
+    ) -> Self {
+        Self {
+            voices: vec![None; NUM_KEYS],
+            sounds: vec![None; NUM_KEYS],
+            empty: KeySound::Buffer(Arc::from(Vec::new())),
+            commands,
+            garbage,
+            activity,
+            fade_duration,
+            notes_started: 0,
+            // A voice ends at most once per note on, and a block rarely has more than a few
+            terminated: Vec::with_capacity(AUDIO_QUEUE_CAPACITY),
+        }
+    }
+
+    /// Apply queued commands. Sound swaps wait while the garbage queue is full.
+    pub fn apply_commands(&mut self) {
+        while let Ok(command) = self.commands.peek() {
+            if matches!(command, AudioCommand::SetSound(..)) && self.garbage.slots() == 0 {
+                break;
+            }
+            match self.commands.pop() {
+                Ok(AudioCommand::NoteOn(key)) => self.note_on(key),
+                Ok(AudioCommand::NoteOff(key)) => self.note_off(key),
+                Ok(AudioCommand::SetSound(key, sound)) => self.set_sound(key, sound),
+                Err(_) => break,
+            }
+        }
+    }
+
+    pub fn note_on(&mut self, key: usize) {
+        self.note_on_voice(key, None);
+    }
+
+    /// Start a note the host refers to by `note_id` in later expressions and modulation. A
+    /// voice still sounding on the key ends.
+    pub fn note_on_voice(&mut self, key: usize, note_id: Option<NoteId>) {
+        if key >= NUM_KEYS {
+            return;
+        }
+        let sound = self.sounds[key].as_ref().unwrap_or(&self.empty).clone();
+        let previous = self.voices[key].replace(Voice { note_id, ..Voice::new(sound) });
+        if let Some(previous_id) = previous.and_then(|v| v.note_id) {
+            Self::terminate(&mut self.terminated, key, previous_id);
+        }
+        self.notes_started += 1;
+        self.activity.started[key].store(self.notes_started, Ordering::Relaxed);
+    }
+
+    fn terminate(terminated: &mut Vec<(usize, NoteId)>, key: usize, note_id: NoteId) {
+        // Never grows on the audio thread; a voice that doesn't fit goes unreported
+        if terminated.len() < terminated.capacity() {
+            terminated.push((key, note_id));
+        }
+    }
+
+    /// Keys and note ids of host notes that ended, so the host stops modulating them
+    pub fn drain_terminated(&mut self) -> impl Iterator<Item = (usize, NoteId)> + '_ {
+        self.terminated.drain(..)
+    }
+
+    /// Key of the sounding voice with the host's note id
+    pub fn key_of_voice(&self, id: i32) -> Option<usize> {
+        self.voices
+            .iter()
+            .position(|v| v.as_ref().and_then(|v| v.note_id).is_some_and(|note_id| note_id.id == id))
+    }
+
+    /// Sounding voice of the key
+    pub fn voice_mut(&mut self, key: usize) -> Option<&mut Voice> {
+        self.voices.get_mut(key).and_then(|v| v.as_mut())
+    }
+
+    pub fn note_off(&mut self, key: usize) {
+        if let Some(v) = self.voices.get_mut(key).and_then(|v| v.as_mut()) {
+            v.start_fade_out();
+        }
+    }
+
+    fn set_sound(&mut self, key: usize, sound: KeySound) {
+        if key >= NUM_KEYS {
+            // Out-of-range key; the sound is still freed off the audio thread
+            let _ = self.garbage.push(sound);
+            return;
+        }
+        // Keep the current idx and fade states so edits are audible immediately
+        if let Some(v) = self.voices[key].as_mut() {
+            v.sound = sound.clone();
+        }
+        if let Some(old) = self.sounds[key].replace(sound) {
+            let _ = self.garbage.push(old);
+        }
+    }
+
+    /// Mix the next output sample of all active voices with headroom, in mono
+    pub fn next_sample(&mut self) -> f32 {
+        let [left, right] = self.next_frame();
+        0.5 * (left + right)
+    }
+
+    /// Mix the next stereo frame of all active voices with headroom
+    pub fn next_frame(&mut self) -> [f32; 2] {
+        let fade_duration = self.fade_duration;
+
+        // Count active voices this frame (cheap; keeps headroom stable)
+        let active_count = self.voices.iter().filter(|o| o.is_some()).count();
+
+        // Per-voice scaling with safe loudness compensation
+        // Scale each voice down, then boost final mix carefully to avoid clipping
+        let (voice_gain, master_gain) = if active_count > 0 {
+            let n = active_count as f32;
+            // Each voice gets 1/N scaling to prevent clipping
+            let voice_scaling = 0.8 / n;  // More conservative base scaling
+            // Safer loudness compensation that won't exceed ±1.0
+            let loudness_compensation = match active_count {
+                1 => 1.0,   // Single voice: 0.8 * 1.0 = 0.8
+                2 => 1.5,   // 2 voices: 0.4 * 1.5 = 0.6
+                3 => 2.0,   // 3 voices: 0.267 * 2.0 = 0.53
+                4 => 2.4,   // 4 voices: 0.2 * 2.4 = 0.48
+                5 => 2.8,   // 5 voices: 0.16 * 2.8 = 0.45
+                _ => 3.0,   // 6+ voices: 0.133 * 3.0 = 0.4 max
+            };
+            (voice_scaling, loudness_compensation)
+        } else {
+            (1.0, 1.0)
+        };
+
+        let mut mixed = [0.0f32; 2];
+
+        for (key, opt) in self.voices.iter_mut().enumerate() {
+            if let Some(v) = opt.as_mut() {
+                // A key that hasn't finished rendering is silent and waits to fade in, but a
+                // released note still fades out and ends on time
+                let len = v.sound.len();
+                let mut s = 0.0;
+                if len > 0 {
+                    s = v.sound.sample(v.idx % len);
+                    if v.frac > 0.0 {
+                        let next = v.sound.sample((v.idx + 1) % len);
+                        s += (next - s) * v.frac as f32;
+                    }
+                }
+
+                // Apply per-voice scaling FIRST to prevent intermediate clipping
+                s *= voice_gain;
+
+                // Fade in
+                if len > 0 {
+                    if v.fade_in_active && v.fade_in_pos < fade_duration {
+                        let g = v.fade_in_pos as f32 / fade_duration as f32;
+                        s *= g;
+                        v.fade_in_pos += 1;
+                    } else {
+                        v.fade_in_active = false;
+                    }
+                }
+
+                // Fade out
+                if v.fade_out_active {
+                    if v.fade_out_pos < fade_duration {
+                        let g = 1.0 - (v.fade_out_pos as f32 / fade_duration as f32);
+                        s *= g;
+                        v.fade_out_pos += 1;
+                    } else {
+                        // Voice finished after fade; its sound is still referenced by `sounds`
+                        if let Some(note_id) = v.note_id {
+                            Self::terminate(&mut self.terminated, key, note_id);
+                        }
+                        *opt = None;
+                        continue;
+                    }
+                }
+
+                let [left, right] = v.controls.channel_gains();
+                mixed[0] += s * left;
+                mixed[1] += s * right;
+                if len > 0 {
+                    v.frac += v.rate;
+                    let whole = v.frac.floor();
+                    v.idx = v.idx.wrapping_add(whole as usize);
+                    v.frac -= whole;
+                }
+            }
+        }
+
+        // Apply loudness compensation, then a final clamp that should rarely trigger now
+        mixed.map(|channel| (channel * master_gain).clamp(-1.0, 1.0))
+    }
+
+    /// Publish which keys are sounding and where, for the keyboard and playback indicator
+    pub fn publish_activity(&self) {
+        let mut active = [0u64; KEY_MASK_WORDS];
+        let mut released = [0u64; KEY_MASK_WORDS];
+        for (key, voice) in self.voices.iter().enumerate() {
+            if let Some(v) = voice {
+                active[key / 64] |= 1 << (key % 64);
+                if v.fade_out_active {
+                    released[key / 64] |= 1 << (key % 64);
+                }
+                self.activity.positions[key].store(v.idx, Ordering::Relaxed);
+            }
+        }
+        for word in 0..KEY_MASK_WORDS {
+            self.activity.released[word].store(released[word], Ordering::Release);
+            self.activity.active[word].store(active[word], Ordering::Release);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn create_test_bank() -> (VoiceBank, Producer<AudioCommand>, Consumer<KeySound>) {
+        let (command_tx, command_rx) = rtrb::RingBuffer::new(16);
+        let (garbage_tx, garbage_rx) = rtrb::RingBuffer::new(16);
+        let bank = VoiceBank::new(command_rx, garbage_tx, Arc::new(VoiceActivity::new()), 4);
+        (bank, command_tx, garbage_rx)
+    }
+
     #[test]
     fn test_voice_new() {
         let buffer = vec![0.1, 0.2, 0.3, 0.4];
-        let voice = Voice::new(buffer.clone());
-        
-        assert_eq!(voice.buffer, buffer);
+        let voice = Voice::new(buffer.clone().into());
+
+        assert_eq!(voice.sound.to_samples(), buffer);
         assert_eq!(voice.idx, 0);
         assert_eq!(voice.fade_in_active, true);
         assert_eq!(voice.fade_in_pos, 0);
@@ -63,15 +649,15 @@ mod tests {
 
     #[test]
     fn test_voice_is_fading() {
-        let mut voice = Voice::new(vec![0.0; 10]);
-        
+        let mut voice = Voice::new(vec![0.0; 10].into());
+
         // Initially fading in
         assert!(voice.is_fading());
-        
+
         // Stop fade in
         voice.fade_in_active = false;
         assert!(!voice.is_fading());
-        
+
         // Start fade out
         voice.start_fade_out();
         assert!(voice.is_fading());
@@ -81,24 +667,239 @@ mod tests {
 
     #[test]
     fn test_voice_start_fade_out() {
-        let mut voice = Voice::new(vec![0.0; 5]);
-        
+        let mut voice = Voice::new(vec![0.0; 5].into());
+
         assert!(!voice.fade_out_active);
-        
+
         voice.start_fade_out();
-        
+
         assert!(voice.fade_out_active);
         assert_eq!(voice.fade_out_pos, 0);
     }
 
     #[test]
     fn test_voice_clone() {
-        let original = Voice::new(vec![1.0, 2.0, 3.0]);
+        let original = Voice::new(vec![1.0, 2.0, 3.0].into());
         let cloned = original.clone();
-        
-        assert_eq!(original.buffer, cloned.buffer);
+
+        assert_eq!(original.sound, cloned.sound);
+        let (KeySound::Buffer(a), KeySound::Buffer(b)) = (&original.sound, &cloned.sound) else {
+            panic!("Expected buffers");
+        };
+        assert!(Arc::ptr_eq(a, b));
         assert_eq!(original.idx, cloned.idx);
         assert_eq!(original.fade_in_active, cloned.fade_in_active);
         assert_eq!(original.fade_out_active, cloned.fade_out_active);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_note_waits_for_buffer() {
+        let (mut bank, mut commands, _garbage) = create_test_bank();
+
+        // A note without a rendered buffer is silent and does not advance
+        bank.note_on(10);
+        assert_eq!(bank.next_sample(), 0.0);
+        bank.publish_activity();
+        assert!(bank.activity.is_active(10));
+        assert_eq!(bank.activity.position(10), 0);
+
+        assert!(commands.push(AudioCommand::SetSound(10, vec![1.0; 8].into())).is_ok());
+        bank.apply_commands();
+        // Fade-in starts at zero gain, then the voice becomes audible
+        assert_eq!(bank.next_sample(), 0.0);
+        assert!(bank.next_sample() > 0.0);
+    }
+
+    #[test]
+    fn test_buffer_swap_keeps_position_and_retires_old_buffer() {
+        let (mut bank, mut commands, mut garbage) = create_test_bank();
+        let first: KeyBuffer = vec![0.5; 8].into();
+
+        assert!(commands.push(AudioCommand::SetSound(3, first.clone().into())).is_ok());
+        assert!(commands.push(AudioCommand::NoteOn(3)).is_ok());
+        bank.apply_commands();
+        for _ in 0..5 {
+            bank.next_sample();
+        }
+
+        assert!(commands.push(AudioCommand::SetSound(3, vec![0.25; 8].into())).is_ok());
+        bank.apply_commands();
+        let voice = bank.voices[3].as_ref().unwrap();
+        assert_eq!(voice.idx, 5);
+        assert_eq!(voice.sound.sample(0), 0.25);
+
+        // The replaced buffer goes back through the garbage queue instead of being freed here
+        let Ok(KeySound::Buffer(retired)) = garbage.pop() else {
+            panic!("Expected the retired buffer");
+        };
+        assert!(Arc::ptr_eq(&retired, &first));
+    }
+
+    #[test]
+    fn test_buffer_swap_waits_for_garbage_space() {
+        let (command_tx, command_rx) = rtrb::RingBuffer::new(4);
+        let (garbage_tx, mut garbage_rx) = rtrb::RingBuffer::new(1);
+        let mut commands = command_tx;
+        let mut bank = VoiceBank::new(command_rx, garbage_tx, Arc::new(VoiceActivity::new()), 4);
+
+        for value in [0.1, 0.2, 0.3] {
+            assert!(commands.push(AudioCommand::SetSound(0, vec![value; 4].into())).is_ok());
+        }
+        bank.apply_commands();
+        // The second swap filled the garbage queue, so the third one is still queued
+        assert_eq!(bank.sounds[0].as_ref().unwrap().sample(0), 0.2);
+
+        garbage_rx.pop().unwrap();
+        bank.apply_commands();
+        assert_eq!(bank.sounds[0].as_ref().unwrap().sample(0), 0.3);
+    }
+
+    #[test]
+    fn test_wavetable_sound_plays_one_cycle_per_bucket() {
+        // Two buckets, a constant frame and a sawtooth-like ramp
+        let frames = vec![0.5, 0.5, 0.5, 0.5, 0.0, 0.25, 0.5, 0.75];
+        let table = Arc::new(Wavetable::new(frames, 4));
+        assert_eq!(table.num_buckets(), 2);
+
+        let sound = KeySound::Wavetable { table, period: 8 };
+        assert_eq!(sound.len(), 16);
+        assert!((0..8).all(|idx| (sound.sample(idx) - 0.5).abs() < 1e-6));
+        // Frame points are hit exactly at every other sample, interpolated in between
+        assert_eq!(sound.sample(8), 0.0);
+        assert_eq!(sound.sample(10), 0.25);
+        assert!(sound.sample(11) > 0.25 && sound.sample(11) < 0.5);
+        assert_eq!(sound.to_samples().len(), 16);
+    }
+
+    #[test]
+    fn test_chunked_buffer_plays_silence_until_rendered() {
+        let buffer = Arc::new(ChunkedBuffer::new(10, 4));
+        assert_eq!(buffer.num_chunks(), 3);
+        let sound = KeySound::Chunked(buffer.clone());
+        assert_eq!(sound.len(), 10);
+        assert_eq!(sound.sample(5), 0.0);
+        assert_eq!(sound.memory_bytes(), 0);
+
+        // Filled in place, so a voice already holding the sound picks it up
+        assert!(buffer.fill(1, Arc::from(vec![0.1, 0.2, 0.3, 0.4])));
+        assert!(!buffer.fill(1, Arc::from(vec![0.0; 4])));
+        assert!(buffer.is_rendered(1) && !buffer.is_rendered(2));
+        assert_eq!(sound.sample(5), 0.2);
+        assert_eq!(buffer.chunk_of(9), 2);
+        assert_eq!(sound.memory_bytes(), 16);
+
+        let trimmed = buffer.retain_chunks(|chunk| chunk == 0);
+        assert_eq!(trimmed.len(), 10);
+        assert_eq!(trimmed.rendered_len(), 0);
+
+        let full = ChunkedBuffer::from_samples(&[1.0, 2.0, 3.0, 4.0, 5.0], 2);
+        assert_eq!(full.rendered_len(), 5);
+        assert_eq!(full.sample(4), 5.0);
+    }
+
+    #[test]
+    fn test_recently_played() {
+        let (mut bank, _commands, _garbage) = create_test_bank();
+        assert!(bank.activity.recently_played(4).is_empty());
+
+        for key in [10, 20, 30, 20] {
+            bank.note_on(key);
+        }
+        assert_eq!(bank.activity.recently_played(4), vec![20, 30, 10]);
+        assert_eq!(bank.activity.recently_played(1), vec![20]);
+    }
+
+    #[test]
+    fn test_voice_controls() {
+        assert_eq!(VoiceControls::default().channel_gains(), [1.0, 1.0]);
+        assert_eq!(VoiceControls { gain: 0.5, pan: -1.0, ..Default::default() }.channel_gains(), [0.5, 0.0]);
+        assert_eq!(VoiceControls { pan: 0.5, ..Default::default() }.channel_gains(), [0.5, 1.0]);
+        assert_eq!(VoiceControls { tuning: 12.0, ..Default::default() }.playback_rate(), 2.0);
+        assert_eq!(VoiceControls { tuning: -100.0, ..Default::default() }.playback_rate(), 1.0 / 16.0);
+    }
+
+    #[test]
+    fn test_retuned_and_panned_voice() {
+        let (mut bank, mut commands, _garbage) = create_test_bank();
+        let ramp: Vec<f32> = (0..16).map(|i| i as f32 / 16.0).collect();
+        assert!(commands.push(AudioCommand::SetSound(5, ramp.into())).is_ok());
+        bank.apply_commands();
+        bank.note_on_voice(5, Some(NoteId { id: 42, channel: 0 }));
+        assert_eq!(bank.key_of_voice(42), Some(5));
+        assert_eq!(bank.key_of_voice(7), None);
+
+        // A fifth below reads the buffer at 2/3 speed, interpolating between samples
+        let voice = bank.voice_mut(5).unwrap();
+        voice.set_controls(VoiceControls { tuning: -7.0, pan: 1.0, ..Default::default() });
+        let frames: Vec<[f32; 2]> = (0..8).map(|_| bank.next_frame()).collect();
+        assert!(frames.iter().all(|frame| frame[0] == 0.0));
+        let voice = bank.voices[5].as_ref().unwrap();
+        let position = voice.idx as f64 + voice.frac;
+        assert!((position - 8.0 * 2f64.powf(-7.0 / 12.0)).abs() < 1e-9);
+        // Past the fade-in the ramp comes through at the interpolated position, scaled like any single voice
+        let expected = 0.8 * (position as f32 / 16.0);
+        assert!((bank.next_frame()[1] - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_terminated_voices_are_reported() {
+        let (mut bank, mut commands, _garbage) = create_test_bank();
+        assert!(commands.push(AudioCommand::SetSound(2, vec![1.0; 8].into())).is_ok());
+        bank.apply_commands();
+        bank.note_on(1);
+        let (first, second) = (NoteId { id: 1, channel: 0 }, NoteId { id: 2, channel: 3 });
+        bank.note_on_voice(2, Some(first));
+        bank.voice_mut(2).unwrap().modulation[0] = Some(0.5);
+        // Retriggering the key ends the first voice, and the new one starts unmodulated
+        bank.note_on_voice(2, Some(second));
+        assert_eq!(bank.voice_mut(2).unwrap().modulation, [None; VOICE_MODULATIONS]);
+        assert_eq!(bank.drain_terminated().collect::<Vec<_>>(), [(2, first)]);
+
+        bank.note_off(1);
+        bank.note_off(2);
+        for _ in 0..8 {
+            bank.next_frame();
+        }
+        // Only voices started with an id are reported
+        assert_eq!(bank.drain_terminated().collect::<Vec<_>>(), [(2, second)]);
+        assert_eq!(bank.drain_terminated().count(), 0);
+    }
+
+    #[test]
+    fn test_unrendered_voice_ends_after_fade_out() {
+        let (mut bank, _commands, _garbage) = create_test_bank();
+        let note_id = NoteId { id: 5, channel: 0 };
+        bank.note_on_voice(3, Some(note_id));
+        bank.note_off(3);
+        // Fade duration is 4 samples in the test bank
+        for _ in 0..5 {
+            assert_eq!(bank.next_frame(), [0.0, 0.0]);
+        }
+        assert!(bank.voice_mut(3).is_none());
+        assert_eq!(bank.drain_terminated().collect::<Vec<_>>(), [(3, note_id)]);
+    }
+
+    #[test]
+    fn test_voice_ends_after_fade_out() {
+        let (mut bank, mut commands, _garbage) = create_test_bank();
+        assert!(commands.push(AudioCommand::SetSound(7, vec![1.0; 8].into())).is_ok());
+        assert!(commands.push(AudioCommand::NoteOn(7)).is_ok());
+        bank.apply_commands();
+        bank.next_sample();
+
+        bank.publish_activity();
+        assert!(bank.activity.is_held(7));
+        bank.note_off(7);
+        bank.publish_activity();
+        assert!(bank.activity.is_released(7));
+        assert!(!bank.activity.is_held(7));
+
+        // Fade duration is 4 samples in the test bank
+        for _ in 0..5 {
+            bank.next_sample();
+        }
+        bank.publish_activity();
+        assert!(!bank.activity.is_active(7));
+        assert_eq!(bank.next_sample(), 0.0);
+    }
+}